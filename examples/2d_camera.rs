@@ -1,3 +1,4 @@
+use pira_wgpu::Error;
 use pira_wgpu::factories;
 use pira_wgpu::factories::texture::{SamplerOptions, Texture2dOptions};
 use pira_wgpu::framework;
@@ -164,8 +165,8 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
-        let image = image::open("./assets/rusty.png").unwrap().to_rgba8();
+    fn init(state: &State) -> Result<Self, Error> {
+        let image = image::open("./assets/rusty.png")?.to_rgba8();
         let rect_size: f32 = 500.0;
 
         let aspect_ratio = image.height() as f32 / image.width() as f32;
@@ -217,7 +218,7 @@ impl Application for MyExample {
             None,
         );
 
-        MyExample {
+        Ok(MyExample {
             position: glam::Vec3::ZERO, //glam::vec3(1000.0, 800.0, 0.0),
             camera_controller: CameraController2D::new(),
 
@@ -238,7 +239,7 @@ impl Application for MyExample {
                 }),
 
             pipeline,
-        }
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
@@ -1,3 +1,4 @@
+use pira_wgpu::Error;
 use pira_wgpu::state::State;
 use pira_wgpu::{
     framework::{self, Application},
@@ -16,7 +17,7 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let mut axis_geo = axis::Axis::new(10.0); //sphere::Sphere::new(10.0, 32, 16);
         axis_geo.texture_coords();
         axis_geo.vertex_colors();
@@ -41,11 +42,11 @@ impl Application for MyExample {
         camera.position = glam::vec3(100.0, 70.0, -100.0);
         camera.look_at(glam::Vec3::ZERO);
 
-        Self {
+        Ok(Self {
             mesh,
             pipeline_batch,
             orbit_control: OrbitControls::new(size.aspect_ratio()),
-        }
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 
 use glam::Mat4;
+use pira_wgpu::Error;
 use pira_wgpu::factories::texture::{SamplerOptions, Texture2dOptions, TextureBundle};
 use pira_wgpu::factories::BindGroupFactory;
 use wgpu::util::DeviceExt;
@@ -26,7 +27,7 @@ struct ComputeExample {
 }
 
 impl Application for ComputeExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let compute_shader = state.device.create_shader_module(ShaderModuleDescriptor {
             label: Some("Compute shader"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/compute.wgsl"))),
@@ -138,7 +139,7 @@ impl Application for ComputeExample {
         //pipeline.set_texture_bind_group(draw_bind_group.clone(), _draw_bind_group_layout);
 
 
-        ComputeExample {
+        Ok(ComputeExample {
             clear_color: [0.5, 0.1, 0.1, 1.0],
             buffer: state
                 .device
@@ -158,7 +159,7 @@ impl Application for ComputeExample {
             pipeline,
             // bind_group: draw_bind_group,
             _texture_bundle: output_texture,
-        }
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
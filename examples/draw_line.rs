@@ -4,6 +4,7 @@ use glam::Vec2;
 use wgpu;
 use wgpu::util::DeviceExt;
 
+use pira_wgpu::Error;
 use pira_wgpu::factories::{BindGroupFactory, RenderPipelineFactory};
 use pira_wgpu::framework::{self, Application};
 use pira_wgpu::state::State;
@@ -117,7 +118,7 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let rect_size = 1.0;
         let rect_height = 5.0;
         let vertices = vec![
@@ -261,7 +262,7 @@ impl Application for MyExample {
         let pipeline =
             pipeline_factory.create_render_pipeline(&state, &shader_module, &[Some(&bind_group_layout)]);
 
-        MyExample {
+        Ok(MyExample {
             clear_color: [0.0, 0.0, 0.0, 0.0],
             buffer: state
                 .device
@@ -298,7 +299,7 @@ impl Application for MyExample {
             instance_points: Vec::new(),
             _draw_next_buffer: false,
             is_mouse_down: false,
-        }
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
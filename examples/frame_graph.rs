@@ -0,0 +1,225 @@
+use std::borrow::Cow;
+
+use pira_wgpu::Error;
+use pira_wgpu::{
+    factories::RenderPipelineFactory,
+    frame_graph::{ColorAttachment, FrameGraph, PassOptions, TransientTextureOptions},
+    framework::{self, Application},
+    state::{Size, State},
+};
+use winit::dpi::PhysicalSize;
+
+const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+const SCENE_SHADER_SRC: &str = "
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var positions = array<vec2<f32>, 3>(vec2(0.0, 0.7), vec2(-0.7, -0.7), vec2(0.7, -0.7));
+    var colors = array<vec3<f32>, 3>(vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0));
+
+    var out: VertexOutput;
+    out.clip_position = vec4(positions[index], 0.0, 1.0);
+    out.color = colors[index];
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(in.color, 1.0);
+}
+";
+
+const UPSCALE_SHADER_SRC: &str = "
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(scene_texture, scene_sampler, in.uv);
+}
+";
+
+struct MyExample {
+    scene_pipeline: wgpu::RenderPipeline,
+    upscale_pipeline: wgpu::RenderPipeline,
+    texture_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+
+    pixel_size: u32,
+}
+
+impl Application for MyExample {
+    fn init(state: &State) -> Result<Self, Error> {
+        let scene_shader = state
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Scene Shader"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SCENE_SHADER_SRC)),
+            });
+
+        let mut pipeline_factory = RenderPipelineFactory::new();
+        pipeline_factory.set_label("Scene Pipeline");
+        pipeline_factory.set_color_target_format(Some(SCENE_FORMAT));
+        pipeline_factory.set_sample_count(Some(1));
+        let scene_pipeline = pipeline_factory.create_render_pipeline(state, &scene_shader, &[]);
+
+        let texture_layout =
+            state
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Scene Texture Layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                });
+
+        let upscale_shader = state
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("Upscale Shader"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(UPSCALE_SHADER_SRC)),
+            });
+
+        let mut pipeline_factory = RenderPipelineFactory::new();
+        pipeline_factory.set_label("Upscale Pipeline");
+        pipeline_factory.set_color_target_format(Some(state.config.format));
+        let upscale_pipeline = pipeline_factory.create_render_pipeline(
+            state,
+            &upscale_shader,
+            &[Some(&texture_layout)],
+        );
+
+        let sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            scene_pipeline,
+            upscale_pipeline,
+            texture_layout,
+            sampler,
+
+            pixel_size: 8,
+        })
+    }
+
+    fn on_gui(&mut self, egui_ctx: &mut framework::EguiLayer) {
+        egui::Window::new("Settings").show(&egui_ctx.ctx, |ui| {
+            ui.label("Pixel size");
+            ui.add(egui::Slider::new(&mut self.pixel_size, 1..=32));
+        });
+    }
+
+    fn update(&mut self, _state: &mut State, _frame_count: u64, _delta_time: f64) {}
+
+    fn render<'rpass>(&'rpass self, _state: &State, _render_pass: &mut wgpu::RenderPass<'rpass>) {}
+
+    fn build_frame_graph<'a>(&'a self, state: &'a State, graph: &mut FrameGraph<'a>) {
+        let scene = graph.create_texture(TransientTextureOptions {
+            label: Some("Low Resolution Scene"),
+            size: Size::new(
+                (state.window_size.width / self.pixel_size).max(1),
+                (state.window_size.height / self.pixel_size).max(1),
+            ),
+            format: SCENE_FORMAT,
+            sample_count: 1,
+        });
+
+        graph.add_pass(
+            PassOptions {
+                label: Some("Scene Pass"),
+                color_attachments: vec![ColorAttachment::new(
+                    scene,
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                )],
+                ..Default::default()
+            },
+            move |_, mut render_pass| {
+                render_pass.set_pipeline(&self.scene_pipeline);
+                render_pass.draw(0..3, 0..1);
+            },
+        );
+
+        let targets = graph.targets();
+        graph.add_pass(
+            PassOptions {
+                label: Some("Upscale Pass"),
+                color_attachments: vec![
+                    targets.color_attachment(wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+                ],
+                reads: vec![scene],
+                ..Default::default()
+            },
+            move |resources, mut render_pass| {
+                let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Scene Texture Bind Group"),
+                    layout: &self.texture_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(resources.view(scene)),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                });
+
+                render_pass.set_pipeline(&self.upscale_pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            },
+        );
+    }
+}
+
+fn main() {
+    framework::run::<MyExample>(
+        "frame graph",
+        PhysicalSize {
+            width: 1280,
+            height: 720,
+        },
+        4,
+    );
+}
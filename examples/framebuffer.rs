@@ -2,10 +2,13 @@
 #![allow(unused_variables)]
 
 use image::EncodableLayout;
+use pira_wgpu::Error;
 use pira_wgpu::factories::texture::{SamplerOptions, Texture2dOptions};
+use pira_wgpu::frame_graph::{FrameGraph, PassOptions};
 use pira_wgpu::framework::{self, Application};
 use pira_wgpu::helpers::geometry::attribute_names;
-use pira_wgpu::state::State;
+use pira_wgpu::pipelines::post::{self, PostProcessing};
+use pira_wgpu::state::{State, StateOptions};
 use pira_wgpu::{factories, pipelines};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
@@ -31,7 +34,6 @@ fn vs_main(@location(0) vertex_position: vec3<f32>) -> VertexOutput {
 
 struct Uniforms {
     @location(2) rotation_matrix: mat4x4<f32>,
-    @location(3) exposure: f32,
 };
 
 
@@ -64,26 +66,6 @@ fn sample_spherical_map(v : vec3<f32>) -> vec2<f32>
     return uv;
 }
 
-// Maps HDR values to linear values
-// Based on http://www.oscars.org/science-technology/sci-tech-projects/aces
-fn aces_tone_map(hdr: vec3<f32>) -> vec3<f32> {
-    let m1 = mat3x3(
-        0.59719, 0.07600, 0.02840,
-        0.35458, 0.90834, 0.13383,
-        0.04823, 0.01566, 0.83777,
-    );
-    let m2 = mat3x3(
-        1.60475, -0.10208, -0.00327,
-        -0.53108,  1.10813, -0.07276,
-        -0.07367, -0.00605,  1.07602,
-    );
-    let v = m1 * hdr;
-    let a = v * (v + 0.0245786) - 0.000090537;
-    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
-    return clamp(m2 * (a / b), vec3(0.0), vec3(1.0));
-}
-
-
 @fragment
 fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
@@ -93,10 +75,8 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
     var texture_color = textureLoad(hdr_texture, vec2<i32>(cube_uv * vec2<f32>(1024.0, 512.0)), 0).rgb;
 
-    var gamma = 2.2;
-    var mapped = texture_color / (texture_color + vec3(1.0));
-    mapped = vec3(1.0) - exp(-texture_color * m_uniform.exposure); //pow(mapped, vec3(1.0 / gamma));
-    return vec4(mapped, 1.0);  //vec4(aces_tone_map(texture_color + m_uniform.exposure) , 1.0);
+    // Exposure and tonemapping happen in the post-processing passes.
+    return vec4(texture_color, 1.0);
  }
 ";
 
@@ -104,8 +84,6 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Uniform {
     pub rotation_matrix: [f32; 16],
-    pub exposure: f32,
-    _pad: [f32; 3],
 }
 
 struct Sky {
@@ -125,19 +103,19 @@ struct MyExample {
     bind_group: wgpu::BindGroup,
 
     rotation: glam::Vec3,
-    exposure: f32,
     uniform_buffer: wgpu::Buffer,
+    post: PostProcessing,
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let State {
             instance,
             adapter,
             device,
             queue,
             config,
-            window_surface,
+            render_target,
             depth_texture,
             default_white_texture_bundle,
             window_size,
@@ -172,8 +150,7 @@ impl Application for MyExample {
             "assets/buikslotermeerplein_1k.exr"
             // "assets/rusty.png",
             // "/Users/henrique/Documents/dev/rust/pira-wgpu/assets/cubemap-equi.png",
-        )
-        .unwrap()
+        )?
         .to_rgba32f();
 
         let px = image.get_pixel(200, 200);
@@ -196,8 +173,6 @@ impl Application for MyExample {
         let rotation_matrix_buffer = glam::Mat4::IDENTITY;
         let uniform: Uniform = Uniform {
             rotation_matrix: *rotation_matrix_buffer.as_ref(),
-            exposure: 1.0,
-            _pad: [0.0, 0.0, 0.0],
         };
 
         let uniform_buffer = pipelines::create_uniform_buffer::<Uniform>(1, device);
@@ -267,7 +242,7 @@ impl Application for MyExample {
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
                 entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::TextureFormat::Bgra8UnormSrgb.into())],
+                targets: &[Some(state.scene_format.into())],
                 // compilation_options : ,
                 compilation_options : wgpu::PipelineCompilationOptions::default(),
             }),
@@ -282,22 +257,22 @@ impl Application for MyExample {
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: MultisampleState {
-                count: 4,
+                count: state.get_sample_count(),
                 ..Default::default()
             },
             multiview_mask : None,
             cache : None,
         });
 
-        Self {
+        Ok(Self {
             pipeline,
             vertex_buffer,
             index_buffer,
             bind_group,
             rotation: glam::Vec3::ZERO,
-            exposure: 1.0,
             uniform_buffer,
-        }
+            post: PostProcessing::new(state, Default::default()),
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
@@ -318,8 +293,6 @@ impl Application for MyExample {
 
         let uniform: Uniform = Uniform {
             rotation_matrix: *rotation_matrix_buffer.as_ref(),
-            exposure: self.exposure,
-            _pad: [0.0, 0.0, 0.0],
         };
 
         pipelines::write_uniform_buffer(&[uniform], &self.uniform_buffer, queue, device);
@@ -327,15 +300,31 @@ impl Application for MyExample {
     }
 
     fn on_gui(&mut self, egui_ctx: &mut framework::EguiLayer) {
-        // egui::SidePanel::new(egui::panel::Side::Left, "Debug").show(&egui_ctx.ctx, |ui| {
-        //     ui.drag_angle(&mut self.rotation.x);
-        //     ui.drag_angle(&mut self.rotation.y);
-        //     ui.drag_angle(&mut self.rotation.z);
+        egui::Window::new("Post Processing").show(&egui_ctx.ctx, |ui| {
+            ui.drag_angle(&mut self.rotation.x);
+            ui.drag_angle(&mut self.rotation.y);
+            ui.drag_angle(&mut self.rotation.z);
 
-        //     ui.spacing();
+            ui.separator();
 
-        //     ui.add(egui::DragValue::new(&mut self.exposure).speed(0.01));
-        // });
+            self.post.ui(ui);
+        });
+    }
+
+    fn build_frame_graph<'a>(&'a self, state: &'a State, graph: &mut FrameGraph<'a>) {
+        let scene = self.post.scene_targets(state, graph);
+        graph.add_pass(
+            PassOptions {
+                label: Some("Scene Pass"),
+                color_attachments: vec![
+                    scene.color_attachment(wgpu::LoadOp::Clear(self.clear_color()))
+                ],
+                depth_attachment: scene.depth_attachment(),
+                ..Default::default()
+            },
+            move |_, mut render_pass| self.render(state, &mut render_pass),
+        );
+        self.post.add_passes(state, graph, scene.backbuffer);
     }
 
     fn render<'rpass>(&'rpass self, state: &State, render_pass: &mut wgpu::RenderPass<'rpass>) {
@@ -351,12 +340,17 @@ impl Application for MyExample {
 }
 
 fn main() {
-    framework::run::<MyExample>(
-        "framebuffer",
-        PhysicalSize {
+    framework::run_with_config::<MyExample>(framework::AppConfig {
+        title: "framebuffer".to_string(),
+        size: PhysicalSize {
             width: 1000,
             height: 1000,
         },
-        4,
-    );
+        state: StateOptions {
+            sample_count: 4,
+            scene_format: Some(post::SCENE_FORMAT),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
 }
@@ -1,3 +1,4 @@
+use pira_wgpu::Error;
 use pira_wgpu::{
     framework::{self, Application},
     helpers::cameras::OrbitControls,
@@ -5,7 +6,10 @@ use pira_wgpu::{
     pipelines::{self, shadeless, ModelUniform},
     state::State,
 };
-use winit::dpi::PhysicalSize;
+use winit::{
+    dpi::PhysicalSize,
+    keyboard::{KeyCode, PhysicalKey},
+};
 
 struct Object {
     _name: &'static str,
@@ -20,10 +24,18 @@ struct MyExample {
     wire_pipeline: pira_wgpu::pipelines::shadeless::ShadelessPipeline,
     objects: Vec<Object>,
     orbit_controls: OrbitControls,
+
+    /// Set by pressing `M`, toggles MSAA on the next update.
+    requested_sample_count: Option<u32>,
 }
 
-impl Application for MyExample {
-    fn init(state: &State) -> Self {
+impl MyExample {
+    fn create_pipelines(
+        state: &State,
+    ) -> (
+        pipelines::shadeless::ShadelessPipeline,
+        pipelines::shadeless::ShadelessPipeline,
+    ) {
         let batch = pipelines::shadeless::ShadelessPipeline::new_with_texture(
             state,
             &state.default_white_texture_bundle,
@@ -32,6 +44,22 @@ impl Application for MyExample {
             None,
         );
 
+        let wire_pipeline = pipelines::shadeless::ShadelessPipeline::new_with_texture(
+            state,
+            &state.default_white_texture_bundle,
+            wgpu::PrimitiveTopology::LineList,
+            true,
+            None,
+        );
+
+        (batch, wire_pipeline)
+    }
+}
+
+impl Application for MyExample {
+    fn init(state: &State) -> Result<Self, Error> {
+        let (batch, wire_pipeline) = Self::create_pipelines(state);
+
         let mut cube = cube::Cube::new(5.0);
         cube.texture_coords();
         cube.vertex_colors_from_normal();
@@ -44,15 +72,7 @@ impl Application for MyExample {
         let axis_mesh =
             shadeless::ShadelessPipeline::get_buffers_from_geometry(state, &sphere.geometry);
 
-        let wire_pipeline = pipelines::shadeless::ShadelessPipeline::new_with_texture(
-            state,
-            &state.default_white_texture_bundle,
-            wgpu::PrimitiveTopology::LineList,
-            true,
-            None,
-        );
-
-        Self {
+        Ok(Self {
             batch,
             wire_pipeline,
             objects: vec![
@@ -73,7 +93,8 @@ impl Application for MyExample {
             ],
 
             orbit_controls: OrbitControls::new(state.window_size.aspect_ratio()),
-        }
+            requested_sample_count: None,
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
@@ -87,10 +108,30 @@ impl Application for MyExample {
 
     fn event(&mut self, state: &State, event: &winit::event::WindowEvent) {
         self.orbit_controls.handle_events(state, event);
+
+        if let winit::event::WindowEvent::KeyboardInput { event, .. } = event {
+            if event.state.is_pressed()
+                && event.physical_key == PhysicalKey::Code(KeyCode::KeyM)
+            {
+                let sample_count = if state.sample_count > 1 { 1 } else { 4 };
+                self.requested_sample_count = Some(sample_count);
+            }
+        }
     }
 
-    fn update(&mut self, _state: &mut State, _frame_count: u64, _delta_time: f64) {
+    fn update(&mut self, state: &mut State, _frame_count: u64, _delta_time: f64) {
         self.orbit_controls.update();
+
+        if let Some(sample_count) = self.requested_sample_count.take() {
+            if let Err(err) = state.set_sample_count(sample_count) {
+                println!("{}", err);
+            }
+        }
+    }
+
+    fn on_sample_count_changed(&mut self, state: &State) {
+        self.batch.recreate_pipelines(state);
+        self.wire_pipeline.recreate_pipelines(state);
     }
 
     fn render<'rpass>(&'rpass self, state: &State, render_pass: &mut wgpu::RenderPass<'rpass>) {
@@ -0,0 +1,48 @@
+use pira_wgpu::{
+    factories::RenderPassFactory,
+    immediate_mode::DrawContext,
+    state::{PerFrameData, Size, State},
+};
+
+fn main() {
+    let state = pollster::block_on(State::new_headless(
+        Size::new(512, 512),
+        4,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    ));
+
+    println!("Rendering with: {:?}", state.adapter.get_info());
+
+    let mut draw_context = DrawContext::new(&state);
+
+    draw_context.start();
+    draw_context.push_color(1.0, 0.5, 0.0);
+    draw_context.push_circle(256.0, 256.0, 128.0);
+    draw_context.push_color(0.0, 0.5, 1.0);
+    draw_context.push_rect(32.0, 32.0, 96.0, 96.0);
+    draw_context.end(&state);
+
+    state.render(|state, frame_data| {
+        let PerFrameData {
+            encoder,
+            view,
+            multisampled_view,
+        } = frame_data;
+
+        let mut render_pass_factory = RenderPassFactory::new();
+        match multisampled_view {
+            Some(multisampled_view) => render_pass_factory.add_color_atachment(
+                wgpu::Color::BLACK,
+                multisampled_view,
+                Some(view),
+            ),
+            None => render_pass_factory.add_color_atachment(wgpu::Color::BLACK, view, None),
+        }
+
+        let mut render_pass = render_pass_factory.get_render_pass(state, encoder, true);
+        draw_context.draw(state, &mut render_pass);
+    });
+
+    state.save_frame("headless.png").unwrap();
+    println!("Saved headless.png");
+}
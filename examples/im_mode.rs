@@ -1,3 +1,4 @@
+use pira_wgpu::Error;
 use pira_wgpu::factories::texture::TextureBundle;
 use pira_wgpu::framework::EguiLayer;
 use pira_wgpu::immediate_mode::DrawContext;
@@ -7,7 +8,7 @@ use pira_wgpu::{
         texture::{SamplerOptions, Texture2dOptions},
     },
     framework::{self, Application},
-    state::State,
+    state::{Size, State},
 };
 use winit::dpi::PhysicalSize;
 
@@ -24,12 +25,12 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
-        let base_path = std::env::current_exe().unwrap().join( "../../../../").canonicalize().unwrap();
+    fn init(state: &State) -> Result<Self, Error> {
+        let base_path = std::env::current_exe()?.join( "../../../../").canonicalize()?;
         println!("{:?}", base_path);
 
 
-        let image = image::open(base_path.join("./assets/rusty.png")).unwrap().to_rgba8();
+        let image = image::open(base_path.join("./assets/rusty.png"))?.to_rgba8();
         let rust_texture_bundle = factories::Texture2dFactory::new_with_options(
             &state,
             [image.width(), image.height()],
@@ -43,8 +44,7 @@ impl Application for MyExample {
             image.as_bytes(),
         );
 
-        let image = image::open(base_path.join("./assets/toronto-skyline.jpeg"))
-            .unwrap()
+        let image = image::open(base_path.join("./assets/toronto-skyline.jpeg"))?
             .to_rgba8();
         let toronto_texture_bundle = factories::Texture2dFactory::new_with_options(
             &state,
@@ -61,14 +61,23 @@ impl Application for MyExample {
 
         let im_draw = DrawContext::new(state);
 
-        Self {
+        Ok(Self {
             im_draw,
             spacing: 25.0,
             freq: 0.01,
 
             texture_bundle: rust_texture_bundle,
             toronto_texture_bundle,
-        }
+        })
+    }
+
+    fn resize(
+        &mut self,
+        config: &wgpu::SurfaceConfiguration,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+    ) {
+        self.im_draw.resize(Size::new(config.width, config.height));
     }
 
     fn event(&mut self, _state: &State, _event: &winit::event::WindowEvent) {}
@@ -2,6 +2,7 @@
 #![allow(unused_variables)]
 
 
+use pira_wgpu::Error;
 use pira_wgpu::factories::texture::{SamplerOptions, Texture2dOptions, TextureBundle};
 use pira_wgpu::factories::{self, BindGroupFactory};
 use pira_wgpu::framework::{self, Application};
@@ -28,7 +29,7 @@ struct KtxExample {
 }
 
 impl Application for KtxExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let bytes = include_bytes!("../assets/toronto-skyline.ktx2");
         let reader = ktx2::Reader::new(bytes).expect("Can't create reader"); // Crate instance of reader.
         let header = reader.header();
@@ -82,7 +83,7 @@ impl Application for KtxExample {
             None,
         );
 
-        Self {
+        Ok(Self {
             clear_color: [0.5, 0.1, 0.1, 1.0],
             buffer: state
                 .device
@@ -105,7 +106,7 @@ impl Application for KtxExample {
             texture_bundle,
             texture_bind_group,
             save_frame: false,
-        }
+        })
     }
 
     fn event(&mut self, state: &State, _event: &winit::event::WindowEvent) {}
@@ -114,9 +115,15 @@ impl Application for KtxExample {
 
         self.clear_color = [1.0, 1.0, 1.0, 1.0];
         
+        if frame_count == 0 {
+            state.set_frame_capture(true);
+        }
+
         if self.save_frame {
             self.save_frame = false;
-            state.save_window_surface_to_file("window.png");
+            if let Err(err) = state.save_frame("window.png") {
+                println!("Failed to save frame: {}", err);
+            }
             // framework::utils::save_texture_to_file(
             //     &state.device,
             //     &state.queue,
@@ -2,56 +2,81 @@
 #![allow(unused_variables)]
 
 use image::EncodableLayout;
+use pira_wgpu::Error;
 use pira_wgpu::{
     factories::{
         self,
         texture::{SamplerOptions, Texture2dOptions},
     },
+    frame_graph::{FrameGraph, PassOptions},
     framework::{self, Application},
     helpers::cameras::OrbitControls,
-    helpers::geometry::{sphere, GeometryFactory},
+    helpers::geometry::{cube, sphere, GeometryFactory},
     image,
     pipelines::{
         self, pbr,
+        post::{self, PostProcessing},
         sky::{self, SkyRendererOptions},
     },
-    state::State,
+    state::{State, StateOptions},
 };
 use wgpu::TextureFormat;
+#[cfg(feature = "hot-reload")]
+use pira_wgpu::helpers::hot_reload::ShaderHotReload;
 use winit::dpi::PhysicalSize;
 
 struct MyExample {
     pipeline: pira_wgpu::pipelines::pbr::PbrPipeline,
     mesh: pbr::GpuMesh,
+    floor: pbr::GpuMesh,
     orbit_controls: OrbitControls,
 
-    uniform: pipelines::pbr::PbrMaterialModelUniform,
+    material: pbr::PbrMaterial,
+    floor_material: pbr::PbrMaterial,
+    lights: pbr::LightSet,
 
     sky_renderer: pipelines::sky::SkyRenderer,
+    post: PostProcessing,
+
+    /// Rebuilds the PBR pipelines when `shader_pbr.wgsl` or `shader_shadow.wgsl` are saved.
+    #[cfg(feature = "hot-reload")]
+    hot_reload: Option<ShaderHotReload>,
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn required_limits() -> wgpu::Limits {
+        pbr::required_limits()
+    }
+
+    fn init(state: &State) -> Result<Self, Error> {
         let sphere_mesh = {
             puffin::profile_scope!("Creating Sphere geometry");
             let mut sphere = sphere::Sphere::new(5.0, 16, 32);
             sphere.texture_coords();
             sphere.normals();
             sphere.vertex_colors_from_normal();
+            sphere.tangents();
             pbr::PbrPipeline::get_buffers_from_geometry(state, &sphere.geometry)
         };
 
+        let floor_mesh = {
+            let mut cube = cube::Cube::new(1.0);
+            cube.texture_coords();
+            cube.normals();
+            cube.tangents();
+            pbr::PbrPipeline::get_buffers_from_geometry(state, &cube.geometry)
+        };
+
         let base_path =
             std::path::Path::new("./assets/");
 
-        let abs_path = std::fs::canonicalize(base_path).unwrap().to_str();
+        let abs_path = std::fs::canonicalize(base_path)?.to_str();
 
-        println!("Base path: {}", std::fs::canonicalize(base_path).unwrap().to_str().unwrap_or_default());
+        println!("Base path: {}", std::fs::canonicalize(base_path)?.to_str().unwrap_or_default());
         let roughness_bundle = {
             puffin::profile_scope!("Loading roughness map");
 
-            let roughness_image = image::open(base_path.join("rustediron2_roughness.png"))
-                .unwrap()
+            let roughness_image = image::open(base_path.join("rustediron2_roughness.png"))?
                 .to_rgba8();
 
             factories::Texture2dFactory::new_with_options(
@@ -70,8 +95,7 @@ impl Application for MyExample {
             )
         };
 
-        let albedo_image = image::open(base_path.join("rustediron2_basecolor.png"))
-            .unwrap()
+        let albedo_image = image::open(base_path.join("rustediron2_basecolor.png"))?
             .to_rgba8();
 
         let albedo_bundle = factories::Texture2dFactory::new_with_options(
@@ -89,8 +113,7 @@ impl Application for MyExample {
             albedo_image.as_bytes(),
         );
 
-        let metallic_image = image::open(base_path.join("rustediron2_metallic.png"))
-            .unwrap()
+        let metallic_image = image::open(base_path.join("rustediron2_metallic.png"))?
             .to_rgba8();
 
         let metallic_bundle = factories::Texture2dFactory::new_with_options(
@@ -108,13 +131,34 @@ impl Application for MyExample {
             metallic_image.as_bytes(),
         );
 
+        // The material has no normal map, derive one from the roughness as if it were a height map.
+        let normal_image = {
+            puffin::profile_scope!("Creating normal map");
+            let height_image = image::open(base_path.join("rustediron2_roughness.png"))?.to_luma8();
+            normal_map_from_height(&height_image, 4.0)
+        };
+
+        let normal_bundle = factories::Texture2dFactory::new_with_options(
+            state,
+            [normal_image.width(), normal_image.height()],
+            Texture2dOptions {
+                label: Some("Normal Texture"),
+                format: TextureFormat::Rgba8Unorm,
+                ..Default::default()
+            },
+            SamplerOptions {
+                filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+            normal_image.as_bytes(),
+        );
+
         let image = {
             puffin::profile_scope!("Loading HDR");
             image::open(
                 base_path.join("buikslotermeerplein_1k.exr"),
                 // "/Users/henrique/Documents/dev/rust/pira-wgpu/assets/cubemap-equi.png",
-            )
-            .unwrap()
+            )?
         };
 
         let sky_renderer = sky::SkyRenderer::new(
@@ -126,28 +170,87 @@ impl Application for MyExample {
             },
         );
 
-        let pipeline = pipelines::pbr::PbrPipeline::new_with_texture(
+        let lights = pbr::LightSet::new(
+            state,
+            vec![
+                pbr::Light {
+                    color: glam::vec3(1.0, 0.9, 0.8),
+                    intensity: 500.0,
+                    ..pbr::Light::point(glam::vec3(5.0, 5.0, 10.0))
+                },
+                pbr::Light {
+                    color: glam::vec3(0.3, 0.5, 1.0),
+                    intensity: 1000.0,
+                    range: 40.0,
+                    ..pbr::Light::spot(
+                        glam::vec3(-10.0, 10.0, 0.0),
+                        glam::vec3(1.0, -1.0, 0.0),
+                        0.2,
+                        0.4,
+                    )
+                },
+                pbr::Light {
+                    intensity: 2.0,
+                    ..pbr::Light::directional(glam::vec3(0.5, -1.0, -0.5))
+                },
+            ],
+            Default::default(),
+        );
+
+        let pipeline = pipelines::pbr::PbrPipeline::new(
             state,
-            &roughness_bundle,
-            &albedo_bundle,
-            &metallic_bundle,
             &sky_renderer,
+            &lights,
             wgpu::PrimitiveTopology::TriangleList,
             true,
         );
 
-        let mut uniform = pipelines::pbr::PbrMaterialModelUniform::new(glam::Mat4::IDENTITY);
-        uniform.ambient = glam::vec3(0.4, 0.4, 0.4);
-        uniform.light_intensity = 5.0;
+        let material = pbr::PbrMaterial::new(
+            state,
+            &pipeline,
+            pbr::PbrMaterialOptions {
+                label: Some("Rusted iron"),
+                factors: pbr::PbrMaterialFactors {
+                    ambient: glam::vec3(0.4, 0.4, 0.4),
+                    ..Default::default()
+                },
+                albedo_texture: Some(&albedo_bundle),
+                roughness_texture: Some(&roughness_bundle),
+                metallic_texture: Some(&metallic_bundle),
+                normal_texture: Some(&normal_bundle),
+                ..Default::default()
+            },
+        );
 
-        Self {
+        let floor_material = pbr::PbrMaterial::new(
+            state,
+            &pipeline,
+            pbr::PbrMaterialOptions {
+                label: Some("Floor"),
+                factors: pbr::PbrMaterialFactors {
+                    roughness: 0.8,
+                    metallic: 0.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+
+        Ok(Self {
             pipeline,
             mesh: sphere_mesh,
+            floor: floor_mesh,
             orbit_controls: OrbitControls::new(state.window_size.aspect_ratio()),
-            uniform,
+            material,
+            floor_material,
+            lights,
 
             sky_renderer,
-        }
+            post: PostProcessing::new(state, Default::default()),
+
+            #[cfg(feature = "hot-reload")]
+            hot_reload: ShaderHotReload::new(),
+        })
     }
 
     fn event(&mut self, state: &State, event: &winit::event::WindowEvent) {
@@ -155,25 +258,23 @@ impl Application for MyExample {
     }
 
     fn update(&mut self, state: &mut State, frame_count: u64, delta_time: f64) {
+        #[cfg(feature = "hot-reload")]
+        if let Some(hot_reload) = &mut self.hot_reload {
+            hot_reload.poll();
+            self.pipeline.reload_shaders(state, &self.lights, hot_reload);
+            self.sky_renderer.reload_shaders(state, hot_reload);
+            self.post.reload_shaders(state, hot_reload);
+        }
+
         let State { device, queue, .. } = state;
 
         self.orbit_controls.update();
 
-        let view_pos = glam::Vec4::from((self.orbit_controls.get_local_position(), 1.0));
-        let view_mat = self.orbit_controls.get_view_matrix();
-        let view_proj_mat = self.orbit_controls.get_perspective_view_matrix(); // self.orbit_controls.get_view_matrix();
-
-        let uniform: sky::Uniform = sky::Uniform {
-            view_pos: view_pos.to_array(),
-            view: view_mat.to_cols_array(),
-            view_proj: view_proj_mat.to_cols_array(),
-            inv_proj: self
-                .orbit_controls
-                .get_perspective_matrix()
-                .inverse()
-                .to_cols_array(),
-            inv_view: view_mat.inverse().to_cols_array(),
-        };
+        let uniform = sky::Uniform::new(
+            self.orbit_controls.get_local_position(),
+            self.orbit_controls.get_view_matrix(),
+            self.orbit_controls.get_perspective_matrix(),
+        );
 
         pipelines::write_uniform_buffer(
             &[uniform],
@@ -181,72 +282,198 @@ impl Application for MyExample {
             queue,
             device,
         );
+
+        self.sky_renderer.write_params(state);
+        self.material.write(state);
+        self.lights.write(state);
+        self.lights.update_shadows(
+            state,
+            &self.orbit_controls.camera,
+            self.orbit_controls.get_view_matrix(),
+        );
     }
 
     fn on_gui(&mut self, egui_ctx: &mut framework::EguiLayer) {
+        #[cfg(feature = "hot-reload")]
+        if let Some(hot_reload) = &self.hot_reload {
+            hot_reload.show_errors(&egui_ctx.ctx);
+        }
+
         egui::Window::new("Settings").show(&egui_ctx.ctx, |ui| {
-            ui.color_edit_button_rgb(self.uniform.albedo.as_mut());
+            ui.color_edit_button_rgb(self.material.factors.albedo.as_mut());
 
             ui.label("Ambient");
 
-            ui.color_edit_button_rgb(self.uniform.ambient.as_mut());
-
-            ui.label("Light Position");
-
-            ui.horizontal(|ui| {
-                ui.add(
-                    egui::DragValue::new(&mut self.uniform.light_position.x)
-                        .prefix("x: ")
-                        .speed(0.01),
-                );
-                ui.add(
-                    egui::DragValue::new(&mut self.uniform.light_position.y)
-                        .prefix("y: ")
-                        .speed(0.01),
-                );
-                ui.add(
-                    egui::DragValue::new(&mut self.uniform.light_position.z)
-                        .prefix("z: ")
-                        .speed(0.01),
-                );
+            ui.color_edit_button_rgb(self.material.factors.ambient.as_mut());
+
+            ui.collapsing("Lights", |ui| {
+                for (index, light) in self.lights.lights.iter_mut().enumerate() {
+                    ui.push_id(index, |ui| {
+                        ui.label(format!("{:?}", light.kind));
+
+                        ui.horizontal(|ui| {
+                            ui.add(
+                                egui::DragValue::new(&mut light.position.x)
+                                    .prefix("x: ")
+                                    .speed(0.01),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut light.position.y)
+                                    .prefix("y: ")
+                                    .speed(0.01),
+                            );
+                            ui.add(
+                                egui::DragValue::new(&mut light.position.z)
+                                    .prefix("z: ")
+                                    .speed(0.01),
+                            );
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.color_edit_button_rgb(light.color.as_mut());
+                            ui.add(
+                                egui::DragValue::new(&mut light.intensity)
+                                    .range(0.0..=2000.0)
+                                    .speed(0.1),
+                            );
+                        });
+                    });
+                }
+
+                if ui.button("Add point light").clicked() {
+                    self.lights.lights.push(pbr::Light {
+                        intensity: 100.0,
+                        ..pbr::Light::point(glam::vec3(0.0, 8.0, 0.0))
+                    });
+                }
             });
 
-            ui.label("Light");
+            ui.label("Roughness");
             ui.add(
-                egui::DragValue::new(&mut self.uniform.light_intensity)
-                    .range(0.0..=20.0)
+                egui::DragValue::new(&mut self.material.factors.roughness)
+                    .range(0.0..=1.0)
                     .speed(0.01),
             );
 
-            ui.label("Roughness");
+            ui.label("Normal Strength");
             ui.add(
-                egui::DragValue::new(&mut self.uniform.roughness)
-                    .range(0.0..=1.0)
+                egui::DragValue::new(&mut self.material.factors.normal_strength)
+                    .range(0.0..=4.0)
                     .speed(0.01),
             );
 
             ui.label("Metallic");
             ui.add(
-                egui::DragValue::new(&mut self.uniform.metallic)
+                egui::DragValue::new(&mut self.material.factors.metallic)
                     .range(0.0..=1.0)
                     .speed(0.01),
             );
+
+            ui.collapsing("Extensions", |ui| {
+                let factors = &mut self.material.factors;
+
+                ui.horizontal(|ui| {
+                    ui.label("Emissive");
+                    ui.color_edit_button_rgb(factors.emissive.as_mut());
+                });
+                ui.add(egui::Slider::new(&mut factors.clearcoat, 0.0..=1.0).text("Clearcoat"));
+                ui.add(
+                    egui::Slider::new(&mut factors.clearcoat_roughness, 0.0..=1.0)
+                        .text("Clearcoat roughness"),
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Sheen");
+                    ui.color_edit_button_rgb(factors.sheen_color.as_mut());
+                });
+                ui.add(
+                    egui::Slider::new(&mut factors.sheen_roughness, 0.0..=1.0)
+                        .text("Sheen roughness"),
+                );
+
+                ui.horizontal(|ui| {
+                    let alpha_mode = &mut self.material.alpha_mode;
+                    ui.selectable_value(alpha_mode, pbr::AlphaMode::Opaque, "Opaque");
+                    ui.selectable_value(alpha_mode, pbr::AlphaMode::Mask { cutoff: 0.5 }, "Mask");
+                    ui.selectable_value(alpha_mode, pbr::AlphaMode::Blend, "Blend");
+                });
+                ui.add(egui::Slider::new(&mut factors.alpha, 0.0..=1.0).text("Alpha"));
+            });
+
+            ui.collapsing("Sky", |ui| {
+                let specular_mips = self
+                    .sky_renderer
+                    .specular_reflection_texture
+                    .texture
+                    .mip_level_count();
+                let params = &mut self.sky_renderer.params;
+
+                ui.add(
+                    egui::Slider::new(&mut params.rotation, 0.0..=std::f32::consts::TAU)
+                        .text("Rotation"),
+                );
+                ui.add(egui::Slider::new(&mut params.exposure, -4.0..=4.0).text("Exposure"));
+                ui.add(
+                    egui::Slider::new(&mut params.background_mip, 0.0..=(specular_mips - 1) as f32)
+                        .text("Background blur"),
+                );
+                ui.horizontal(|ui| {
+                    let mut solid = params.background_color.is_some();
+                    ui.checkbox(&mut solid, "Solid background");
+                    params.background_color =
+                        solid.then_some(params.background_color.unwrap_or(glam::Vec3::splat(0.18)));
+                    if let Some(color) = &mut params.background_color {
+                        ui.color_edit_button_rgb(color.as_mut());
+                    }
+                });
+            });
+            ui.collapsing("Shadows", |ui| self.lights.shadows.ui(ui));
+            ui.collapsing("Post Processing", |ui| self.post.ui(ui));
         });
     }
 
     fn clear_color(&self) -> wgpu::Color {
         wgpu::Color {
-            r: self.uniform.ambient.x as f64,
-            g: self.uniform.ambient.y as f64,
-            b: self.uniform.ambient.z as f64,
+            r: self.material.factors.ambient.x as f64,
+            g: self.material.factors.ambient.y as f64,
+            b: self.material.factors.ambient.z as f64,
             a: 1.0,
         }
     }
 
+    fn build_frame_graph<'a>(&'a self, state: &'a State, graph: &mut FrameGraph<'a>) {
+        let view_uniform = pipelines::ViewUniform {
+            view_pespective_matrix: self.orbit_controls.get_perspective_view_matrix(),
+            view_matrix: self.orbit_controls.get_view_matrix(),
+            perspective_matrix: self.orbit_controls.get_perspective_matrix(),
+            camera_position: self.orbit_controls.get_local_position(),
+        };
+        self.pipeline.begin_frame(state, &view_uniform);
+
+        self.lights.shadows.add_passes(graph, move |render_pass| {
+            for (mesh, transform, material) in self.objects() {
+                self.pipeline
+                    .draw_shadow_mesh(state, render_pass, mesh, transform, material);
+            }
+        });
+
+        let scene = self.post.scene_targets(state, graph);
+        graph.add_pass(
+            PassOptions {
+                label: Some("Scene Pass"),
+                color_attachments: vec![
+                    scene.color_attachment(wgpu::LoadOp::Clear(self.clear_color()))
+                ],
+                depth_attachment: scene.depth_attachment(),
+                ..Default::default()
+            },
+            move |_, mut render_pass| self.render(state, &mut render_pass),
+        );
+        self.post.add_passes(state, graph, scene.backbuffer);
+    }
+
     fn render<'rpass>(&'rpass self, state: &State, render_pass: &mut wgpu::RenderPass<'rpass>) {
         let MyExample {
             pipeline,
-            mesh,
             sky_renderer,
             ..
         } = &self;
@@ -254,45 +481,68 @@ impl Application for MyExample {
         sky_renderer.draw(render_pass);
 
         render_pass.set_pipeline(&pipeline.pipeline);
-        self.orbit_controls.get_perspective_view_matrix();
-
-        let view_uniform = pipelines::ViewUniform {
-            view_pespective_matrix: self.orbit_controls.get_perspective_view_matrix(),
-            view_matrix: self.orbit_controls.get_view_matrix(),
-            perspective_matrix: self.orbit_controls.get_perspective_matrix(),
-            camera_position: self.orbit_controls.get_local_position(),
-        };
+        render_pass.set_bind_group(1, &pipeline.environment_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.lights.bind_group, &[]);
 
-        pipelines::write_uniform_buffer(
-            &[view_uniform],
-            self.pipeline.global_uniform_buffer.as_ref().unwrap(),
-            &state.queue,
-            &state.device,
-        );
+        for (mesh, transform, material) in self.objects() {
+            pipeline.draw_mesh(state, render_pass, mesh, transform, material);
+        }
+    }
+}
 
-        pipelines::write_uniform_buffer(
-            &[self.uniform],
-            &pipeline.model_uniform_buffer.as_ref().unwrap(),
-            &state.queue,
-            &state.device,
+impl MyExample {
+    /// Meshes of the scene with their transform and material, drawn in the scene and shadow passes.
+    fn objects(&self) -> [(&pbr::GpuMesh, glam::Mat4, &pbr::PbrMaterial); 2] {
+        let floor_transform = glam::Mat4::from_scale_rotation_translation(
+            glam::vec3(40.0, 0.5, 40.0),
+            glam::Quat::IDENTITY,
+            glam::vec3(0.0, -6.0, 0.0),
         );
 
-        render_pass.set_bind_group(0, &pipeline.bind_group, &[0, 0]);
-        render_pass.set_bind_group(1, pipeline.texture_bind_group.as_ref().unwrap(), &[]);
-
-        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..mesh.vertex_count, 0, 0..1);
+        // Opaque first, the sphere can be switched to blending.
+        [
+            (&self.floor, floor_transform, &self.floor_material),
+            (&self.mesh, glam::Mat4::IDENTITY, &self.material),
+        ]
     }
 }
 
+/// Tangent space normals from the slopes of a height map, `scale` exaggerates them.
+fn normal_map_from_height(height: &image::GrayImage, scale: f32) -> image::RgbaImage {
+    let (width, height_px) = height.dimensions();
+    let sample = |x: i64, y: i64| {
+        let x = x.rem_euclid(width as i64) as u32;
+        let y = y.rem_euclid(height_px as i64) as u32;
+        height.get_pixel(x, y).0[0] as f32 / 255.0
+    };
+
+    image::RgbaImage::from_fn(width, height_px, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dx = (sample(x + 1, y) - sample(x - 1, y)) * scale;
+        let dy = (sample(x, y + 1) - sample(x, y - 1)) * scale;
+        let normal = glam::vec3(-dx, dy, 1.0).normalize() * 0.5 + 0.5;
+
+        image::Rgba([
+            (normal.x * 255.0) as u8,
+            (normal.y * 255.0) as u8,
+            (normal.z * 255.0) as u8,
+            255,
+        ])
+    })
+}
+
 fn main() {
-    framework::run::<MyExample>(
-        "simple_app",
-        PhysicalSize {
+    framework::run_with_config::<MyExample>(framework::AppConfig {
+        title: "pbr".to_string(),
+        size: PhysicalSize {
             width: 1920 * 2,
             height: 1080 * 2,
         },
-        4,
-    );
+        state: StateOptions {
+            sample_count: 4,
+            scene_format: Some(post::SCENE_FORMAT),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
 }
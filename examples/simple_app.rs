@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 
+use pira_wgpu::Error;
 use pira_wgpu::{
     factories::{BindGroupFactory, RenderPipelineFactory},
     framework::{self, Application},
@@ -51,7 +52,7 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let vertices = vec![
             Vertex {
                 position: [0.0, 0.0, 0.0],
@@ -92,7 +93,7 @@ impl Application for MyExample {
         let pipeline =
             pipeline_factory.create_render_pipeline(&state, &shader_module, &[Some(&bind_group_layout)]);
 
-        MyExample {
+        Ok(MyExample {
             clear_color: [0.0, 0.0, 0.0, 0.0],
             buffer: state
                 .device
@@ -111,7 +112,7 @@ impl Application for MyExample {
 
             bind_group,
             pipeline,
-        }
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
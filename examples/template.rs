@@ -1,15 +1,16 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+use pira_wgpu::Error;
 use pira_wgpu::framework::{self, Application};
-use pira_wgpu::state::State;
+use pira_wgpu::state::{State, StateOptions, VSync};
 use winit::dpi::PhysicalSize;
 
 struct MyExample {}
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
-        Self {}
+    fn init(state: &State) -> Result<Self, Error> {
+        Ok(Self {})
     }
 
     fn event(&mut self, state: &State, _event: &winit::event::WindowEvent) {}
@@ -20,12 +21,17 @@ impl Application for MyExample {
 }
 
 fn main() {
-    framework::run::<MyExample>(
-        "simple_app",
-        PhysicalSize {
+    framework::run_with_config::<MyExample>(framework::AppConfig {
+        title: "simple_app".to_string(),
+        size: PhysicalSize {
             width: 1920 * 2,
             height: 1080 * 2,
         },
-        4,
-    );
+        state: StateOptions {
+            sample_count: 4,
+            vsync: VSync::On,
+            ..Default::default()
+        },
+        ..Default::default()
+    });
 }
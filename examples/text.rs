@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+use pira_wgpu::Error;
 use pira_wgpu::factories::render_pipeline::DepthConfig;
 use pira_wgpu::framework::{self, Application};
 use pira_wgpu::state::State;
@@ -18,7 +19,7 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         
         let firacode_medium = include_bytes!("../assets/FiraCode-Medium.ttf");
         let firacode_regular = include_bytes!("../assets/FiraCode-Regular.ttf");
@@ -44,11 +45,11 @@ impl Application for MyExample {
         println!("Fonts: {:?}", brush.fonts());
         
         
-        Self { 
+        Ok(Self { 
             brush,
             med_id,
             reg_id,
-         }
+         })
     }
 
     fn event(&mut self, state: &State, _event: &winit::event::WindowEvent) {}
@@ -2,6 +2,7 @@ use glam::Mat4;
 use wgpu::util::DeviceExt;
 use wgpu::{self, BindGroup};
 
+use pira_wgpu::Error;
 use pira_wgpu::factories::texture::{SamplerOptions, Texture2dOptions, TextureBundle};
 use pira_wgpu::factories::{self, BindGroupFactory};
 use pira_wgpu::framework;
@@ -28,7 +29,7 @@ struct MyExample {
 }
 
 impl Application for MyExample {
-    fn init(state: &State) -> Self {
+    fn init(state: &State) -> Result<Self, Error> {
         let vertices = vec![
             shadeless::Vertex::new([-0.8, -0.8, 0.0], [0.0, 0.0], [1.0, 1.0, 1.0, 1.0]),
             shadeless::Vertex::new([0.8, 0.8, 0.0], [1.0, 1.0], [1.0, 1.0, 1.0, 1.0]),
@@ -39,7 +40,7 @@ impl Application for MyExample {
         let mut indices: [u16; 6] = [0, 1, 2, 0, 3, 1];
         indices.reverse();
 
-        let image = image::open("./assets/rusty.png").unwrap().to_rgba8();
+        let image = image::open("./assets/rusty.png")?.to_rgba8();
         let texture_bundle = factories::Texture2dFactory::new_with_options(
             &state,
             [image.width(), image.height()],
@@ -53,8 +54,7 @@ impl Application for MyExample {
             image.as_bytes(),
         );
 
-        let image_toronto = image::open("./assets/toronto-skyline.jpeg")
-            .unwrap()
+        let image_toronto = image::open("./assets/toronto-skyline.jpeg")?
             .to_rgba8();
         let texture_bundle_toronto = factories::Texture2dFactory::new_with_options(
             &state,
@@ -86,7 +86,7 @@ impl Application for MyExample {
             None,
         );
 
-        MyExample {
+        Ok(MyExample {
             clear_color: [0.5, 0.1, 0.1, 1.0],
             buffer: state
                 .device
@@ -108,7 +108,7 @@ impl Application for MyExample {
             use_toronto_photo: false,
             _texture_bundle_toronto: texture_bundle_toronto,
             toronto_bind_group,
-        }
+        })
     }
 
     fn clear_color(&self) -> wgpu::Color {
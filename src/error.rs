@@ -0,0 +1,113 @@
+use std::fmt;
+
+use crate::readback::CaptureError;
+use crate::state::DeviceRequirementsError;
+
+/// Errors returned by the fallible parts of the crate.
+#[derive(Debug)]
+pub enum Error {
+    /// No adapter or device matches the requested features and limits.
+    Device(DeviceRequirementsError),
+    CreateSurface(wgpu::CreateSurfaceError),
+    EventLoop(winit::error::EventLoopError),
+    CreateWindow(winit::error::OsError),
+    Image(image::ImageError),
+    /// A KTX2 file that can't be parsed.
+    Ktx2(ktx2::ParseError),
+    Capture(CaptureError),
+    Io(std::io::Error),
+    /// Errors raised by applications, e.g. from [`crate::framework::Application::init`].
+    Message(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Device(err) => write!(f, "{}", err),
+            Error::CreateSurface(err) => write!(f, "failed to create the window surface: {}", err),
+            Error::EventLoop(err) => write!(f, "event loop error: {}", err),
+            Error::CreateWindow(err) => write!(f, "failed to create the window: {}", err),
+            Error::Image(err) => write!(f, "failed to load image: {}", err),
+            Error::Ktx2(err) => write!(f, "failed to parse KTX2 file: {}", err),
+            Error::Capture(err) => write!(f, "{}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Message(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Device(err) => Some(err),
+            Error::CreateSurface(err) => Some(err),
+            Error::EventLoop(err) => Some(err),
+            Error::CreateWindow(err) => Some(err),
+            Error::Image(err) => Some(err),
+            Error::Ktx2(err) => Some(err),
+            Error::Capture(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Message(_) => None,
+        }
+    }
+}
+
+impl From<DeviceRequirementsError> for Error {
+    fn from(err: DeviceRequirementsError) -> Self {
+        Error::Device(err)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for Error {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        Error::CreateSurface(err)
+    }
+}
+
+impl From<winit::error::EventLoopError> for Error {
+    fn from(err: winit::error::EventLoopError) -> Self {
+        Error::EventLoop(err)
+    }
+}
+
+impl From<winit::error::OsError> for Error {
+    fn from(err: winit::error::OsError) -> Self {
+        Error::CreateWindow(err)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(err: image::ImageError) -> Self {
+        Error::Image(err)
+    }
+}
+
+impl From<ktx2::ParseError> for Error {
+    fn from(err: ktx2::ParseError) -> Self {
+        Error::Ktx2(err)
+    }
+}
+
+impl From<CaptureError> for Error {
+    fn from(err: CaptureError) -> Self {
+        Error::Capture(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Message(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Message(message.to_string())
+    }
+}
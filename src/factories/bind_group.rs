@@ -1,5 +1,7 @@
 use std::num::NonZeroU64;
 
+use crate::state::State;
+
 pub struct BindGroupFactory<'a> {
     resources: Vec<wgpu::BindGroupEntry<'a>>,
     // buffers : Vec<wgpu::Buffer>,
@@ -58,6 +60,28 @@ impl<'a> BindGroupFactory<'a> {
         self
     }
 
+    /// Like [`BindGroupFactory::add_uniform`] but bound without a dynamic offset.
+    pub fn add_static_uniform<'b>(
+        &'b mut self,
+        stage: wgpu::ShaderStages,
+        data: &'a wgpu::Buffer,
+        min_binding_size: Option<NonZeroU64>,
+    ) -> &'b mut Self {
+        let binding_type = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size,
+        };
+        self.binding_types.push((stage, binding_type));
+
+        self.resources.push(wgpu::BindGroupEntry {
+            binding: self.resources.len() as u32,
+            resource: data.as_entire_binding(),
+        });
+
+        self
+    }
+
     pub fn add_texture_and_sampler<'b>(
         &'b mut self,
         stage: wgpu::ShaderStages,
@@ -115,6 +139,35 @@ impl<'a> BindGroupFactory<'a> {
         self
     }
 
+    /// Filterable cube map with a filtering sampler, e.g. the image based lighting maps of a sky.
+    pub fn add_cube_texture_and_sampler<'b>(
+        &'b mut self,
+        stage: wgpu::ShaderStages,
+        texture_view: &'a wgpu::TextureView,
+        sampler: &'a wgpu::Sampler,
+    ) -> &'b mut Self {
+        self.resources.push(wgpu::BindGroupEntry {
+            binding: self.resources.len() as u32,
+            resource: wgpu::BindingResource::TextureView(texture_view),
+        });
+        self.resources.push(wgpu::BindGroupEntry {
+            binding: self.resources.len() as u32,
+            resource: wgpu::BindingResource::Sampler(sampler),
+        });
+
+        let texture_binding_type = wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        };
+        let sampler_binding_type = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering);
+
+        self.binding_types.push((stage, texture_binding_type));
+        self.binding_types.push((stage, sampler_binding_type));
+
+        self
+    }
+
     pub fn add_texture_hdr_and_sampler<'b>(
         &'b mut self,
         stage: wgpu::ShaderStages,
@@ -146,6 +199,33 @@ impl<'a> BindGroupFactory<'a> {
     }
 
     pub fn build(&self, device: &wgpu::Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let layout_entries = self.layout_entries();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &layout_entries.as_slice(),
+            label: self.bind_group_layout_label,
+        });
+
+        let bind_group = self.build_bind_group(device, &bind_group_layout);
+
+        (bind_group_layout, bind_group)
+    }
+
+    /// Like [`BindGroupFactory::build`], but takes the layout from [`State::pipeline_cache`] so
+    /// factories with the same bindings share it, and the pipelines built with it.
+    pub fn build_cached(&self, state: &State) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let bind_group_layout = state.pipeline_cache.bind_group_layout(
+            &state.device,
+            self.bind_group_layout_label,
+            &self.layout_entries(),
+        );
+
+        let bind_group = self.build_bind_group(&state.device, &bind_group_layout);
+
+        (bind_group_layout, bind_group)
+    }
+
+    fn layout_entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
         let mut layout_entries = Vec::new();
         for index in 0..self.binding_types.len() {
             // println!("{} Binding {:?}", index, self.binding_types[index]);
@@ -158,18 +238,18 @@ impl<'a> BindGroupFactory<'a> {
             };
             layout_entries.push(layout_entry);
         }
+        layout_entries
+    }
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &layout_entries.as_slice(),
-            label: self.bind_group_layout_label,
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
+    fn build_bind_group(
+        &self,
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: bind_group_layout,
             entries: self.resources.as_slice(),
             label: self.bind_group_label,
-        });
-
-        (bind_group_layout, bind_group)
+        })
     }
 }
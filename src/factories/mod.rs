@@ -1,6 +1,9 @@
 pub mod bind_group;
 pub use bind_group::BindGroupFactory;
 
+pub mod pipeline_cache;
+pub use pipeline_cache::PipelineCache;
+
 pub mod render_pass;
 pub use render_pass::RenderPassFactory;
 
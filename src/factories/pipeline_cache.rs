@@ -0,0 +1,192 @@
+//! Shares shader modules, bind group layouts and render pipelines between identical requests.
+//!
+//! [`State::pipeline_cache`](crate::state::State::pipeline_cache) is used by
+//! [`RenderPipelineFactory::create_cached_render_pipeline`](super::RenderPipelineFactory::create_cached_render_pipeline)
+//! and [`BindGroupFactory::build_cached`](super::BindGroupFactory::build_cached). On backends
+//! with [`wgpu::Features::PIPELINE_CACHE`] it also keeps a driver cache that can be saved to
+//! disk, so compiled pipelines survive restarts.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Everything a [`super::RenderPipelineFactory`] puts into a pipeline descriptor, with the
+/// defaults taken from the state resolved.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    /// Source of the shader module, as returned by [`PipelineCache::shader_module`].
+    pub shader: Arc<str>,
+    pub bind_group_layouts: Vec<Option<wgpu::BindGroupLayout>>,
+    pub vertex_buffer_layouts: Vec<VertexBufferKey>,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub blend: Option<wgpu::BlendState>,
+    pub vert_entry: Option<String>,
+    pub frag_entry: Option<String>,
+    pub color_target_format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub topology: wgpu::PrimitiveTopology,
+    pub cull_mode: Option<wgpu::Face>,
+    pub depth_only: bool,
+}
+
+/// Owned version of a [`wgpu::VertexBufferLayout`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct VertexBufferKey {
+    pub array_stride: wgpu::BufferAddress,
+    pub step_mode: wgpu::VertexStepMode,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl From<&wgpu::VertexBufferLayout<'_>> for VertexBufferKey {
+    fn from(layout: &wgpu::VertexBufferLayout<'_>) -> Self {
+        Self {
+            array_stride: layout.array_stride,
+            step_mode: layout.step_mode,
+            attributes: layout.attributes.to_vec(),
+        }
+    }
+}
+
+/// Cache of the GPU objects that are expensive to create and often requested more than once.
+///
+/// Entries live as long as the cache, call [`PipelineCache::clear`] to drop the ones nobody
+/// else holds on to, e.g. after [`crate::state::State::set_sample_count`].
+pub struct PipelineCache {
+    // Keyed by the whole source, a hash alone could hand out the module of another shader.
+    shader_modules: Mutex<HashMap<Arc<str>, wgpu::ShaderModule>>,
+    bind_group_layouts: Mutex<HashMap<Vec<wgpu::BindGroupLayoutEntry>, wgpu::BindGroupLayout>>,
+    render_pipelines: Mutex<HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>>,
+
+    /// Driver side cache, `None` when the device doesn't have [`wgpu::Features::PIPELINE_CACHE`].
+    wgpu_cache: Option<wgpu::PipelineCache>,
+    /// File [`PipelineCache::save`] writes the driver cache to.
+    path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+    /// Creates an empty cache, loading the driver cache from `dir` when given and supported.
+    pub fn new(device: &wgpu::Device, adapter: &wgpu::Adapter, dir: Option<&Path>) -> Self {
+        let mut wgpu_cache = None;
+        let mut path = None;
+
+        if device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            // Only backends with a stable cache format have a key, e.g. Vulkan.
+            path = dir
+                .zip(wgpu::util::pipeline_cache_key(&adapter.get_info()))
+                .map(|(dir, key)| dir.join(key));
+            let data = path.as_ref().and_then(|path| std::fs::read(path).ok());
+
+            // SAFETY: the data was written by `save` from the cache of an adapter with the same
+            // key, wgpu checks its header and falls back to an empty cache when it doesn't match.
+            wgpu_cache = Some(unsafe {
+                device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                    label: Some("Pipeline cache"),
+                    data: data.as_deref(),
+                    fallback: true,
+                })
+            });
+        }
+
+        Self {
+            shader_modules: Mutex::new(HashMap::new()),
+            bind_group_layouts: Mutex::new(HashMap::new()),
+            render_pipelines: Mutex::new(HashMap::new()),
+            wgpu_cache,
+            path,
+        }
+    }
+
+    /// Compiles `source` the first time it is requested, returns the source it is cached by and
+    /// the module.
+    pub fn shader_module(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        source: &str,
+    ) -> (Arc<str>, wgpu::ShaderModule) {
+        let mut shader_modules = self.shader_modules.lock().unwrap();
+        if let Some((key, module)) = shader_modules.get_key_value(source) {
+            return (key.clone(), module.clone());
+        }
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let key: Arc<str> = source.into();
+        shader_modules.insert(key.clone(), module.clone());
+
+        (key, module)
+    }
+
+    /// Returns the same layout for the same entries, so pipelines built with it are shared too.
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> wgpu::BindGroupLayout {
+        self.bind_group_layouts
+            .lock()
+            .unwrap()
+            .entry(entries.to_vec())
+            .or_insert_with(|| {
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label, entries })
+            })
+            .clone()
+    }
+
+    /// Returns the pipeline for `key`, creating it with `create` the first time.
+    pub(crate) fn render_pipeline(
+        &self,
+        key: PipelineKey,
+        create: impl FnOnce(Option<&wgpu::PipelineCache>) -> wgpu::RenderPipeline,
+    ) -> Arc<wgpu::RenderPipeline> {
+        self.render_pipelines
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(create(self.wgpu_cache.as_ref())))
+            .clone()
+    }
+
+    pub fn render_pipeline_count(&self) -> usize {
+        self.render_pipelines.lock().unwrap().len()
+    }
+
+    pub fn shader_module_count(&self) -> usize {
+        self.shader_modules.lock().unwrap().len()
+    }
+
+    /// Forgets every cached object, the driver cache is kept.
+    pub fn clear(&self) {
+        self.render_pipelines.lock().unwrap().clear();
+        self.bind_group_layouts.lock().unwrap().clear();
+        self.shader_modules.lock().unwrap().clear();
+    }
+
+    /// The driver cache, pass it to pipelines created without the factories.
+    pub fn wgpu_cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.wgpu_cache.as_ref()
+    }
+
+    /// Writes the driver cache to the directory it was loaded from.
+    ///
+    /// Does nothing when no directory was given or the backend has no driver cache.
+    pub fn save(&self) -> std::io::Result<()> {
+        let (Some(cache), Some(path)) = (&self.wgpu_cache, &self.path) else {
+            return Ok(());
+        };
+        let Some(data) = cache.get_data() else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Write next to the cache first, so a crash never leaves a truncated cache behind.
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, data)?;
+        std::fs::rename(temp_path, path)
+    }
+}
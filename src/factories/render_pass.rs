@@ -31,6 +31,21 @@ impl<'a> RenderPassFactory<'a> {
             }));
     }
 
+    pub fn add_color_attachment_with_ops(
+        &mut self,
+        view: &'a wgpu::TextureView,
+        resolve_target: Option<&'a wgpu::TextureView>,
+        ops: wgpu::Operations<wgpu::Color>,
+    ) {
+        self.color_attachments
+            .push(Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                depth_slice: None,
+                ops,
+            }));
+    }
+
     pub fn add_depth_stencil(&mut self, depth_texture: &'a wgpu::TextureView) {
         self.depth_stencil = Some(wgpu::RenderPassDepthStencilAttachment {
             view: &depth_texture,
@@ -42,6 +57,35 @@ impl<'a> RenderPassFactory<'a> {
         });
     }
 
+    pub fn add_depth_stencil_with_ops(
+        &mut self,
+        depth_texture: &'a wgpu::TextureView,
+        ops: wgpu::Operations<f32>,
+    ) {
+        self.depth_stencil = Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_texture,
+            depth_ops: Some(ops),
+            stencil_ops: None,
+        });
+    }
+
+    /// Begins a pass with the attachments added so far, unlike [`Self::get_render_pass`] the
+    /// depth attachment comes from [`Self::add_depth_stencil`] instead of the state.
+    pub fn begin_render_pass<'e>(
+        &self,
+        encoder: &'e mut wgpu::CommandEncoder,
+        label: Option<&str>,
+    ) -> wgpu::RenderPass<'e> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label,
+            color_attachments: &self.color_attachments,
+            depth_stencil_attachment: self.depth_stencil.clone(),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+            multiview_mask: None,
+        })
+    }
+
     pub fn get_render_pass(
         &self,
         ctx: &'a State,
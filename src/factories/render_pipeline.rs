@@ -1,3 +1,7 @@
+use std::sync::Arc;
+
+use crate::factories::pipeline_cache::{PipelineKey, VertexBufferKey};
+use crate::factories::DepthTextureFactory;
 use crate::state::State;
 use wgpu::{BlendState, DepthStencilState, PipelineCompilationOptions, PrimitiveTopology, ShaderModule, TextureFormat};
 
@@ -7,6 +11,8 @@ pub enum DepthConfig {
     Custom(DepthStencilState),
     DefaultWrite,
     DefaultDontWrite,
+    /// Writes depth with a bias for shadow maps, in [`DepthTextureFactory::get_shadow_map_format`].
+    Shadow,
 }
 
 impl DepthConfig {
@@ -32,6 +38,20 @@ impl DepthConfig {
                     bias: wgpu::DepthBiasState::default(),
                 })
             }
+            DepthConfig::Shadow => {
+                Some(wgpu::DepthStencilState {
+                    format: DepthTextureFactory::get_shadow_map_format(),
+                    depth_write_enabled: Some(true),
+                    depth_compare: Some(wgpu::CompareFunction::LessEqual),
+                    stencil: wgpu::StencilState::default(),
+                    // Slope scaled so surfaces at grazing angles to the light don't self shadow.
+                    bias: wgpu::DepthBiasState {
+                        constant: 2,
+                        slope_scale: 2.0,
+                        clamp: 0.0,
+                    },
+                })
+            }
         }
     }
 }
@@ -77,6 +97,8 @@ pub struct RenderPipelineFactory<'a> {
 
     cull_mode: Option<wgpu::Face>,
 
+    depth_only: bool,
+
     label: Option<&'static str>,
 }
 
@@ -97,6 +119,7 @@ impl<'a> RenderPipelineFactory<'a> {
             topology: PrimitiveTopology::TriangleList,
 
             cull_mode: None,
+            depth_only: false,
             label: Some("Pipeline from helper"),
         }
     }
@@ -160,6 +183,16 @@ impl<'a> RenderPipelineFactory<'a> {
         self.frag_shader_entry = name;
     }
 
+    /// Builds the pipeline without a color target, e.g. for shadow maps. This clears the fragment
+    /// entry, set one afterwards with [`RenderPipelineFactory::set_frag_entry`] to keep a
+    /// fragment stage, e.g. to discard masked fragments.
+    pub fn set_depth_only(&mut self, depth_only: bool) {
+        self.depth_only = depth_only;
+        if depth_only {
+            self.frag_shader_entry = None;
+        }
+    }
+
     pub fn set_topology(&mut self, value: PrimitiveTopology) {
         self.topology = value;
     }
@@ -169,15 +202,79 @@ impl<'a> RenderPipelineFactory<'a> {
         state: &State,
         shader_module: &ShaderModule,
         bind_group_layout: &[Option<&wgpu::BindGroupLayout>],
+    ) -> wgpu::RenderPipeline {
+        self.build(
+            state,
+            shader_module,
+            bind_group_layout,
+            state.pipeline_cache.wgpu_cache(),
+        )
+    }
+
+    /// Like [`RenderPipelineFactory::create_render_pipeline`], but compiles `shader_source` and
+    /// builds the pipeline only the first time this configuration is requested from
+    /// [`State::pipeline_cache`]. Later calls share the same pipeline.
+    ///
+    /// Layouts are compared by identity, use [`crate::factories::BindGroupFactory::build_cached`]
+    /// so equal layouts are the same object.
+    pub fn create_cached_render_pipeline(
+        &self,
+        state: &State,
+        shader_label: &str,
+        shader_source: &str,
+        bind_group_layout: &[Option<&wgpu::BindGroupLayout>],
+    ) -> Arc<wgpu::RenderPipeline> {
+        let cache = &state.pipeline_cache;
+        let (shader, shader_module) =
+            cache.shader_module(&state.device, shader_label, shader_source);
+
+        let key = PipelineKey {
+            shader,
+            bind_group_layouts: bind_group_layout
+                .iter()
+                .map(|layout| layout.cloned())
+                .collect(),
+            vertex_buffer_layouts: self
+                .vertex_buffer_layouts
+                .iter()
+                .map(VertexBufferKey::from)
+                .collect(),
+            depth_stencil: self.depth_config.get(),
+            blend: self.blend_config.get(),
+            vert_entry: self.vert_shader_entry.map(str::to_string),
+            frag_entry: self.frag_shader_entry.map(str::to_string),
+            color_target_format: self.color_target_format(state),
+            sample_count: self.sample_count(state),
+            topology: self.topology,
+            cull_mode: self.cull_mode,
+            depth_only: self.depth_only,
+        };
+
+        cache.render_pipeline(key, |wgpu_cache| {
+            self.build(state, &shader_module, bind_group_layout, wgpu_cache)
+        })
+    }
+
+    fn sample_count(&self, state: &State) -> u32 {
+        self.sample_count.unwrap_or_else(|| state.get_sample_count())
+    }
+
+    fn color_target_format(&self, state: &State) -> TextureFormat {
+        self.color_target_format.unwrap_or(state.scene_format)
+    }
+
+    fn build(
+        &self,
+        state: &State,
+        shader_module: &ShaderModule,
+        bind_group_layout: &[Option<&wgpu::BindGroupLayout>],
+        cache: Option<&wgpu::PipelineCache>,
     ) -> wgpu::RenderPipeline {
         let depth_config = self.depth_config.get();
 
         let blend_config = self.blend_config.get();
 
-        let sample_count = match &self.sample_count {
-            Some(val) => *val,
-            None => state.get_sample_count(),
-        };
+        let sample_count = self.sample_count(state);
 
         let pipeline_layout_desc = wgpu::PipelineLayoutDescriptor {
             label: Some("PipelineLayout"),
@@ -194,32 +291,31 @@ impl<'a> RenderPipelineFactory<'a> {
             compilation_options: PipelineCompilationOptions::default(),
         };
 
-        let color_target_format = match self.color_target_format {
-            Some(format) => format,
-            None => state
-                .window_surface
-                .get_capabilities(&state.adapter)
-                .formats[0]
-                .clone(),
-        };
+        let color_target_format = self.color_target_format(state);
+
+        let color_targets = [Some(wgpu::ColorTargetState {
+            format: color_target_format,
+            blend: blend_config,
+            write_mask: wgpu::ColorWrites::ALL,
+        })];
 
         let frag_state = wgpu::FragmentState {
             module: shader_module,
             entry_point: self.frag_shader_entry,
             compilation_options : PipelineCompilationOptions::default(),
 
-            targets: &[Some(wgpu::ColorTargetState {
-                format: color_target_format,
-                blend: blend_config,
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+            targets: if self.depth_only { &[] } else { &color_targets },
         };
 
         let r_pipeline: wgpu::RenderPipelineDescriptor<'_> = wgpu::RenderPipelineDescriptor {
             label: self.label,
             layout: Some(&pipeline_layout),
             vertex: vertex_state,
-            fragment: Some(frag_state),
+            fragment: if self.depth_only && self.frag_shader_entry.is_none() {
+                None
+            } else {
+                Some(frag_state)
+            },
             primitive: wgpu::PrimitiveState {
                 cull_mode: self.cull_mode,
                 topology: self.topology,
@@ -230,7 +326,7 @@ impl<'a> RenderPipelineFactory<'a> {
                 count: sample_count,
                 ..Default::default()
             },
-            cache : None,
+            cache,
             multiview_mask : None,
         };
 
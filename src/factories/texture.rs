@@ -263,6 +263,16 @@ impl DepthTextureFactory {
 
         let depth_format: wgpu::TextureFormat = Self::get_default_depth_format();
 
+        // Multisampled depth can't be read through the sampler below, and on GL the extra
+        // usages turn the attachment into a texture that breaks MSAA resolves.
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+        };
+
         let desc = wgpu::TextureDescriptor {
             label: Some(label),
             size,
@@ -271,9 +281,7 @@ impl DepthTextureFactory {
             sample_count: sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: depth_format,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST,
+            usage,
         };
         let texture = device.create_texture(&desc);
 
@@ -297,7 +305,56 @@ impl DepthTextureFactory {
     }
 
 
+    /// Square depth texture array with one layer per shadow cascade. The bundle's view covers
+    /// every layer and its sampler does depth comparisons.
+    pub fn new_shadow_map(
+        device: &wgpu::Device,
+        size: u32,
+        layers: u32,
+        label: &str,
+    ) -> TextureBundle {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::get_shadow_map_format(),
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        TextureBundle {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
     pub fn get_default_depth_format() -> wgpu::TextureFormat {
         wgpu::TextureFormat::Depth24Plus
-    }  
+    }
+
+    pub fn get_shadow_map_format() -> wgpu::TextureFormat {
+        wgpu::TextureFormat::Depth32Float
+    }
 }
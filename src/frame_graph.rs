@@ -0,0 +1,417 @@
+//! A small render graph on top of [`RenderPassFactory`].
+//!
+//! Passes declare the attachments they write and the textures they sample, and are recorded in
+//! the order they were added. Transient textures only live for one frame: they are taken from a
+//! [`TexturePool`], and once the last pass using one is done the texture can back another
+//! transient with the same size, format and sample count.
+
+use crate::factories::texture::{SamplerOptions, Texture2dOptions, TextureBundle};
+use crate::factories::{RenderPassFactory, Texture2dFactory};
+use crate::state::{Size, State};
+
+/// Handle to a texture of a [`FrameGraph`], only valid for the graph that created it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransientTextureOptions {
+    pub label: Option<&'static str>,
+    pub size: Size,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+impl TransientTextureOptions {
+    fn is_compatible(&self, other: &TransientTextureOptions) -> bool {
+        self.size == other.size
+            && self.format == other.format
+            && self.sample_count == other.sample_count
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ColorAttachment {
+    pub target: ResourceId,
+    pub resolve_target: Option<ResourceId>,
+    pub ops: wgpu::Operations<wgpu::Color>,
+}
+
+impl ColorAttachment {
+    pub fn new(target: ResourceId, load: wgpu::LoadOp<wgpu::Color>) -> Self {
+        Self {
+            target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DepthAttachment {
+    pub target: ResourceId,
+    pub ops: wgpu::Operations<f32>,
+}
+
+impl DepthAttachment {
+    pub fn new(target: ResourceId, load: wgpu::LoadOp<f32>) -> Self {
+        Self {
+            target,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PassOptions {
+    pub label: Option<&'static str>,
+    pub color_attachments: Vec<ColorAttachment>,
+    pub depth_attachment: Option<DepthAttachment>,
+    /// Textures the pass samples, they have to be written by an earlier pass.
+    pub reads: Vec<ResourceId>,
+}
+
+impl PassOptions {
+    fn writes(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.color_attachments
+            .iter()
+            .flat_map(|attachment| std::iter::once(attachment.target).chain(attachment.resolve_target))
+            .chain(self.depth_attachment.map(|attachment| attachment.target))
+    }
+}
+
+/// The attachments of the frame [`State::render`] is drawing.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTargets {
+    /// The surface texture, or the offscreen texture of a headless state.
+    pub backbuffer: ResourceId,
+    /// `None` when the sample count is 1.
+    pub multisampled: Option<ResourceId>,
+    pub depth: Option<ResourceId>,
+}
+
+impl FrameTargets {
+    /// Draws into the multisampled target and resolves into the backbuffer when MSAA is on.
+    pub fn color_attachment(&self, load: wgpu::LoadOp<wgpu::Color>) -> ColorAttachment {
+        match self.multisampled {
+            Some(multisampled) => ColorAttachment {
+                resolve_target: Some(self.backbuffer),
+                ..ColorAttachment::new(multisampled, load)
+            },
+            None => ColorAttachment::new(self.backbuffer, load),
+        }
+    }
+
+    /// Clears the state's depth texture, its content is discarded at the end of the pass.
+    pub fn depth_attachment(&self) -> Option<DepthAttachment> {
+        self.depth.map(|target| DepthAttachment {
+            target,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Discard,
+            },
+        })
+    }
+}
+
+enum Resource<'a> {
+    Imported(&'a wgpu::TextureView),
+    Transient(TransientTextureOptions),
+}
+
+type PassCallback<'a> = Box<dyn FnOnce(&FrameResources, wgpu::RenderPass<'a>) + 'a>;
+
+struct Pass<'a> {
+    options: PassOptions,
+    callback: PassCallback<'a>,
+}
+
+pub struct FrameGraph<'a> {
+    resources: Vec<Resource<'a>>,
+    passes: Vec<Pass<'a>>,
+    targets: FrameTargets,
+}
+
+impl<'a> FrameGraph<'a> {
+    /// Starts a graph for the frame [`State::render`] is drawing, `view` and `multisampled_view`
+    /// come from its [`crate::state::PerFrameData`].
+    pub fn new(
+        state: &'a State,
+        view: &'a wgpu::TextureView,
+        multisampled_view: Option<&'a wgpu::TextureView>,
+    ) -> Self {
+        let mut graph = Self {
+            resources: Vec::new(),
+            passes: Vec::new(),
+            targets: FrameTargets {
+                backbuffer: ResourceId(0),
+                multisampled: None,
+                depth: None,
+            },
+        };
+
+        graph.targets = FrameTargets {
+            backbuffer: graph.import_view(view),
+            multisampled: multisampled_view.map(|view| graph.import_view(view)),
+            depth: state
+                .depth_texture
+                .as_ref()
+                .map(|depth| graph.import_view(&depth.view)),
+        };
+
+        graph
+    }
+
+    pub fn targets(&self) -> FrameTargets {
+        self.targets
+    }
+
+    /// Makes a texture that outlives the frame usable by the passes, e.g. a shadow map.
+    pub fn import_view(&mut self, view: &'a wgpu::TextureView) -> ResourceId {
+        self.resources.push(Resource::Imported(view));
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Declares a texture that only lives for this frame, it is allocated from the pool when
+    /// the graph is executed.
+    pub fn create_texture(&mut self, options: TransientTextureOptions) -> ResourceId {
+        self.resources.push(Resource::Transient(options));
+        ResourceId(self.resources.len() - 1)
+    }
+
+    pub fn add_pass<F>(&mut self, options: PassOptions, callback: F)
+    where
+        F: FnOnce(&FrameResources, wgpu::RenderPass<'a>) + 'a,
+    {
+        self.passes.push(Pass {
+            options,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Allocates the transient textures and records every pass into `encoder`.
+    ///
+    /// # Panics
+    ///
+    /// When a pass reads a transient texture no earlier pass wrote.
+    pub fn execute(
+        self,
+        state: &State,
+        encoder: &mut wgpu::CommandEncoder,
+        pool: &mut TexturePool,
+    ) {
+        let FrameGraph {
+            resources, passes, ..
+        } = self;
+
+        let mut written = vec![false; resources.len()];
+        let mut first_use: Vec<Option<usize>> = vec![None; resources.len()];
+        let mut last_use = vec![0; resources.len()];
+
+        for (index, pass) in passes.iter().enumerate() {
+            for id in &pass.options.reads {
+                if matches!(resources[id.0], Resource::Transient(_)) && !written[id.0] {
+                    panic!(
+                        "pass {:?} reads a transient texture no earlier pass writes",
+                        pass.options.label.unwrap_or("unnamed")
+                    );
+                }
+            }
+
+            for id in pass.options.reads.iter().copied().chain(pass.options.writes()) {
+                first_use[id.0].get_or_insert(index);
+                last_use[id.0] = index;
+            }
+
+            for id in pass.options.writes() {
+                written[id.0] = true;
+            }
+        }
+
+        pool.begin_frame();
+
+        let mut slots: Vec<Option<usize>> = vec![None; resources.len()];
+        for index in 0..passes.len() {
+            for (id, resource) in resources.iter().enumerate() {
+                if let (Resource::Transient(options), Some(first)) = (resource, first_use[id]) {
+                    if first == index {
+                        slots[id] = Some(pool.acquire(state, options));
+                    }
+                }
+            }
+
+            for (id, slot) in slots.iter().enumerate() {
+                if let Some(slot) = slot {
+                    if last_use[id] == index {
+                        pool.release(*slot);
+                    }
+                }
+            }
+        }
+
+        let frame_resources = FrameResources {
+            entries: resources
+                .iter()
+                .zip(&slots)
+                .map(|(resource, slot)| match (resource, slot) {
+                    (Resource::Imported(view), _) => FrameResource::View(view),
+                    (Resource::Transient(_), Some(slot)) => {
+                        FrameResource::Texture(&pool.textures[*slot].bundle)
+                    }
+                    (Resource::Transient(_), None) => FrameResource::Unused,
+                })
+                .collect(),
+        };
+
+        for Pass { options, callback } in passes {
+            let mut render_pass_factory = RenderPassFactory::new();
+            for attachment in &options.color_attachments {
+                render_pass_factory.add_color_attachment_with_ops(
+                    frame_resources.view(attachment.target),
+                    attachment
+                        .resolve_target
+                        .map(|target| frame_resources.view(target)),
+                    attachment.ops,
+                );
+            }
+
+            if let Some(attachment) = &options.depth_attachment {
+                render_pass_factory
+                    .add_depth_stencil_with_ops(frame_resources.view(attachment.target), attachment.ops);
+            }
+
+            let render_pass = render_pass_factory
+                .begin_render_pass(encoder, options.label)
+                .forget_lifetime();
+
+            callback(&frame_resources, render_pass);
+        }
+    }
+}
+
+enum FrameResource<'r> {
+    View(&'r wgpu::TextureView),
+    Texture(&'r TextureBundle),
+    Unused,
+}
+
+/// Views of the graph's textures, handed to every pass while the graph executes.
+pub struct FrameResources<'r> {
+    entries: Vec<FrameResource<'r>>,
+}
+
+impl<'r> FrameResources<'r> {
+    /// # Panics
+    ///
+    /// When `id` is a transient texture no pass uses.
+    pub fn view(&self, id: ResourceId) -> &'r wgpu::TextureView {
+        match self.entries[id.0] {
+            FrameResource::View(view) => view,
+            FrameResource::Texture(texture) => &texture.view,
+            FrameResource::Unused => panic!("transient texture {:?} is not used by any pass", id),
+        }
+    }
+
+    /// The pooled texture backing a transient, with a linear clamping sampler. `None` for
+    /// imported views.
+    pub fn texture(&self, id: ResourceId) -> Option<&'r TextureBundle> {
+        match self.entries[id.0] {
+            FrameResource::Texture(texture) => Some(texture),
+            _ => None,
+        }
+    }
+}
+
+struct PooledTexture {
+    options: TransientTextureOptions,
+    bundle: TextureBundle,
+    in_use: bool,
+    used_this_frame: bool,
+}
+
+/// Textures backing the transients of [`FrameGraph`]s, kept between frames.
+///
+/// Textures no pass used during the previous frame are dropped, so resizing the window doesn't
+/// leave the old sizes around.
+#[derive(Default)]
+pub struct TexturePool {
+    textures: Vec<PooledTexture>,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn texture_count(&self) -> usize {
+        self.textures.len()
+    }
+
+    fn begin_frame(&mut self) {
+        self.textures.retain(|texture| texture.used_this_frame);
+        for texture in &mut self.textures {
+            texture.in_use = false;
+            texture.used_this_frame = false;
+        }
+    }
+
+    fn acquire(&mut self, state: &State, options: &TransientTextureOptions) -> usize {
+        let free = self
+            .textures
+            .iter()
+            .position(|texture| !texture.in_use && texture.options.is_compatible(options));
+
+        let index = match free {
+            Some(index) => index,
+            None => {
+                self.textures.push(PooledTexture {
+                    options: *options,
+                    bundle: Self::create_texture(state, options),
+                    in_use: false,
+                    used_this_frame: false,
+                });
+                self.textures.len() - 1
+            }
+        };
+
+        let texture = &mut self.textures[index];
+        texture.in_use = true;
+        texture.used_this_frame = true;
+        index
+    }
+
+    fn release(&mut self, index: usize) {
+        self.textures[index].in_use = false;
+    }
+
+    fn create_texture(state: &State, options: &TransientTextureOptions) -> TextureBundle {
+        // Same as the state's targets, on GL a multisampled attachment with extra usages breaks
+        // the resolve.
+        let usage = if options.sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+        };
+
+        Texture2dFactory::new_with_options(
+            state,
+            [options.size.width, options.size.height],
+            Texture2dOptions {
+                sample_count: options.sample_count,
+                format: options.format,
+                usage,
+                label: options.label,
+                ..Default::default()
+            },
+            SamplerOptions::default(),
+            &[],
+        )
+    }
+}
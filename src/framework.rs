@@ -12,8 +12,9 @@ use winit::{
 };
 
 use crate::{
-    factories::{DepthTextureFactory, render_pass::RenderPassFactory},
-    state::{PerFrameData, Size, State},
+    frame_graph::{ColorAttachment, FrameGraph, PassOptions, TexturePool},
+    state::{PerFrameData, Size, State, StateOptions},
+    Error,
 };
 
 pub trait Application: 'static + Sized {
@@ -36,11 +37,12 @@ pub trait Application: 'static + Sized {
         }
     }
 
+    /// Return [`crate::pipelines::pbr::required_limits`] to use the PBR pipelines or the sky.
     fn required_limits() -> wgpu::Limits {
         wgpu::Limits::downlevel_webgl2_defaults() // These downlevel limits will allow the code to run on all possible hardware
     }
 
-    fn init(state: &State) -> Self;
+    fn init(state: &State) -> Result<Self, Error>;
 
     fn resize(
         &mut self,
@@ -50,6 +52,11 @@ pub trait Application: 'static + Sized {
     ) {
     }
 
+    /// Called after [`State::set_sample_count`] changed the sample count, pipelines created with
+    /// the previous count have to be recreated here, the library's with their
+    /// `recreate_pipelines`.
+    fn on_sample_count_changed(&mut self, _state: &State) {}
+
     fn on_gui(&mut self, _egui_ctx: &mut EguiLayer) {}
 
     fn event(&mut self, _state: &State, _event: &winit::event::WindowEvent) {}
@@ -57,10 +64,30 @@ pub trait Application: 'static + Sized {
     fn update(&mut self, state: &mut State, frame_count: u64, delta_time: f64);
 
     fn render<'rpass>(&'rpass self, state: &State, render_pass: &mut wgpu::RenderPass<'rpass>);
+
+    /// Adds the passes of a frame to `graph`, egui is composited on top of the backbuffer after
+    /// all of them.
+    ///
+    /// The default is a single pass that clears to [`Application::clear_color`] and draws
+    /// [`Application::render`] into the window.
+    fn build_frame_graph<'a>(&'a self, state: &'a State, graph: &mut FrameGraph<'a>) {
+        let targets = graph.targets();
+        graph.add_pass(
+            PassOptions {
+                label: Some("Main Pass"),
+                color_attachments: vec![
+                    targets.color_attachment(wgpu::LoadOp::Clear(self.clear_color()))
+                ],
+                depth_attachment: targets.depth_attachment(),
+                ..Default::default()
+            },
+            move |_, mut render_pass| self.render(state, &mut render_pass),
+        );
+    }
 }
 
 pub trait UILayer {
-    fn setup(window: &Window, device: &wgpu::Device) -> Self
+    fn setup(window: &Window, state: &State) -> Self
     where
         Self: Sized;
     fn event(&mut self, _window: &Window, _event: &WindowEvent) -> bool {
@@ -90,7 +117,7 @@ pub struct EguiLayer {
 }
 
 impl UILayer for EguiLayer {
-    fn setup(window: &Window, device: &wgpu::Device) -> Self {
+    fn setup(window: &Window, state: &State) -> Self {
         let ctx = egui::Context::default();
         ctx.set_fonts(FontDefinitions::default());
 
@@ -106,11 +133,12 @@ impl UILayer for EguiLayer {
         );
 
         let renderer = egui_wgpu::Renderer::new(
-            device,
-            wgpu::TextureFormat::Bgra8UnormSrgb,
+            &state.device,
+            state.config.format,
             egui_wgpu::RendererOptions {
-                msaa_samples: 4,
-                depth_stencil_format: Some( DepthTextureFactory::get_default_depth_format() ),
+                // egui gets its own pass on top of the resolved backbuffer.
+                msaa_samples: 1,
+                depth_stencil_format: None,
                 dithering: true,
                 predictable_texture_filtering: false,
             },
@@ -182,6 +210,31 @@ impl UILayer for EguiLayer {
     }
 }
 
+/// Window and device settings for [`run_with_config`].
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub title: String,
+    pub size: PhysicalSize<u32>,
+    /// Backends wgpu may pick an adapter from, the `WGPU_BACKEND` environment variable overrides this.
+    pub backends: wgpu::Backends,
+    /// Sample count, vsync, surface format, transparency, frame latency and power preference.
+    ///
+    /// The required and optional features, limits and downlevel capabilities are taken from the
+    /// [`Application`] instead.
+    pub state: StateOptions,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "pira-wgpu".to_string(),
+            size: PhysicalSize::new(1280, 720),
+            backends: wgpu::Backends::all(),
+            state: StateOptions::default(),
+        }
+    }
+}
+
 struct AppHandler<E: Application> {
     window: Option<Arc<Window>>,
     state: Option<State>,
@@ -189,13 +242,14 @@ struct AppHandler<E: Application> {
     ui: Option<EguiLayer>,
     last_frame_inst: Instant,
     frame_count: u64,
-    title: String,
-    size: PhysicalSize<u32>,
-    sample_count: u32,
+    config: AppConfig,
+    texture_pool: TexturePool,
+    /// Set when startup failed, returned from [`try_run_with_config`].
+    error: Option<Error>,
 }
 
 impl<E: Application> AppHandler<E> {
-    fn new(title: String, size: PhysicalSize<u32>, sample_count: u32) -> Self {
+    fn new(config: AppConfig) -> Self {
         Self {
             window: None,
             state: None,
@@ -203,69 +257,65 @@ impl<E: Application> AppHandler<E> {
             ui: None,
             last_frame_inst: Instant::now(),
             frame_count: 0,
-            title,
-            size,
-            sample_count,
+            config,
+            texture_pool: TexturePool::new(),
+            error: None,
         }
     }
-}
 
-impl<E: Application> ApplicationHandler for AppHandler<E> {
-    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-        if self.window.is_some() {
+    fn resize(state: &mut State, application: &mut E, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        if state.config.width == size.width && state.config.height == size.height {
             return;
         }
 
+        state.resize(size);
+        application.resize(&state.config, &state.device, &state.queue);
+    }
+
+    fn init(&mut self, event_loop: &ActiveEventLoop) -> Result<(), Error> {
         let window_attributes = Window::default_attributes()
-            .with_title(&self.title)
-            .with_inner_size(self.size);
+            .with_title(&self.config.title)
+            .with_inner_size(self.config.size)
+            .with_transparent(self.config.state.transparent);
 
-        let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        let window = Arc::new(event_loop.create_window(window_attributes)?);
 
         println!("Window scale factor: {}", window.scale_factor());
 
         let size = window.inner_size();
-        let instance = wgpu::Instance::default();
-        let window_surface = instance.create_surface(window.clone()).unwrap();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::from_env().unwrap_or(self.config.backends),
+            ..wgpu::InstanceDescriptor::new_without_display_handle()
+        });
+        let window_surface = instance.create_surface(window.clone())?;
+
+        let options = StateOptions {
+            required_features: E::required_features(),
+            optional_features: E::optional_features(),
+            required_limits: E::required_limits(),
+            required_downlevel_capabilities: E::required_downlevel_capabilities(),
+            ..self.config.state.clone()
+        };
 
-        let state = pollster::block_on(State::new(
-            self.sample_count,
+        let state = pollster::block_on(State::try_new(
             instance,
             window_surface,
             Size::new(size.width, size.height),
-        ));
-
-        // let mut config = state
-        //     .window_surface
-        //     .get_default_config(&state.adapter, size.width, size.height)
-        //     .expect("Surface isn't supported by the adapter.");
-        
-        let caps = state.window_surface.get_capabilities(&state.adapter);
-
-        let mut config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-            format: *caps.formats.first().unwrap(),
-            width: size.width,
-            height: size.height,
-            desired_maximum_frame_latency: 2,
-            present_mode: *caps.present_modes.first().unwrap(),
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-            view_formats: vec![],
-        };
-        
-        let surface_view_format = config.format.add_srgb_suffix();
-        config.view_formats.push(surface_view_format);
-
-        state.window_surface.configure(&state.device, &config);
+            &options,
+        ))?;
 
         puffin::set_scopes_on(true);
 
         let application = {
             puffin::profile_scope!("Application init");
-            E::init(&state)
+            E::init(&state)?
         };
 
-        let ui = EguiLayer::setup(&window, &state.device);
+        let ui = EguiLayer::setup(&window, &state);
 
         puffin::GlobalProfiler::lock().new_frame();
 
@@ -273,6 +323,21 @@ impl<E: Application> ApplicationHandler for AppHandler<E> {
         self.state = Some(state);
         self.application = Some(application);
         self.ui = Some(ui);
+
+        Ok(())
+    }
+}
+
+impl<E: Application> ApplicationHandler for AppHandler<E> {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() || self.error.is_some() {
+            return;
+        }
+
+        if let Err(err) = self.init(event_loop) {
+            self.error = Some(err);
+            event_loop.exit();
+        }
     }
 
     fn window_event(
@@ -298,63 +363,76 @@ impl<E: Application> ApplicationHandler for AppHandler<E> {
         application.event(state, &event);
 
         match event {
+            WindowEvent::Resized(size) => {
+                Self::resize(state, application, size);
+            }
+            WindowEvent::ScaleFactorChanged { .. } => {
+                // The new physical size is reported through `Resized`, but not every platform
+                // sends one when only the scale factor changes.
+                Self::resize(state, application, window.inner_size());
+            }
             WindowEvent::RedrawRequested => {
+                let size = window.inner_size();
+                if size.width == 0 || size.height == 0 {
+                    // Minimised, there is nothing to present to.
+                    return;
+                }
+
                 let delta_time = Instant::now() - self.last_frame_inst;
                 self.last_frame_inst = Instant::now();
 
                 {
                     puffin::profile_scope!("update");
+                    let sample_count = state.get_sample_count();
                     application.update(state, self.frame_count, delta_time.as_secs_f64());
+
+                    if state.get_sample_count() != sample_count {
+                        application.on_sample_count_changed(state);
+                    }
                 }
                 self.frame_count += 1;
 
                 state.delta_time = delta_time.as_millis() as f32;
 
+                let texture_pool = &mut self.texture_pool;
                 state.render(|ctx, frame_data| {
                     puffin::profile_scope!("Render");
-                    let mut render_pass_factory = RenderPassFactory::new();
 
                     let PerFrameData {
                         encoder,
                         view,
                         multisampled_view,
                     } = frame_data;
-                    
 
-                    ui.begin_gui(); 
+                    ui.begin_gui();
 
                     application.on_gui(ui);
 
-                    ui.end_gui(&window, &state.device, &state.queue, encoder);
-
-                    {
-                        if state.sample_count > 1 {
-                            render_pass_factory.add_color_atachment(
-                                application.clear_color(),
-                                &multisampled_view,
-                                Some(&view),
-                            );
-                        } else {
-                            render_pass_factory
-                                .add_color_atachment(application.clear_color(), &view, None);
-                        }
-
-                        let mut render_pass =
-                            render_pass_factory.get_render_pass(ctx, encoder, true);
-
-                        application.render(&state, &mut render_pass);
-
-                        let screen_descriptor = ScreenDescriptor {
-                            size_in_pixels: [
-                                window.inner_size().width,
-                                window.inner_size().height,
-                            ],
-                            pixels_per_point: window.scale_factor() as f32,
-                        };
-                        ui.render(render_pass, &screen_descriptor)
-                    }
+                    ui.end_gui(window, &ctx.device, &ctx.queue, encoder);
+
+                    let mut graph = FrameGraph::new(ctx, view, multisampled_view.as_ref());
+                    application.build_frame_graph(ctx, &mut graph);
+
+                    let screen_descriptor = ScreenDescriptor {
+                        size_in_pixels: [window.inner_size().width, window.inner_size().height],
+                        pixels_per_point: window.scale_factor() as f32,
+                    };
+                    let ui: &EguiLayer = ui;
+                    graph.add_pass(
+                        PassOptions {
+                            label: Some("Egui Pass"),
+                            color_attachments: vec![ColorAttachment::new(
+                                graph.targets().backbuffer,
+                                wgpu::LoadOp::Load,
+                            )],
+                            ..Default::default()
+                        },
+                        move |_, render_pass| ui.render(render_pass, &screen_descriptor),
+                    );
+
+                    graph.execute(ctx, encoder, texture_pool);
                 });
-                
+
                 puffin::GlobalProfiler::lock().new_frame();                
                 window.request_redraw();
             }
@@ -380,11 +458,61 @@ impl<E: Application> ApplicationHandler for AppHandler<E> {
             window.request_redraw();
         }
     }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(state) = &self.state {
+            if let Err(err) = state.pipeline_cache.save() {
+                eprintln!("Failed to save the pipeline cache: {}", err);
+            }
+        }
+    }
 }
 
+/// Opens a window and runs `E` until it is closed.
+///
+/// # Panics
+///
+/// When the window, the device or the application can't be created, see [`try_run`].
 #[cfg(not(target_arch = "wasm32"))]
 pub fn run<E: Application>(title: &str, size: PhysicalSize<u32>, sample_count: u32) {
-    let event_loop = EventLoop::new().unwrap();
-    let mut app = AppHandler::<E>::new(title.to_string(), size, sample_count);
-    let _ = event_loop.run_app(&mut app);
+    if let Err(err) = try_run::<E>(title, size, sample_count) {
+        panic!("{}", err);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn try_run<E: Application>(
+    title: &str,
+    size: PhysicalSize<u32>,
+    sample_count: u32,
+) -> Result<(), Error> {
+    try_run_with_config::<E>(AppConfig {
+        title: title.to_string(),
+        size,
+        state: StateOptions {
+            sample_count,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+/// Same as [`run`], with full control over the window and device settings.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_with_config<E: Application>(config: AppConfig) {
+    if let Err(err) = try_run_with_config::<E>(config) {
+        panic!("{}", err);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn try_run_with_config<E: Application>(config: AppConfig) -> Result<(), Error> {
+    let event_loop = EventLoop::new()?;
+    let mut app = AppHandler::<E>::new(config);
+    event_loop.run_app(&mut app)?;
+
+    match app.error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
 }
\ No newline at end of file
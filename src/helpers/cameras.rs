@@ -94,6 +94,9 @@ impl OrbitControls {
         use winit::event;
 
         if let event::WindowEvent::Resized(size) = event {
+            if size.width == 0 || size.height == 0 {
+                return;
+            }
             self.camera.aspect_ratio = size.width as f32 / size.height as f32;
         }
 
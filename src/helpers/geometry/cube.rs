@@ -70,6 +70,17 @@ impl Cube {
         vertex_colors_from_normals_impl(&mut self.geometry);
     }
 
+    /// Adds tangents for normal mapping, generating the normals and UVs first if needed.
+    pub fn tangents(&mut self) {
+        if !self.geometry.attributes.contains_key(&attribute_names::NORMALS) {
+            self.normals();
+        }
+        if !self.geometry.attributes.contains_key(&attribute_names::UV) {
+            self.texture_coords();
+        }
+        self.geometry.generate_tangents();
+    }
+
 }
 
 impl GeometryFactory for Cube {
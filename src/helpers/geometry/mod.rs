@@ -12,6 +12,8 @@ pub mod attribute_names {
     pub const UV: AttributeIndex = 1;
     pub const COLOR: AttributeIndex = 2;
     pub const NORMALS: AttributeIndex = 3;
+    /// Four floats per vertex, see [`super::GeometryData::generate_tangents`].
+    pub const TANGENT: AttributeIndex = 4;
 }
 
 pub struct GeometryData {
@@ -28,6 +30,118 @@ impl GeometryData {
             topology: PrimitiveTopology::TriangleList,
         }
     }
+
+    /// Computes [`attribute_names::TANGENT`] from the positions, normals and UVs of a triangle list.
+    ///
+    /// Uses the conventions of MikkTSpace and glTF normal maps: `xyz` points along +u and is
+    /// orthogonal to the normal, `w` is the sign for `bitangent = w * cross(normal, tangent)`
+    /// with the bitangent along -v. It isn't the MikkTSpace algorithm though: the face tangents
+    /// around a vertex are averaged, weighted by the corner angle, and a vertex shared by faces
+    /// with mirrored UVs is split in two, so each side keeps its own tangent. Split vertices are
+    /// appended with a copy of every attribute and the indices are updated.
+    ///
+    /// # Panics
+    ///
+    /// When the positions, normals or UVs are missing, or the split vertices don't fit `u16`
+    /// indices anymore.
+    pub fn generate_tangents(&mut self) {
+        let positions = &self.attributes[&attribute_names::POSITION];
+        let normals = &self.attributes[&attribute_names::NORMALS];
+        let uvs = &self.attributes[&attribute_names::UV];
+
+        let vertex_count = positions.len() / 3;
+        let position = |i: usize| glam::Vec3::from_slice(&positions[i * 3..]);
+        let normal = |i: usize| glam::Vec3::from_slice(&normals[i * 3..]);
+        // Normal maps store +Y towards the top of the image, where v decreases.
+        let uv = |i: usize| glam::vec2(uvs[i * 2], -uvs[i * 2 + 1]);
+
+        let mut tangents = vec![glam::Vec3::ZERO; vertex_count];
+        let mut bitangents = vec![glam::Vec3::ZERO; vertex_count];
+        // Vertex each corner ends up on, and the vertices split off for mirrored faces.
+        let mut indices = self.indices.clone();
+        let mut handedness = vec![None; vertex_count];
+        let mut mirrored_copies: HashMap<usize, usize> = HashMap::new();
+        let mut copied_from = Vec::new();
+
+        for (triangle, new_triangle) in self
+            .indices
+            .chunks_exact(3)
+            .zip(indices.chunks_exact_mut(3))
+        {
+            let corners = [triangle[0], triangle[1], triangle[2]].map(usize::from);
+            let points = corners.map(position);
+            let [p0, p1, p2] = points;
+            let [uv0, uv1, uv2] = corners.map(uv);
+
+            let (edge1, edge2) = (p1 - p0, p2 - p0);
+            let (duv1, duv2) = (uv1 - uv0, uv2 - uv0);
+            let determinant = duv1.x * duv2.y - duv2.x * duv1.y;
+            if determinant.abs() < f32::EPSILON {
+                continue;
+            }
+
+            let tangent = ((edge1 * duv2.y - edge2 * duv1.y) / determinant).normalize_or_zero();
+            let bitangent = ((edge2 * duv1.x - edge1 * duv2.x) / determinant).normalize_or_zero();
+            let positive = determinant > 0.0;
+
+            for (corner, &index) in corners.iter().enumerate() {
+                let next = points[(corner + 1) % 3] - points[corner];
+                let previous = points[(corner + 2) % 3] - points[corner];
+                let angle = next.angle_between(previous);
+                if angle.is_nan() {
+                    continue;
+                }
+
+                // The first face decides the vertex's handedness, mirrored faces get a copy.
+                let target = if *handedness[index].get_or_insert(positive) == positive {
+                    index
+                } else {
+                    *mirrored_copies.entry(index).or_insert_with(|| {
+                        copied_from.push(index);
+                        tangents.push(glam::Vec3::ZERO);
+                        bitangents.push(glam::Vec3::ZERO);
+                        tangents.len() - 1
+                    })
+                };
+                new_triangle[corner] = u16::try_from(target).expect("too many vertices for u16");
+
+                tangents[target] += tangent * angle;
+                bitangents[target] += bitangent * angle;
+            }
+        }
+
+        let mut data = Vec::with_capacity(tangents.len() * 4);
+        for index in 0..tangents.len() {
+            let source = index
+                .checked_sub(vertex_count)
+                .map_or(index, |copy| copied_from[copy]);
+            let normal = normal(source).normalize_or_zero();
+
+            // Gram-Schmidt, vertices without a usable UV gradient get any perpendicular vector.
+            let tangent = tangents[index] - normal * normal.dot(tangents[index]);
+            let tangent = tangent
+                .try_normalize()
+                .unwrap_or_else(|| normal.any_orthonormal_vector());
+            let sign = if normal.cross(tangent).dot(bitangents[index]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            data.extend_from_slice(&[tangent.x, tangent.y, tangent.z, sign]);
+        }
+
+        if !copied_from.is_empty() {
+            for values in self.attributes.values_mut() {
+                let components = values.len() / vertex_count;
+                for &source in &copied_from {
+                    values.extend_from_within(source * components..(source + 1) * components);
+                }
+            }
+            self.indices = indices;
+        }
+        self.attributes.insert(attribute_names::TANGENT, data);
+    }
 }
 
 pub trait GeometryFactory {
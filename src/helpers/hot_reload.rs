@@ -0,0 +1,396 @@
+//! Shader hot reload for pipelines built from a [`ShaderSource`].
+//!
+//! Pipelines compile the WGSL embedded in the binary. With the opt-in `hot-reload` feature and in
+//! debug builds, a [`ShaderHotReload`] watches the files the sources were embedded from, and the
+//! files of the chunks they include, and rebuilds the pipelines when they change, keeping the last
+//! good pipeline when the new source doesn't compile.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "hot-reload")]
+use std::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(feature = "hot-reload")]
+use std::sync::mpsc;
+
+use crate::pipelines::preprocessor::{PreprocessError, ProcessedShader, ShaderPreprocessor};
+#[cfg(feature = "hot-reload")]
+use crate::state::State;
+
+/// Embeds a WGSL file next to the calling source file, like `include_str!`, and remembers its path
+/// so a [`ShaderHotReload`] can watch it.
+#[macro_export]
+macro_rules! include_shader {
+    ($file:literal) => {
+        $crate::helpers::hot_reload::ShaderSource::new(
+            $file,
+            include_str!($file),
+            $crate::helpers::hot_reload::source_path(env!("CARGO_MANIFEST_DIR"), file!(), $file),
+        )
+    };
+}
+
+#[doc(hidden)]
+pub fn source_path(manifest_dir: &str, caller: &str, file: &str) -> PathBuf {
+    let caller = Path::new(manifest_dir).join(caller);
+    caller.parent().unwrap_or(&caller).join(file)
+}
+
+/// WGSL source compiled into the binary, and the file it came from.
+///
+/// The source goes through a [`ShaderPreprocessor`] before compiling, so it can include the shared
+/// chunks and be specialized with defines.
+#[derive(Clone, Debug)]
+pub struct ShaderSource {
+    pub label: &'static str,
+    pub preprocessor: ShaderPreprocessor,
+    embedded: &'static str,
+    path: PathBuf,
+}
+
+impl ShaderSource {
+    /// Usually created with [`crate::include_shader`].
+    pub fn new(label: &'static str, embedded: &'static str, path: impl Into<PathBuf>) -> Self {
+        Self {
+            label,
+            preprocessor: ShaderPreprocessor::new(),
+            embedded,
+            path: path.into(),
+        }
+    }
+
+    /// Defines `name` for the preprocessor, e.g. to build a permutation of a shader.
+    pub fn define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.preprocessor.define(name, value);
+        self
+    }
+
+    pub fn preprocess(&self, source: &str) -> Result<ProcessedShader, PreprocessError> {
+        self.preprocessor.process(self.label, source)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file of the source, followed by the files of the chunks its embedded source includes,
+    /// see [`ShaderPreprocessor::add_chunk_file`].
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        self.paths_of(self.preprocess(self.embedded).ok().as_ref())
+    }
+
+    fn paths_of(&self, shader: Option<&ProcessedShader>) -> Vec<PathBuf> {
+        let chunks = shader
+            .into_iter()
+            .flat_map(ProcessedShader::included_chunks);
+        std::iter::once(self.path.clone())
+            .chain(
+                chunks.filter_map(|chunk| Some(self.preprocessor.chunk_path(chunk)?.to_path_buf())),
+            )
+            .collect()
+    }
+
+    /// Preprocesses the embedded source, e.g. for [`crate::factories::PipelineCache`].
+    ///
+    /// # Panics
+    ///
+    /// When the embedded source has a preprocessor error, like wgpu does for invalid WGSL.
+    pub fn processed(&self) -> ProcessedShader {
+        self.preprocess(self.embedded)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Compiles the embedded source, the files on disk are only read when reloading.
+    ///
+    /// # Panics
+    ///
+    /// When the embedded source has a preprocessor error, like wgpu does for invalid WGSL.
+    pub fn create_module(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        let shader = self.processed();
+
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(self.label),
+            source: wgpu::ShaderSource::Wgsl(shader.source.into()),
+        })
+    }
+
+    /// Reads and preprocesses the file with the chunks read from their files too, then validates
+    /// it with naga. The error messages quote the preprocessed source and name the file and line
+    /// it came from.
+    #[cfg(feature = "hot-reload")]
+    pub fn read_validated(&self) -> Result<ProcessedShader, ShaderReloadError> {
+        let text = std::fs::read_to_string(&self.path)?;
+        let mut preprocessor = self.preprocessor.clone();
+        preprocessor.read_chunk_files()?;
+        let shader = preprocessor.process(self.label, &text)?;
+        let source = &shader.source;
+        let path = self.path.to_string_lossy();
+
+        let module = naga::front::wgsl::parse_str(source).map_err(|err| {
+            let message = err.emit_to_string_with_path(source, path.as_ref());
+            ShaderReloadError::Parse(with_origin(message, &shader, err.location(source)))
+        })?;
+
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|err| {
+            let message = err.emit_to_string_with_path(source, path.as_ref());
+            ShaderReloadError::Validation(with_origin(message, &shader, err.location(source)))
+        })?;
+
+        Ok(shader)
+    }
+}
+
+/// Appends where the line naga points at was before preprocessing.
+#[cfg(feature = "hot-reload")]
+fn with_origin(
+    message: String,
+    shader: &ProcessedShader,
+    location: Option<naga::SourceLocation>,
+) -> String {
+    let origin = location.and_then(|location| shader.original_location(location.line_number));
+    match origin {
+        Some((file, line)) => format!("{}  = in {}:{}\n", message, file, line),
+        None => message,
+    }
+}
+
+/// Why a reloaded shader wasn't used, the previous pipeline stays in place.
+#[derive(Debug)]
+pub enum ShaderReloadError {
+    Io(std::io::Error),
+    Preprocess(PreprocessError),
+    /// WGSL syntax or type errors.
+    Parse(String),
+    /// The module parsed but isn't valid, e.g. uses an undeclared binding.
+    Validation(String),
+    /// wgpu rejected the module or the pipeline built from it, e.g. the bindings don't match
+    /// the layouts of the pipeline.
+    Pipeline(String),
+}
+
+impl fmt::Display for ShaderReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderReloadError::Io(err) => write!(f, "failed to read the shader: {}", err),
+            ShaderReloadError::Preprocess(err) => write!(f, "{}", err),
+            ShaderReloadError::Parse(message) => write!(f, "{}", message),
+            ShaderReloadError::Validation(message) => write!(f, "{}", message),
+            ShaderReloadError::Pipeline(message) => {
+                write!(f, "failed to create the pipeline: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderReloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderReloadError::Io(err) => Some(err),
+            ShaderReloadError::Preprocess(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ShaderReloadError {
+    fn from(err: std::io::Error) -> Self {
+        ShaderReloadError::Io(err)
+    }
+}
+
+impl From<PreprocessError> for ShaderReloadError {
+    fn from(err: PreprocessError) -> Self {
+        ShaderReloadError::Preprocess(err)
+    }
+}
+
+/// Watches the files of [`ShaderSource`]s and tells pipelines when to rebuild.
+///
+/// Call [`ShaderHotReload::poll`] once per frame, then let each pipeline rebuild through
+/// [`ShaderHotReload::reload`]. [`crate::pipelines::pbr::PbrPipeline`],
+/// [`crate::pipelines::sky::SkyRenderer`], [`crate::pipelines::post::PostProcessing`] and
+/// [`crate::pipelines::shadeless::ShadelessPipeline`] have a `reload_shaders` for it.
+/// Show the errors of shaders that failed to compile with [`ShaderHotReload::show_errors`].
+#[cfg(feature = "hot-reload")]
+pub struct ShaderHotReload {
+    watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    /// Directories being watched, editors often replace files instead of writing them.
+    watched_dirs: HashSet<PathBuf>,
+    /// Canonical paths of the file of each watched source path and of its chunks, see
+    /// [`ShaderSource::watched_paths`].
+    watched: HashMap<PathBuf, Vec<PathBuf>>,
+    changed: HashSet<PathBuf>,
+    errors: BTreeMap<PathBuf, ShaderReloadError>,
+}
+
+#[cfg(feature = "hot-reload")]
+impl ShaderHotReload {
+    /// `None` in release builds, or when the platform has no file watcher.
+    pub fn new() -> Option<Self> {
+        if !cfg!(debug_assertions) {
+            return None;
+        }
+
+        let (sender, events) = mpsc::channel();
+        let watcher = match notify::recommended_watcher(sender) {
+            Ok(watcher) => watcher,
+            Err(err) => {
+                eprintln!("Shader hot reload disabled: {}", err);
+                return None;
+            }
+        };
+
+        Some(Self {
+            watcher,
+            events,
+            watched_dirs: HashSet::new(),
+            watched: HashMap::new(),
+            changed: HashSet::new(),
+            errors: BTreeMap::new(),
+        })
+    }
+
+    /// Starts watching the file of `source` and of the chunks it includes,
+    /// [`ShaderHotReload::reload`] does it on first use.
+    pub fn watch(&mut self, source: &ShaderSource) {
+        if self.watched.contains_key(source.path()) {
+            return;
+        }
+        self.set_watched_paths(source, source.watched_paths());
+    }
+
+    fn set_watched_paths(&mut self, source: &ShaderSource, paths: Vec<PathBuf>) {
+        use notify::Watcher;
+
+        let paths: Vec<PathBuf> = paths.iter().map(|path| normalize(path)).collect();
+        for dir in paths.iter().filter_map(|path| path.parent()) {
+            if self.watched_dirs.insert(dir.to_path_buf()) {
+                if let Err(err) = self.watcher.watch(dir, notify::RecursiveMode::NonRecursive) {
+                    eprintln!("Can't watch {} for shader changes: {}", dir.display(), err);
+                }
+            }
+        }
+        self.watched.insert(source.path().to_path_buf(), paths);
+    }
+
+    /// Collects the shaders changed since the last poll, the rest of the frame sees the same set.
+    pub fn poll(&mut self) {
+        self.changed.clear();
+
+        for event in self.events.try_iter() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if !(event.kind.is_modify() || event.kind.is_create()) {
+                continue;
+            }
+
+            for path in event.paths {
+                let path = normalize(&path);
+                if self
+                    .watched
+                    .values()
+                    .flatten()
+                    .any(|watched| *watched == path)
+                {
+                    self.changed.insert(path);
+                }
+            }
+        }
+    }
+
+    pub fn has_changed(&self, source: &ShaderSource) -> bool {
+        self.watched
+            .get(source.path())
+            .is_some_and(|paths| paths.iter().any(|path| self.changed.contains(path)))
+    }
+
+    /// Rebuilds with `build` when the file of `source` changed since the last poll.
+    ///
+    /// Returns `None` when nothing changed or the new source fails to compile, then the error is
+    /// kept until the file compiles again.
+    pub fn reload<T>(
+        &mut self,
+        ctx: &State,
+        source: &ShaderSource,
+        build: impl FnOnce(&wgpu::ShaderModule) -> T,
+    ) -> Option<T> {
+        self.watch(source);
+        if !self.has_changed(source) {
+            return None;
+        }
+
+        let path = self.watched[source.path()][0].clone();
+        match Self::compile(ctx, source, build) {
+            Ok((result, shader)) => {
+                // The includes may have changed with the source.
+                self.set_watched_paths(source, source.paths_of(Some(&shader)));
+                self.errors.remove(&path);
+                Some(result)
+            }
+            Err(err) => {
+                eprintln!("Shader {} failed to reload:\n{}", path.display(), err);
+                self.errors.insert(path, err);
+                None
+            }
+        }
+    }
+
+    fn compile<T>(
+        ctx: &State,
+        source: &ShaderSource,
+        build: impl FnOnce(&wgpu::ShaderModule) -> T,
+    ) -> Result<(T, ProcessedShader), ShaderReloadError> {
+        let shader = source.read_validated()?;
+
+        // naga accepts more than the device may, catch what wgpu rejects instead of panicking.
+        let scope = ctx.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = ctx
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(source.label),
+                source: wgpu::ShaderSource::Wgsl(shader.source.as_str().into()),
+            });
+        let result = build(&module);
+
+        match pollster::block_on(scope.pop()) {
+            Some(err) => Err(ShaderReloadError::Pipeline(err.to_string())),
+            None => Ok((result, shader)),
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &ShaderReloadError)> {
+        self.errors.iter().map(|(path, err)| (path.as_path(), err))
+    }
+
+    /// Draws the errors of the shaders that failed to reload over the whole screen.
+    pub fn show_errors(&self, ctx: &egui::Context) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        egui::Area::new(egui::Id::new("shader_hot_reload_errors"))
+            .anchor(egui::Align2::LEFT_TOP, [8.0, 8.0])
+            .order(egui::Order::Foreground)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for (path, err) in self.errors() {
+                        ui.colored_label(egui::Color32::LIGHT_RED, path.display().to_string());
+                        ui.label(egui::RichText::new(err.to_string()).monospace());
+                    }
+                });
+            });
+    }
+}
+
+/// Canonical path when the file exists, so the watched paths and event paths compare equal.
+#[cfg(feature = "hot-reload")]
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
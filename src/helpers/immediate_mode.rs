@@ -4,7 +4,7 @@ use crate::wgpu;
 use crate::{
     factories::{self},
     pipelines::{self, shadeless },
-    state::State,
+    state::{Size, State},
 };
 
 use crate::glam;
@@ -82,14 +82,7 @@ impl DrawContext {
             last_uv: *glam::Vec2::ZERO.as_ref(),
 
             view_matrix: glam::Mat4::IDENTITY,
-            perspective_matrix: glam::Mat4::orthographic_lh(
-                0.0,
-                window_size.width_f32(),
-                window_size.height_f32(),
-                0.0,
-                -1.0,
-                1.0,
-            ),
+            perspective_matrix: Self::ortho_matrix(window_size),
 
             transform_matrices,
             last_transform_index: 0,
@@ -105,6 +98,24 @@ impl DrawContext {
         }
     }
 
+    fn ortho_matrix(size: Size) -> glam::Mat4 {
+        glam::Mat4::orthographic_lh(0.0, size.width_f32(), size.height_f32(), 0.0, -1.0, 1.0)
+    }
+
+    /// Rebuilds the pipelines for the current sample count after [`State::set_sample_count`].
+    pub fn recreate_pipelines(&mut self, state: &State) {
+        for pipeline in &mut self.pipelines {
+            pipeline.recreate_pipelines(state);
+        }
+    }
+
+    /// Updates the ortho projection so one unit keeps mapping to one pixel after a resize.
+    pub fn resize(&mut self, size: Size) {
+        if size.width > 0 && size.height > 0 {
+            self.perspective_matrix = Self::ortho_matrix(size);
+        }
+    }
+
     pub fn start(&mut self) {
         self.commands = Vec::new();
         self.vertices = Vec::new();
@@ -359,6 +370,11 @@ impl DrawContext {
     }
 
     pub fn draw<'rpass>(&'rpass self, state: &State, render_pass: &mut wgpu::RenderPass<'rpass>) {
+        assert_eq!(
+            self.pipelines[0].sample_count(),
+            state.get_sample_count(),
+            "the sample count changed, call DrawContext::recreate_pipelines first"
+        );
         let uniform_alignment = state.device.limits().min_uniform_buffer_offset_alignment as u32;
         
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
@@ -1,3 +1,4 @@
 pub mod cameras;
 pub mod geometry;
+pub mod hot_reload;
 pub mod immediate_mode;
@@ -7,10 +7,13 @@
 
 // // pub mod cameras;
 
+pub mod error;
 pub mod factories;
+pub mod frame_graph;
 pub mod framework;
 pub mod helpers;
 pub mod pipelines;
+pub mod readback;
 pub mod state;
 
 pub use glam;
@@ -20,4 +23,5 @@ pub use wgpu;
 pub use egui;
 pub use egui_extras;
 
+pub use error::Error;
 pub use helpers::immediate_mode;
@@ -1,6 +1,8 @@
 use wgpu::BufferAddress;
 
 pub mod pbr;
+pub mod post;
+pub mod preprocessor;
 pub mod shadeless;
 pub mod sky;
 
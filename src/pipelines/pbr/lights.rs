@@ -0,0 +1,339 @@
+use crate::helpers::cameras::PespectiveCamera;
+use crate::state::State;
+
+use super::shadows::{ShadowMaps, ShadowOptions, ShadowUniform};
+
+/// Lights the buffer has room for before it has to be reallocated.
+const MIN_CAPACITY: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Point,
+    /// Angles are in radians from the spot direction, the light fades out between them.
+    Spot {
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    Directional,
+}
+
+impl LightKind {
+    fn id(&self) -> u32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Spot { .. } => 1,
+            LightKind::Directional => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Ignored by directional lights.
+    pub position: glam::Vec3,
+    /// Direction the light travels in, ignored by point lights.
+    pub direction: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    /// Distance at which point and spot lights fade out completely, 0 is unlimited.
+    pub range: f32,
+}
+
+impl Light {
+    pub fn point(position: glam::Vec3) -> Self {
+        Self {
+            kind: LightKind::Point,
+            position,
+            ..Default::default()
+        }
+    }
+
+    pub fn spot(
+        position: glam::Vec3,
+        direction: glam::Vec3,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            },
+            position,
+            direction,
+            ..Default::default()
+        }
+    }
+
+    pub fn directional(direction: glam::Vec3) -> Self {
+        Self {
+            kind: LightKind::Directional,
+            direction,
+            ..Default::default()
+        }
+    }
+
+    /// The light as it is laid out in the storage buffer of a [`LightSet`].
+    pub fn to_gpu(self) -> GpuLight {
+        let (cos_inner, cos_outer) = match self.kind {
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => (inner_angle.cos(), outer_angle.cos()),
+            _ => (-1.0, -1.0),
+        };
+
+        GpuLight {
+            position: self.position.to_array(),
+            range: self.range,
+            direction: self.direction.normalize_or_zero().to_array(),
+            kind: self.kind.id(),
+            color: self.color.to_array(),
+            intensity: self.intensity,
+            cos_inner,
+            cos_outer,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Point,
+            position: glam::Vec3::ZERO,
+            direction: glam::Vec3::NEG_Y,
+            color: glam::Vec3::ONE,
+            intensity: 1.0,
+            range: 0.0,
+        }
+    }
+}
+
+/// A [`Light`] in the `Light` struct layout of `shader_pbr.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuLight {
+    pub position: [f32; 3],
+    pub range: f32,
+    /// Normalized, zero when the light has no direction.
+    pub direction: [f32; 3],
+    /// 0 for point, 1 for spot and 2 for directional lights.
+    pub kind: u32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Cosines of the spot angles, -1 for other kinds so every direction is inside the cone.
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    _pad: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsHeader {
+    count: u32,
+    _pad: [u32; 3],
+}
+
+/// Lights shared by every PBR draw, kept in a storage buffer, and the [`ShadowMaps`] of the
+/// first directional one. Both are bound at group 3 of a [`super::PbrPipeline`].
+///
+/// Edit `lights` and call [`LightSet::write`] to upload them, the buffer grows when needed up to
+/// [`LightSet::max_lights`].
+pub struct LightSet {
+    pub lights: Vec<Light>,
+    pub shadows: ShadowMaps,
+
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+
+    buffer: wgpu::Buffer,
+    capacity: usize,
+}
+
+impl LightSet {
+    pub fn new(state: &State, lights: Vec<Light>, shadow_options: ShadowOptions) -> Self {
+        let shadows = ShadowMaps::new(state, shadow_options);
+        let shadow_uniform_size = wgpu::BufferSize::new(std::mem::size_of::<ShadowUniform>() as _);
+
+        let bind_group_layout =
+            state
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Light set layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: wgpu::BufferSize::new(Self::buffer_size(1) as _),
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2Array,
+                                sample_type: wgpu::TextureSampleType::Depth,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: shadow_uniform_size,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+
+        let capacity = lights
+            .len()
+            .max(MIN_CAPACITY)
+            .min(Self::max_lights(&state.device.limits()));
+        let buffer = Self::create_buffer(state, capacity);
+        let bind_group = Self::create_bind_group(state, &bind_group_layout, &buffer, &shadows);
+
+        let light_set = Self {
+            lights,
+            shadows,
+            bind_group_layout,
+            bind_group,
+            buffer,
+            capacity,
+        };
+        light_set.upload(state);
+
+        light_set
+    }
+
+    /// The most lights a storage buffer binding can hold with `limits`.
+    pub fn max_lights(limits: &wgpu::Limits) -> usize {
+        let size = limits
+            .max_storage_buffer_binding_size
+            .min(limits.max_buffer_size);
+        (size as usize - std::mem::size_of::<LightsHeader>()) / std::mem::size_of::<GpuLight>()
+    }
+
+    /// Uploads `lights`, reallocating the buffer and bind group if they don't fit anymore. Lights
+    /// past [`LightSet::max_lights`] are left out.
+    pub fn write(&mut self, state: &State) {
+        let max_lights = Self::max_lights(&state.device.limits());
+        if self.lights.len() > self.capacity && self.capacity < max_lights {
+            self.capacity = self.lights.len().next_power_of_two().min(max_lights);
+            self.buffer = Self::create_buffer(state, self.capacity);
+            self.bind_group = Self::create_bind_group(
+                state,
+                &self.bind_group_layout,
+                &self.buffer,
+                &self.shadows,
+            );
+        }
+
+        self.upload(state);
+    }
+
+    /// The number of lights the shaders see after [`LightSet::write`].
+    pub fn uploaded_count(&self) -> usize {
+        self.lights.len().min(self.capacity)
+    }
+
+    /// Refits the shadow cascades of the first directional light to the camera, within
+    /// [`super::ShadowSettings::max_distance`]. The shadow map is allocated the first time a
+    /// directional light shows up.
+    ///
+    /// `view` is the camera's view matrix, e.g. [`crate::helpers::cameras::OrbitControls::get_view_matrix`]
+    /// when `camera` belongs to orbit controls.
+    pub fn update_shadows(&mut self, state: &State, camera: &PespectiveCamera, view: glam::Mat4) {
+        let lights = &self.lights[..self.uploaded_count()];
+        if self.shadows.update(state, camera, view, lights) {
+            self.bind_group = Self::create_bind_group(
+                state,
+                &self.bind_group_layout,
+                &self.buffer,
+                &self.shadows,
+            );
+        }
+    }
+
+    fn upload(&self, state: &State) {
+        let count = self.uploaded_count();
+        let header = LightsHeader {
+            count: count as u32,
+            _pad: [0; 3],
+        };
+        let lights: Vec<GpuLight> = self.lights[..count]
+            .iter()
+            .map(|light| light.to_gpu())
+            .collect();
+
+        state
+            .queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&header));
+        if !lights.is_empty() {
+            state.queue.write_buffer(
+                &self.buffer,
+                std::mem::size_of::<LightsHeader>() as u64,
+                bytemuck::cast_slice(&lights),
+            );
+        }
+    }
+
+    fn buffer_size(capacity: usize) -> usize {
+        std::mem::size_of::<LightsHeader>() + std::mem::size_of::<GpuLight>() * capacity
+    }
+
+    fn create_buffer(state: &State, capacity: usize) -> wgpu::Buffer {
+        state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light set"),
+            size: Self::buffer_size(capacity) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        state: &State,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        shadows: &ShadowMaps,
+    ) -> wgpu::BindGroup {
+        state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light set bind group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&shadows.shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&shadows.shadow_map.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: shadows.uniform_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+}
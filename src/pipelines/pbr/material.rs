@@ -0,0 +1,268 @@
+use crate::factories::render_pipeline::{BlendConfig, DepthConfig};
+use crate::factories::texture::TextureBundle;
+use crate::state::State;
+
+use super::PbrPipeline;
+
+/// How the alpha of the base color is used, as in glTF.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Fragments with an alpha below `cutoff` are discarded, the rest are opaque.
+    Mask { cutoff: f32 },
+    /// Blended over what is behind without writing depth. Draw these last, back to front.
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn blend_config(&self) -> BlendConfig {
+        match self {
+            AlphaMode::Opaque | AlphaMode::Mask { .. } => BlendConfig::None,
+            AlphaMode::Blend => BlendConfig::Default,
+        }
+    }
+
+    /// Blended surfaces are depth tested but don't hide what is drawn after them.
+    pub fn depth_config(&self) -> DepthConfig {
+        match self {
+            AlphaMode::Opaque | AlphaMode::Mask { .. } => DepthConfig::DefaultWrite,
+            AlphaMode::Blend => DepthConfig::DefaultDontWrite,
+        }
+    }
+
+    fn id(&self) -> u32 {
+        match self {
+            AlphaMode::Opaque => 0,
+            AlphaMode::Mask { .. } => 1,
+            AlphaMode::Blend => 2,
+        }
+    }
+}
+
+/// Scalar factors of a [`PbrMaterial`], multiplied with its textures in the shader.
+#[derive(Clone, Copy, Debug)]
+pub struct PbrMaterialFactors {
+    pub albedo: glam::Vec3,
+    /// Alpha of the base color, see [`AlphaMode`].
+    pub alpha: f32,
+    pub roughness: f32,
+    pub metallic: f32,
+    /// Scales the normal map's deviation from the surface normal, 0 ignores the map.
+    pub normal_strength: f32,
+    /// Added on top of the lighting, times the albedo.
+    pub ambient: glam::Vec3,
+    /// Linear color the surface emits, can go above 1 for bloom.
+    pub emissive: glam::Vec3,
+    /// How much the occlusion map darkens the ambient and image based lighting.
+    pub occlusion_strength: f32,
+    /// Strength of a clear dielectric layer on top of the surface, as in `KHR_materials_clearcoat`.
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    /// Retro-reflective tint of cloth like surfaces, as in `KHR_materials_sheen`. Black disables it.
+    pub sheen_color: glam::Vec3,
+    pub sheen_roughness: f32,
+}
+
+impl Default for PbrMaterialFactors {
+    fn default() -> Self {
+        Self {
+            albedo: glam::Vec3::ONE,
+            alpha: 1.0,
+            roughness: 1.0,
+            metallic: 1.0,
+            normal_strength: 1.0,
+            ambient: glam::Vec3::ONE * 0.005,
+            emissive: glam::Vec3::ZERO,
+            occlusion_strength: 1.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+            sheen_color: glam::Vec3::ZERO,
+            sheen_roughness: 0.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PbrMaterialUniform {
+    albedo: [f32; 3],
+    roughness: f32,
+    ambient: [f32; 3],
+    metallic: f32,
+    emissive: [f32; 3],
+    normal_strength: f32,
+    sheen_color: [f32; 3],
+    sheen_roughness: f32,
+    alpha: f32,
+    alpha_cutoff: f32,
+    alpha_mode: u32,
+    occlusion_strength: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    _pad: [f32; 2],
+}
+
+impl PbrMaterialUniform {
+    fn new(factors: &PbrMaterialFactors, alpha_mode: AlphaMode) -> Self {
+        let alpha_cutoff = match alpha_mode {
+            AlphaMode::Mask { cutoff } => cutoff,
+            _ => 0.0,
+        };
+
+        Self {
+            albedo: factors.albedo.to_array(),
+            roughness: factors.roughness,
+            ambient: factors.ambient.to_array(),
+            metallic: factors.metallic,
+            emissive: factors.emissive.to_array(),
+            normal_strength: factors.normal_strength,
+            sheen_color: factors.sheen_color.to_array(),
+            sheen_roughness: factors.sheen_roughness,
+            alpha: factors.alpha,
+            alpha_cutoff,
+            alpha_mode: alpha_mode.id(),
+            occlusion_strength: factors.occlusion_strength,
+            clearcoat: factors.clearcoat,
+            clearcoat_roughness: factors.clearcoat_roughness,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+/// Textures left as `None` fall back to the defaults of [`State`], so only the factors apply.
+#[derive(Default)]
+pub struct PbrMaterialOptions<'a> {
+    pub label: Option<&'a str>,
+    pub factors: PbrMaterialFactors,
+    pub alpha_mode: AlphaMode,
+    /// Color and alpha in an sRGB format.
+    pub albedo_texture: Option<&'a TextureBundle>,
+    /// Roughness in the red channel.
+    pub roughness_texture: Option<&'a TextureBundle>,
+    /// Metalness in the red channel.
+    pub metallic_texture: Option<&'a TextureBundle>,
+    /// Tangent space normals in a linear format.
+    pub normal_texture: Option<&'a TextureBundle>,
+    /// Emitted color in an sRGB format, times [`PbrMaterialFactors::emissive`].
+    pub emissive_texture: Option<&'a TextureBundle>,
+    /// Ambient occlusion in the red channel.
+    pub occlusion_texture: Option<&'a TextureBundle>,
+}
+
+/// Textures and factors of a surface, bound at group 2 of a [`PbrPipeline`].
+///
+/// Edit `factors` or `alpha_mode` and call [`PbrMaterial::write`] to upload them.
+pub struct PbrMaterial {
+    pub factors: PbrMaterialFactors,
+    /// Picks the pipeline in [`PbrPipeline::draw_mesh`].
+    pub alpha_mode: AlphaMode,
+    pub bind_group: wgpu::BindGroup,
+
+    uniform_buffer: wgpu::Buffer,
+}
+
+impl PbrMaterial {
+    pub fn new(state: &State, pipeline: &PbrPipeline, options: PbrMaterialOptions) -> Self {
+        let uniform_buffer = state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: options.label,
+            size: std::mem::size_of::<PbrMaterialUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let white = &state.default_white_texture_bundle;
+        let textures = [
+            options.albedo_texture.unwrap_or(white),
+            options.roughness_texture.unwrap_or(white),
+            options.metallic_texture.unwrap_or(white),
+            options
+                .normal_texture
+                .unwrap_or(&state.default_normal_texture_bundle),
+            options.emissive_texture.unwrap_or(white),
+            options.occlusion_texture.unwrap_or(white),
+        ];
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }];
+        for (index, texture) in textures.iter().enumerate() {
+            let binding = 1 + index as u32 * 2;
+            entries.push(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+
+        let bind_group = state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: options.label,
+            layout: &pipeline.material_layout,
+            entries: &entries,
+        });
+
+        let material = Self {
+            factors: options.factors,
+            alpha_mode: options.alpha_mode,
+            bind_group,
+            uniform_buffer,
+        };
+        material.write(state);
+
+        material
+    }
+
+    pub fn write(&self, state: &State) {
+        state.queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&PbrMaterialUniform::new(&self.factors, self.alpha_mode)),
+        );
+    }
+
+    /// Uniform followed by the albedo, roughness, metallic, normal, emissive and occlusion
+    /// textures, each with its sampler.
+    pub(super) fn create_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(
+                    std::mem::size_of::<PbrMaterialUniform>() as _
+                ),
+            },
+            count: None,
+        }];
+        for index in 0..6 {
+            let binding = 1 + index * 2;
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("PBR material layout"),
+            entries: &entries,
+        })
+    }
+}
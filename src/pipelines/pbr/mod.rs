@@ -1,4 +1,3 @@
-use crate::factories::texture::TextureBundle;
 use crate::helpers::geometry::{self, attribute_names, GeometryData};
 use crate::state::State;
 
@@ -8,38 +7,76 @@ use wgpu::PrimitiveTopology;
 
 use wgpu::util::DeviceExt;
 
-use super::sky::SkyRenderer;
-use super::{create_global_uniform, create_uniform_buffer, ModelUniform, ViewUniform};
+use std::cell::RefCell;
 
-#[repr(C, align(256))]
-#[derive(Clone, Copy)]
+use super::sky::{IrradianceSource, SkyRenderer, IRRADIANCE_SH_SIZE};
+#[cfg(feature = "hot-reload")]
+use crate::helpers::hot_reload::ShaderHotReload;
+use crate::helpers::hot_reload::ShaderSource;
+use super::{self as pipelines, create_global_uniform, create_uniform_buffer_stride, ViewUniform};
 
-pub struct PbrMaterialModelUniform {
-    pub model_matrix: glam::Mat4,
-    pub light_position: glam::Vec3,
-    pub light_intensity: f32,
+mod lights;
+mod material;
+mod shadows;
+
+pub use lights::{GpuLight, Light, LightKind, LightSet};
+pub use material::{AlphaMode, PbrMaterial, PbrMaterialFactors, PbrMaterialOptions};
+pub use shadows::{Cascade, ShadowMaps, ShadowOptions, ShadowSettings, MAX_CASCADES};
 
-    pub ambient: glam::Vec3,
-    pub roughness: f32,
+/// Limits of the PBR pipelines and the [`SkyRenderer`] they are lit by, the lights are read from a
+/// storage buffer. Return it from [`crate::framework::Application::required_limits`].
+pub fn required_limits() -> wgpu::Limits {
+    super::sky::required_limits()
+}
 
-    pub albedo: glam::Vec3,
-    pub metallic: f32,
+/// Per draw data at group 0, written at a multiple of its size for each mesh.
+#[repr(C, align(256))]
+#[derive(Clone, Copy)]
+pub struct PbrModelUniform {
+    pub model_matrix: glam::Mat4,
+    /// Cofactor matrix of the model matrix, the inverse transpose scaled by the absolute
+    /// determinant. Keeps normals perpendicular under non-uniform scale and stays finite for
+    /// singular or tiny scales, the shader normalizes the result.
+    pub normal_matrix: glam::Mat4,
 }
 
-impl PbrMaterialModelUniform {
+impl PbrModelUniform {
     pub fn new(mat: glam::Mat4) -> Self {
+        let linear = glam::Mat3::from_mat4(mat);
+        let (x, y, z) = (linear.x_axis, linear.y_axis, linear.z_axis);
+        let cofactor = glam::Mat3::from_cols(y.cross(z), z.cross(x), x.cross(y));
+        // Mirroring transforms have a negative determinant, which would turn the normals inward.
+        let normal_matrix = glam::Mat4::from_mat3(cofactor * linear.determinant().signum());
+
         Self {
             model_matrix: mat,
-            light_position: glam::Vec3::new(5.0, 5.0, 10.0),
-            light_intensity: 1.0,
-            ambient: glam::Vec3::ONE * 0.005,
-            albedo: glam::Vec3::ONE,
-            metallic: 1.0,
-            roughness: 1.0,
+            normal_matrix,
         }
     }
 }
 
+/// Sky dependent constants at the end of the environment group.
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    /// Last mip of the prefiltered specular map, sampled at roughness 1.
+    max_reflection_lod: f32,
+    /// 1 to evaluate the spherical harmonics instead of sampling the irradiance map.
+    irradiance_sh: u32,
+    _pad: [u32; 2],
+}
+
+/// Model uniforms of the objects drawn this frame, one per [`PbrPipeline::draw_mesh`] call.
+struct ModelSlots {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    capacity: usize,
+    used: usize,
+}
+
+/// Slots allocated up front, the buffer doubles when a frame needs more.
+const MIN_MODEL_SLOTS: usize = 64;
+
 #[repr(C, align(16))]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -47,48 +84,69 @@ pub struct Vertex {
     pub uv: [f32; 2],
     pub color: [f32; 4],
     pub normal: [f32; 3],
+    pub tangent: [f32; 4],
 }
 
+/// Draws meshes lit by a [`LightSet`] and the image based lighting of a [`SkyRenderer`].
+///
+/// Bind groups: 0 camera and [`PbrModelUniform`], 1 [`PbrPipeline::environment_bind_group`],
+/// 2 a [`PbrMaterial`] and 3 [`LightSet::bind_group`].
+///
+/// Every frame call [`PbrPipeline::begin_frame`] before recording any pass, then draw each object
+/// with [`PbrPipeline::draw_mesh`], and into the shadow passes with
+/// [`PbrPipeline::draw_shadow_mesh`].
+// The shader sources and options are only read back when reloading.
+#[cfg_attr(not(feature = "hot-reload"), allow(dead_code))]
 pub struct PbrPipeline {
     pub shader_module: wgpu::ShaderModule,
+    /// Draws [`AlphaMode::Opaque`] and [`AlphaMode::Mask`] materials.
     pub pipeline: wgpu::RenderPipeline,
-    // pub bind_group_layout: wgpu::BindGroupLayout,
-    pub bind_group: wgpu::BindGroup,
-
-    // pub texture_bind_group_layout: Option<wgpu::BindGroupLayout>,
-    pub texture_bind_group: Option<wgpu::BindGroup>,
+    /// Draws [`AlphaMode::Blend`] materials.
+    pub blend_pipeline: wgpu::RenderPipeline,
+    /// Depth only pipeline drawing the meshes into the cascades of [`ShadowMaps::add_passes`].
+    pub shadow_pipeline: wgpu::RenderPipeline,
+    /// Like [`PbrPipeline::shadow_pipeline`] for [`AlphaMode::Mask`] materials, which are bound
+    /// at group 2 so texels below the cutoff don't cast shadows.
+    pub masked_shadow_pipeline: wgpu::RenderPipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+
+    pub environment_layout: wgpu::BindGroupLayout,
+    /// Irradiance, prefiltered reflections and BRDF lookup table of the sky, followed by a
+    /// uniform with the mip count of the reflections, the sky's
+    /// [`params_buffer`](SkyRenderer::params_buffer) and its
+    /// [`irradiance_sh`](SkyRenderer::irradiance_sh).
+    pub environment_bind_group: wgpu::BindGroup,
+    /// Layout of [`PbrMaterial::bind_group`].
+    pub material_layout: wgpu::BindGroupLayout,
 
     pub global_uniform_buffer: Option<wgpu::Buffer>,
-    pub model_uniform_buffer: Option<wgpu::Buffer>,
+    // Grows while passes are recorded, which only hold `&self`.
+    models: RefCell<ModelSlots>,
+
+    // Kept to rebuild the pipelines when the shaders reload.
+    shader: ShaderSource,
+    shadow_shader: ShaderSource,
+    topology: PrimitiveTopology,
+    enable_depth: bool,
+    // The lit pipelines draw into the multisampled targets, the shadow pipeline doesn't.
+    sample_count: u32,
 }
 
 impl PbrPipeline {
-    pub fn new_with_texture(
+    pub fn new(
         ctx: &State,
-        // global_uniform_buffer: &wgpu::Buffer,
-        // model_uniform_buffer: &wgpu::Buffer,
-        // texture: (wgpu::ShaderStages, &wgpu::Sampler, &wgpu::TextureView),
-        texture: &TextureBundle,
-        albedo: &TextureBundle,
-        metallic: &TextureBundle,
-
         sky: &SkyRenderer,
-
+        lights: &LightSet,
         topology: PrimitiveTopology,
         enable_depth: bool,
     ) -> Self {
-        let shader_module = ctx
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("Shader"),
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader_pbr.wgsl").into()),
-            });
-
-        let attribs = wgpu::vertex_attr_array![ 0 => Float32x3, 1 => Float32x2, 2 => Float32x4 ,3 => Float32x3];
-        let stride = std::mem::size_of::<Vertex>() as u64;
+        let shader = crate::include_shader!("shader_pbr.wgsl");
+        let shadow_shader = crate::include_shader!("shader_shadow.wgsl");
+        let shader_module = shader.create_module(&ctx.device);
 
         let global_uniform_buffer = create_global_uniform(&ctx.device);
-        let model_uniform_buffer = create_uniform_buffer::<ModelUniform>(1, &ctx.device);
+        let model_uniform_buffer =
+            create_uniform_buffer_stride::<PbrModelUniform>(MIN_MODEL_SLOTS as u64, &ctx.device);
 
         let mut bind_factory = BindGroupFactory::new();
         bind_factory.add_uniform(
@@ -99,80 +157,397 @@ impl PbrPipeline {
         bind_factory.add_uniform(
             wgpu::ShaderStages::VERTEX_FRAGMENT,
             &model_uniform_buffer,
-            wgpu::BufferSize::new(std::mem::size_of::<PbrMaterialModelUniform>() as _),
+            wgpu::BufferSize::new(std::mem::size_of::<PbrModelUniform>() as _),
         );
         let (bind_group_layout, bind_group) = bind_factory.build(&ctx.device);
+        let models = RefCell::new(ModelSlots {
+            buffer: model_uniform_buffer,
+            bind_group,
+            capacity: MIN_MODEL_SLOTS,
+            used: 0,
+        });
 
-        let mut texture_bind_group_factory = BindGroupFactory::new();
-        texture_bind_group_factory.add_texture_and_sampler(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
-            &texture.view,
-            &texture.sampler,
+        let (environment_layout, environment_bind_group) = Self::build_environment(ctx, sky);
+        let material_layout = PbrMaterial::create_layout(&ctx.device);
+
+        let (pipeline, blend_pipeline) = Self::create_lit_pipelines(
+            ctx,
+            &shader_module,
+            &[
+                Some(&bind_group_layout),
+                Some(&environment_layout),
+                Some(&material_layout),
+                Some(&lights.bind_group_layout),
+            ],
+            topology,
+            enable_depth,
+        );
+        let (shadow_pipeline, masked_shadow_pipeline) = Self::create_shadow_pipelines(
+            ctx,
+            &shadow_shader.create_module(&ctx.device),
+            &[
+                Some(&bind_group_layout),
+                Some(&lights.shadows.cascade_layout),
+                Some(&material_layout),
+            ],
+            topology,
         );
 
-        texture_bind_group_factory.add_texture_and_sampler(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
-            &albedo.view,
-            &albedo.sampler,
+        Self {
+            pipeline,
+            blend_pipeline,
+            shadow_pipeline,
+            masked_shadow_pipeline,
+            shader_module,
+            bind_group_layout,
+
+            environment_layout,
+            environment_bind_group,
+            material_layout,
+
+            global_uniform_buffer: Some(global_uniform_buffer),
+            models,
+
+            shader,
+            shadow_shader,
+            topology,
+            enable_depth,
+            sample_count: ctx.get_sample_count(),
+        }
+    }
+
+    /// The opaque and the blend pipeline, both drawing with the lit shader.
+    fn create_lit_pipelines(
+        ctx: &State,
+        shader_module: &wgpu::ShaderModule,
+        layouts: &[Option<&wgpu::BindGroupLayout>; 4],
+        topology: PrimitiveTopology,
+        enable_depth: bool,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let attribs = Self::vertex_attributes();
+        let stride = std::mem::size_of::<Vertex>() as u64;
+
+        let create_pipeline = |label: &'static str, alpha_mode: AlphaMode| {
+            let mut pipeline_factory = RenderPipelineFactory::new();
+            pipeline_factory.set_label(label);
+            pipeline_factory.add_vertex_attributes(&attribs, stride);
+            // .add_instance_attributes(&instance_attribs, std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress)
+            if enable_depth {
+                pipeline_factory.add_depth_stencil(alpha_mode.depth_config());
+            }
+            pipeline_factory.set_blend_config(alpha_mode.blend_config());
+
+            pipeline_factory.set_topology(topology);
+
+            pipeline_factory.create_render_pipeline(ctx, shader_module, layouts)
+        };
+
+        // Masking only discards in the shader, so it shares the opaque pipeline.
+        (
+            create_pipeline("PBR pipeline", AlphaMode::Opaque),
+            create_pipeline("PBR blend pipeline", AlphaMode::Blend),
+        )
+    }
+
+    /// The plain and the masked shadow pipeline, the plain one only uses the first two layouts.
+    fn create_shadow_pipelines(
+        ctx: &State,
+        shader_module: &wgpu::ShaderModule,
+        layouts: &[Option<&wgpu::BindGroupLayout>; 3],
+        topology: PrimitiveTopology,
+    ) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+        let attribs = Self::vertex_attributes();
+        let stride = std::mem::size_of::<Vertex>() as u64;
+
+        let create_pipeline = |label: &'static str, masked: bool| {
+            let mut shadow_pipeline_factory = RenderPipelineFactory::new();
+            shadow_pipeline_factory.set_label(label);
+            shadow_pipeline_factory.add_vertex_attributes(&attribs, stride);
+            shadow_pipeline_factory
+                .add_depth_stencil(factories::render_pipeline::DepthConfig::Shadow);
+            shadow_pipeline_factory.set_depth_only(true);
+            shadow_pipeline_factory.set_sample_count(Some(1));
+            shadow_pipeline_factory.set_topology(topology);
+
+            if masked {
+                shadow_pipeline_factory.set_vert_entry(Some("vs_masked"));
+                shadow_pipeline_factory.set_frag_entry(Some("fs_masked"));
+                shadow_pipeline_factory.create_render_pipeline(ctx, shader_module, layouts)
+            } else {
+                shadow_pipeline_factory.create_render_pipeline(ctx, shader_module, &layouts[..2])
+            }
+        };
+
+        (
+            create_pipeline("PBR shadow pipeline", false),
+            create_pipeline("PBR masked shadow pipeline", true),
+        )
+    }
+
+    fn vertex_attributes() -> [wgpu::VertexAttribute; 5] {
+        wgpu::vertex_attr_array![ 0 => Float32x3, 1 => Float32x2, 2 => Float32x4 ,3 => Float32x3, 4 => Float32x4]
+    }
+
+    /// Rebuilds [`PbrPipeline::pipeline`] and [`PbrPipeline::blend_pipeline`] for the current
+    /// sample count after [`State::set_sample_count`], does nothing when it already matches.
+    /// `lights` has to be the set the pipeline was created with.
+    pub fn recreate_pipelines(&mut self, ctx: &State, lights: &LightSet) {
+        if self.sample_count == ctx.get_sample_count() {
+            return;
+        }
+
+        let (pipeline, blend_pipeline) = Self::create_lit_pipelines(
+            ctx,
+            &self.shader_module,
+            &[
+                Some(&self.bind_group_layout),
+                Some(&self.environment_layout),
+                Some(&self.material_layout),
+                Some(&lights.bind_group_layout),
+            ],
+            self.topology,
+            self.enable_depth,
         );
+        self.pipeline = pipeline;
+        self.blend_pipeline = blend_pipeline;
+        self.sample_count = ctx.get_sample_count();
+    }
 
-        texture_bind_group_factory.add_texture_and_sampler(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
-            &metallic.view,
-            &metallic.sampler,
+    /// Rebuilds the pipelines whose shader file changed, see [`ShaderHotReload`]. `lights` has
+    /// to be the set the pipeline was created with.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(
+        &mut self,
+        ctx: &State,
+        lights: &LightSet,
+        hot_reload: &mut ShaderHotReload,
+    ) {
+        let lit_layouts = [
+            Some(&self.bind_group_layout),
+            Some(&self.environment_layout),
+            Some(&self.material_layout),
+            Some(&lights.bind_group_layout),
+        ];
+        let lit = hot_reload.reload(ctx, &self.shader, |module| {
+            let (pipeline, blend_pipeline) = Self::create_lit_pipelines(
+                ctx,
+                module,
+                &lit_layouts,
+                self.topology,
+                self.enable_depth,
+            );
+            (module.clone(), pipeline, blend_pipeline)
+        });
+        if let Some((shader_module, pipeline, blend_pipeline)) = lit {
+            self.shader_module = shader_module;
+            self.pipeline = pipeline;
+            self.blend_pipeline = blend_pipeline;
+            self.sample_count = ctx.get_sample_count();
+        }
+
+        let shadow_layouts = [
+            Some(&self.bind_group_layout),
+            Some(&lights.shadows.cascade_layout),
+            Some(&self.material_layout),
+        ];
+        let shadow = hot_reload.reload(ctx, &self.shadow_shader, |module| {
+            Self::create_shadow_pipelines(ctx, module, &shadow_layouts, self.topology)
+        });
+        if let Some((shadow_pipeline, masked_shadow_pipeline)) = shadow {
+            self.shadow_pipeline = shadow_pipeline;
+            self.masked_shadow_pipeline = masked_shadow_pipeline;
+        }
+    }
+
+    /// Writes the camera and frees the model slots of the previous frame. Call it before
+    /// recording the frame's passes, slots can't be reused within a submission.
+    pub fn begin_frame(&self, ctx: &State, view: &ViewUniform) {
+        if let Some(buffer) = &self.global_uniform_buffer {
+            pipelines::write_uniform_buffer(
+                std::slice::from_ref(view),
+                buffer,
+                &ctx.queue,
+                &ctx.device,
+            );
+        }
+
+        self.models.borrow_mut().used = 0;
+    }
+
+    pub fn pipeline_for(&self, alpha_mode: AlphaMode) -> &wgpu::RenderPipeline {
+        match alpha_mode {
+            AlphaMode::Opaque | AlphaMode::Mask { .. } => &self.pipeline,
+            AlphaMode::Blend => &self.blend_pipeline,
+        }
+    }
+
+    /// Draws `mesh` with `material` at `transform`, switching to the pipeline of its alpha mode.
+    /// The environment and light groups have to be set already.
+    pub fn draw_mesh(
+        &self,
+        ctx: &State,
+        render_pass: &mut wgpu::RenderPass,
+        mesh: &GpuMesh,
+        transform: glam::Mat4,
+        material: &PbrMaterial,
+    ) {
+        assert_eq!(
+            self.sample_count,
+            ctx.get_sample_count(),
+            "the sample count changed, call PbrPipeline::recreate_pipelines first"
         );
+        render_pass.set_pipeline(self.pipeline_for(material.alpha_mode));
+        render_pass.set_bind_group(2, &material.bind_group, &[]);
+        self.draw_shadow_caster(ctx, render_pass, mesh, transform);
+    }
 
-        texture_bind_group_factory.add_texture_sky_sampler(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
+    /// [`PbrPipeline::masked_shadow_pipeline`] for [`AlphaMode::Mask`], which needs the material
+    /// bound, [`PbrPipeline::shadow_pipeline`] otherwise.
+    pub fn shadow_pipeline_for(&self, alpha_mode: AlphaMode) -> &wgpu::RenderPipeline {
+        match alpha_mode {
+            AlphaMode::Opaque | AlphaMode::Blend => &self.shadow_pipeline,
+            AlphaMode::Mask { .. } => &self.masked_shadow_pipeline,
+        }
+    }
+
+    /// Draws `mesh` into a cascade of [`ShadowMaps::add_passes`] with the shadow pipeline of
+    /// `material`'s alpha mode, so masked texels don't cast shadows.
+    pub fn draw_shadow_mesh(
+        &self,
+        ctx: &State,
+        render_pass: &mut wgpu::RenderPass,
+        mesh: &GpuMesh,
+        transform: glam::Mat4,
+        material: &PbrMaterial,
+    ) {
+        render_pass.set_pipeline(self.shadow_pipeline_for(material.alpha_mode));
+        if let AlphaMode::Mask { .. } = material.alpha_mode {
+            render_pass.set_bind_group(2, &material.bind_group, &[]);
+        }
+        self.draw_shadow_caster(ctx, render_pass, mesh, transform);
+    }
+
+    /// Draws `mesh` without a material, e.g. with [`PbrPipeline::shadow_pipeline`] in the passes
+    /// of [`ShadowMaps::add_passes`].
+    pub fn draw_shadow_caster(
+        &self,
+        ctx: &State,
+        render_pass: &mut wgpu::RenderPass,
+        mesh: &GpuMesh,
+        transform: glam::Mat4,
+    ) {
+        let mut models = self.models.borrow_mut();
+        if models.used == models.capacity {
+            // Draws recorded so far keep the old buffer alive and read their slots from it.
+            models.capacity *= 2;
+            let (buffer, bind_group) = self.create_model_buffer(ctx, models.capacity);
+            models.buffer = buffer;
+            models.bind_group = bind_group;
+        }
+
+        let stride = std::mem::size_of::<PbrModelUniform>() as wgpu::BufferAddress;
+        let offset = stride * models.used as wgpu::BufferAddress;
+        let uniform = PbrModelUniform::new(transform);
+        let matrices = [
+            uniform.model_matrix.to_cols_array(),
+            uniform.normal_matrix.to_cols_array(),
+        ];
+        ctx.queue
+            .write_buffer(&models.buffer, offset, bytemuck::cast_slice(&matrices));
+        models.used += 1;
+
+        render_pass.set_bind_group(0, &models.bind_group, &[0, offset as wgpu::DynamicOffset]);
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..mesh.vertex_count, 0, 0..1);
+    }
+
+    fn create_model_buffer(&self, ctx: &State, capacity: usize) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = create_uniform_buffer_stride::<PbrModelUniform>(capacity as u64, &ctx.device);
+        let global_uniform_buffer = self.global_uniform_buffer.as_ref().unwrap();
+
+        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("PBR model bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: global_uniform_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<ViewUniform>() as _),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<PbrModelUniform>() as _),
+                    }),
+                },
+            ],
+        });
+
+        (buffer, bind_group)
+    }
+
+    /// Points the environment group at the maps of another sky, e.g. after re-baking it.
+    pub fn set_environment(&mut self, ctx: &State, sky: &SkyRenderer) {
+        let (_, environment_bind_group) = Self::build_environment(ctx, sky);
+        self.environment_bind_group = environment_bind_group;
+    }
+
+    fn build_environment(
+        ctx: &State,
+        sky: &SkyRenderer,
+    ) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let mut factory = BindGroupFactory::new();
+        factory.set_labels("PBR environment layout", "PBR environment bind group");
+        factory.add_cube_texture_and_sampler(
+            wgpu::ShaderStages::FRAGMENT,
             &sky.iradiance_texture.view,
             &sky.iradiance_texture.sampler,
         );
-
-        texture_bind_group_factory.add_texture_sky_sampler(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
+        factory.add_cube_texture_and_sampler(
+            wgpu::ShaderStages::FRAGMENT,
             &sky.specular_reflection_texture.view,
             &sky.specular_reflection_texture.sampler,
         );
-
-        texture_bind_group_factory.add_texture_hdr_and_sampler(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
+        factory.add_texture_hdr_and_sampler(
+            wgpu::ShaderStages::FRAGMENT,
             &sky.brdf_lut.view,
             &sky.brdf_lut.sampler,
-            wgpu::SamplerBindingType::Filtering,
+            wgpu::SamplerBindingType::NonFiltering,
         );
 
-        let (texture_bind_group_layout, texture_bind_group) =
-            texture_bind_group_factory.build(&ctx.device);
-
-        let mut pipeline_factory = RenderPipelineFactory::new();
-        pipeline_factory.set_label("PBR pipeline");
-        pipeline_factory.add_vertex_attributes(&attribs, stride);
-        // .add_instance_attributes(&instance_attribs, std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress)
-        if enable_depth {
-            pipeline_factory
-                .add_depth_stencil(factories::render_pipeline::DepthConfig::DefaultWrite);
-        }
-
-        pipeline_factory.set_topology(topology);
-
-        let pipeline = pipeline_factory.create_render_pipeline(
-            &ctx,
-            &shader_module,
-            &[Some(&bind_group_layout), Some(&texture_bind_group_layout)],
+        let mip_count = sky.specular_reflection_texture.texture.mip_level_count();
+        let environment_uniform = EnvironmentUniform {
+            max_reflection_lod: (mip_count - 1) as f32,
+            irradiance_sh: (sky.irradiance_source == IrradianceSource::SphericalHarmonics) as u32,
+            _pad: [0; 2],
+        };
+        let uniform_buffer = ctx
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("PBR environment uniform"),
+                contents: bytemuck::bytes_of(&environment_uniform),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        factory.add_static_uniform(
+            wgpu::ShaderStages::FRAGMENT,
+            &uniform_buffer,
+            wgpu::BufferSize::new(std::mem::size_of::<EnvironmentUniform>() as _),
+        );
+        // Written by the sky, so its rotation and exposure apply without rebuilding the group.
+        factory.add_static_uniform(wgpu::ShaderStages::FRAGMENT, &sky.params_buffer, None);
+        factory.add_static_uniform(
+            wgpu::ShaderStages::FRAGMENT,
+            &sky.irradiance_sh,
+            wgpu::BufferSize::new(IRRADIANCE_SH_SIZE),
         );
 
-        Self {
-            pipeline,
-            shader_module,
-            // bind_group_layout,
-            bind_group,
-
-            // texture_bind_group_layout: Some(texture_bind_group_layout),
-            texture_bind_group: Some(texture_bind_group),
-
-            global_uniform_buffer: Some(global_uniform_buffer),
-            model_uniform_buffer: Some(model_uniform_buffer),
-        }
+        factory.build(&ctx.device)
     }
 
     pub fn get_buffers_from_geometry(ctx: &State, geo_data: &GeometryData) -> GpuMesh {
@@ -194,6 +569,7 @@ impl PbrPipeline {
                 uv: [0.0, 0.0],
                 color: [1.0, 1.0, 1.0, 1.0],
                 normal: [0.0, 0.0, 0.0],
+                tangent: [1.0, 0.0, 0.0, 1.0],
             });
         }
 
@@ -237,6 +613,15 @@ impl PbrPipeline {
             }
         }
 
+        // Tangents -----
+
+        let tangents_option = geo_data.attributes.get(&attribute_names::TANGENT);
+        if let Some(tangents) = tangents_option {
+            for (vertex, tangent) in vertices.iter_mut().zip(tangents.chunks_exact(4)) {
+                vertex.tangent = [tangent[0], tangent[1], tangent[2], tangent[3]];
+            }
+        }
+
         let vertex_buffer = ctx
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -3,6 +3,7 @@ struct VertexInput {
     @location(1) uv: vec2<f32>,
     @location(2) color: vec4<f32>,
     @location(3) normal: vec3<f32>,
+    @location(4) tangent: vec4<f32>,
 };
 
 struct VertexOutput {
@@ -11,26 +12,17 @@ struct VertexOutput {
     @location(1) uv: vec2<f32>,
     @location(2) color: vec4<f32>,
     @location(3) normal: vec3<f32>,
+    @location(4) tangent: vec4<f32>,
 };
 
-struct CameraUniform {
-    view_proj_matrix : mat4x4<f32>,
-    view_matrix : mat4x4<f32>,
-    perspective_matrix : mat4x4<f32>,
-    position : vec3<f32>,
-}
+#include "camera.wgsl"
+#include "sky_params.wgsl"
+#include "spherical_harmonics.wgsl"
+#include "pbr_material.wgsl"
 
 struct ModelUniform {
     model_matrix : mat4x4<f32>,
-    
-    light_position : vec3<f32>,
-    light_intensity : f32,
-
-    ambient : vec3<f32>,
-    roughness : f32,
-
-    albedo : vec3<f32>,
-    metallic : f32,
+    normal_matrix : mat4x4<f32>,
 }
 
 
@@ -40,35 +32,112 @@ var<uniform> camera: CameraUniform;
 @group(0) @binding(1) // 1.
 var<uniform> modelUniform: ModelUniform;
 
+// Environment ---
+
 @group(1) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var env_map: texture_cube<f32>;
 @group(1) @binding(1)
-var s_diffuse: sampler;
+var env_sampler: sampler;
 
 @group(1) @binding(2)
-var t_albedo: texture_2d<f32>;
+var env_map_prefiltered: texture_cube<f32>;
 @group(1) @binding(3)
-var s_albedo: sampler;
+var env_prefiltered_sampler: sampler;
 
 @group(1) @binding(4)
-var t_metallic: texture_2d<f32>;
+var brdf_lut: texture_2d<f32>;
 @group(1) @binding(5)
-var s_metallic: sampler;
+var brdf_lut_sampler: sampler;
+
+struct EnvironmentUniform {
+    max_reflection_lod : f32,
+    irradiance_sh : u32,
+}
 
 @group(1) @binding(6)
-var env_map: texture_cube<f32>;
+var<uniform> environment: EnvironmentUniform;
+
 @group(1) @binding(7)
-var env_sampler: sampler;
+var<uniform> sky_params: SkyParams;
 
 @group(1) @binding(8)
-var env_map_prefiltered: texture_cube<f32>;
-@group(1) @binding(9)
-var env_prefiltered_sampler: sampler;
+var<uniform> irradiance_sh: array<vec4<f32>, 9>;
 
-@group(1) @binding(10)
-var brdf_lut: texture_2d<f32>;
-@group(1) @binding(11)
-var brdf_lut_sampler: sampler;
+// Material ---
+
+@group(2) @binding(0)
+var<uniform> material: MaterialUniform;
+
+@group(2) @binding(1)
+var t_albedo: texture_2d<f32>;
+@group(2) @binding(2)
+var s_albedo: sampler;
+
+@group(2) @binding(3)
+var t_roughness: texture_2d<f32>;
+@group(2) @binding(4)
+var s_roughness: sampler;
+
+@group(2) @binding(5)
+var t_metallic: texture_2d<f32>;
+@group(2) @binding(6)
+var s_metallic: sampler;
+
+@group(2) @binding(7)
+var t_normal: texture_2d<f32>;
+@group(2) @binding(8)
+var s_normal: sampler;
+
+@group(2) @binding(9)
+var t_emissive: texture_2d<f32>;
+@group(2) @binding(10)
+var s_emissive: sampler;
+
+@group(2) @binding(11)
+var t_occlusion: texture_2d<f32>;
+@group(2) @binding(12)
+var s_occlusion: sampler;
+
+const LIGHT_POINT : u32 = 0u;
+const LIGHT_SPOT : u32 = 1u;
+const LIGHT_DIRECTIONAL : u32 = 2u;
+
+struct Light {
+    position : vec3<f32>,
+    range : f32,
+    direction : vec3<f32>,
+    kind : u32,
+    color : vec3<f32>,
+    intensity : f32,
+    cos_inner : f32,
+    cos_outer : f32,
+}
+
+struct Lights {
+    count : u32,
+    lights : array<Light>,
+}
+
+@group(3) @binding(0)
+var<storage, read> lights: Lights;
+
+struct ShadowUniform {
+    light_view_proj : array<mat4x4<f32>, 4>,
+    splits : vec4<f32>,
+    texel_sizes : vec4<f32>,
+    depth_bias : f32,
+    normal_bias : f32,
+    pcf_radius : f32,
+    cascade_count : u32,
+    light_index : u32,
+}
+
+@group(3) @binding(1)
+var shadow_map: texture_depth_2d_array;
+@group(3) @binding(2)
+var shadow_sampler: sampler_comparison;
+@group(3) @binding(3)
+var<uniform> shadows: ShadowUniform;
 
 
 @vertex
@@ -81,11 +150,13 @@ fn vs_main( model : VertexInput ) -> VertexOutput {
     out.world_position = world_position.xyz;
     out.uv = model.uv;
     out.color = model.color;
-    out.normal = model.normal;
+    out.normal = (modelUniform.normal_matrix * vec4(model.normal, 0.0)).xyz;
+    // Tangents lie in the surface, so they follow the model matrix itself.
+    out.tangent = vec4((modelUniform.model_matrix * vec4(model.tangent.xyz, 0.0)).xyz, model.tangent.w);
     return out;
 }
 
-const PI : f32 = 3.14159265359;
+#include "constants.wgsl"
 
 
 // GGX Normal distribution
@@ -127,69 +198,150 @@ fn getDiffuse( albedo : vec3f, roughness4 : f32, NoV : f32, NoL : f32, VoH : f32
     return albedo / PI * ( NoL * c1 + c2 );
 }
 
-// http://imdoingitwrong.wordpress.com/2011/01/31/light-attenuation/
-fn getAttenuation( lightPosition :  vec3f, vertexPosition : vec3f, lightRadius : f32 ) -> f32
-{
-	var r				= lightRadius;
-	var L				= lightPosition - vertexPosition;
-	var dist			= length(L);
-	var d				= max( dist - r, 0.0 );
-	L					/= dist;
-	var denom			= d / r + 1.0f;
-	var attenuation	    = 1.0f / (denom*denom);
-	var cutoff  		= 0.0052f;
-	attenuation			= (attenuation - cutoff) / (1.0 - cutoff);
-	attenuation			= max(attenuation, 0.0);
-	
-	return attenuation;
-}
-
-const A = 0.15;
-const B = 0.50;
-const C = 0.10;
-const D = 0.20;
-const E = 0.02;
-const F = 0.30;
-
-fn Uncharted2Tonemap( x : vec3f ) -> vec3f
+// Charlie distribution of the sheen lobe, from "Production Friendly Microfacet Sheen BRDF".
+fn getSheenDistribution( sheen_roughness : f32, NoH : f32 ) -> f32 {
+    var inv_alpha = 1.0 / max(sheen_roughness * sheen_roughness, 0.0001);
+    var sin2h = max(1.0 - NoH * NoH, 0.0078125);
+    return (2.0 + inv_alpha) * pow(sin2h, inv_alpha * 0.5) / (2.0 * PI);
+}
+
+// Neubelt's visibility term for cloth.
+fn getSheenVisibility( NoV : f32, NoL : f32 ) -> f32 {
+    return 1.0 / max(4.0 * (NoL + NoV - NoL * NoV), 0.0001);
+}
+
+// Inverse square falloff windowed to reach 0 at the range, as in KHR_lights_punctual.
+fn getAttenuation( distance : f32, range : f32 ) -> f32
 {
-	return ((x*(A*x+C*B)+D*E)/(x*(A*x+B)+D*F))-E/F;
+    var attenuation = 1.0 / max(distance * distance, 0.0001);
+    if (range <= 0.0) {
+        return attenuation;
+    }
+
+    var window = saturate(1.0 - pow(distance / range, 4.0));
+    return attenuation * window * window;
 }
 
+fn getSpotFactor( light : Light, L : vec3f ) -> f32 {
+    var cos_angle = dot(light.direction, -L);
+    return smoothstep(light.cos_outer, light.cos_inner, cos_angle);
+}
+
+// Light reaching the surface along L from the given light.
+fn getLightRadiance( light : Light, world_position : vec3f, L : ptr<function, vec3f> ) -> vec3f {
+    var radiance = light.color * light.intensity;
+
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        *L = -light.direction;
+        return radiance;
+    }
+
+    var to_light = light.position - world_position;
+    var distance = length(to_light);
+    *L = to_light / max(distance, 0.0001);
+    radiance *= getAttenuation(distance, light.range);
+
+    if (light.kind == LIGHT_SPOT) {
+        radiance *= getSpotFactor(light, *L);
+    }
+
+    return radiance;
+}
+
+// Fraction of the shadow casting light reaching the fragment, filtered with a 3x3 PCF kernel.
+fn getShadow( world_position : vec3f, N : vec3f, L : vec3f ) -> f32 {
+    var view_depth = (camera.view_matrix * vec4(world_position, 1.0)).z;
+
+    var cascade = shadows.cascade_count;
+    for (var i = 0u; i < shadows.cascade_count; i++) {
+        if (view_depth < shadows.splits[i]) {
+            cascade = i;
+            break;
+        }
+    }
+    if (cascade >= shadows.cascade_count) {
+        return 1.0;
+    }
+
+    // Pushes the lookup off the surface, more so where the light grazes it.
+    var NoL = saturate(dot(N, L));
+    var offset = N * shadows.normal_bias * shadows.texel_sizes[cascade] * (1.0 - NoL);
+    var light_clip = shadows.light_view_proj[cascade] * vec4(world_position + offset, 1.0);
+    var coords = light_clip.xyz / light_clip.w;
+    var uv = coords.xy * vec2(0.5, -0.5) + 0.5;
+    var depth = coords.z - shadows.depth_bias;
+
+    var texel = shadows.pcf_radius / vec2f(textureDimensions(shadow_map));
+    var visibility = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            var sample_uv = uv + vec2f(f32(x), f32(y)) * texel;
+            visibility += textureSampleCompareLevel(shadow_map, shadow_sampler, sample_uv, cascade, depth);
+        }
+    }
+
+    return visibility / 9.0;
+}
+
+// Perturbs the surface normal with the tangent space normal map.
+fn getMappedNormal( normal : vec3f, tangent : vec4f, mapped : vec3f, strength : f32 ) -> vec3f {
+    var T = tangent.xyz - normal * dot(normal, tangent.xyz);
+    if (strength <= 0.0 || dot(T, T) < 1e-8) {
+        return normal;
+    }
+
+    T = normalize(T);
+    var B = cross(normal, T) * sign(tangent.w);
+    var tangent_normal = mapped * 2.0 - 1.0;
+    tangent_normal = vec3(tangent_normal.xy * strength, tangent_normal.z);
+
+    return normalize(mat3x3<f32>(T, B, normal) * tangent_normal);
+}
 
 @fragment
 fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
     // Textures --- 
 
-    var c_roughness = textureSample(t_diffuse, s_diffuse, in.uv * vec2(1.0)).rgb;
-    var c_albedo = textureSample(t_albedo, s_albedo, in.uv * vec2(1.0)).rgb;
+    var c_roughness = textureSample(t_roughness, s_roughness, in.uv * vec2(1.0)).rgb;
+    var c_albedo_alpha = textureSample(t_albedo, s_albedo, in.uv * vec2(1.0));
+    var c_albedo = c_albedo_alpha.rgb;
     var c_metallic = textureSample(t_metallic, s_metallic, in.uv * vec2(1.0)).rgb;
+    var c_normal = textureSample(t_normal, s_normal, in.uv).rgb;
+    var c_emissive = textureSample(t_emissive, s_emissive, in.uv).rgb;
+    var c_occlusion = textureSample(t_occlusion, s_occlusion, in.uv).r;
 
+    var alpha = material.alpha * c_albedo_alpha.a;
+    if (material.alpha_mode == ALPHA_MASK && alpha < material.alpha_cutoff) {
+        discard;
+    }
+    if (material.alpha_mode != ALPHA_BLEND) {
+        alpha = 1.0;
+    }
 
-    // UNIFORMS 
-    var light_position = modelUniform.light_position; // vec3(5.0, 5.0, 10.0);
-    var light_intensity = modelUniform.light_intensity;
-    
 
     // Vectors ---
-    var N = normalize(in.normal);
-    var L = normalize(light_position - in.world_position);
+    var surface_normal = normalize(in.normal);
+    var N = getMappedNormal(surface_normal, in.tangent, c_normal, material.normal_strength);
     var V = normalize( camera.position - in.world_position ) ;
-    var H = normalize(V + L);
 
     var R = reflect(-V, N);
 
 
     // Textures -----
-    var irradiance = textureSample(env_map, env_sampler, N ).rgb; // diffuse irradiance // modelUniform.ambient; 
+    var irradiance = textureSample(env_map, env_sampler, sky_direction(sky_params, N)).rgb; // diffuse irradiance // modelUniform.ambient; 
+    if (environment.irradiance_sh != 0u) {
+        irradiance = max(sh_evaluate(irradiance_sh, sky_direction(sky_params, N)), vec3(0.0));
+    }
+    irradiance *= sky_params.intensity;
 
 
 
-    var albedo =  irradiance * modelUniform.albedo * c_albedo;
-    var roughness = saturate((modelUniform.roughness * c_roughness.r));
-    var metallic = modelUniform.metallic * c_metallic;
-    var ambient = modelUniform.ambient;
+    var base_color = material.albedo * c_albedo;
+    var albedo =  irradiance * base_color;
+    var roughness = saturate((material.roughness * c_roughness.r));
+    var metallic = material.metallic * c_metallic;
+    var ambient = material.ambient;
     var roughness4 : f32 = pow(roughness, 4.0); //roughness * roughness * roughness * roughness;
 
 
@@ -197,11 +349,8 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
     //Dot products ----
 
-    var NoH = saturate(dot(N, H));
-    var VoH = saturate(dot(V, H));
     var NoV = saturate(dot(N, V));
-    var NoL = saturate(dot(N, L));
-    
+
     var specularColor = mix( vec3( 0.04 ), albedo, metallic );
     var F =  fresnelSchlick(max(dot(N, V), 0.0), specularColor); //fresnelSchlick(max(dot(N, V), 0.0), F0, roughness);
 
@@ -211,13 +360,68 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
     var diffuse    = irradiance * albedo;
 
-    var MAX_REFLECTION_LOD = 6.0;
-    var prefiltered_color = textureSampleLevel(env_map_prefiltered, env_prefiltered_sampler, R, roughness * MAX_REFLECTION_LOD ).rgb;
+    let MAX_REFLECTION_LOD = environment.max_reflection_lod;
+    var prefiltered_color = textureSampleLevel(env_map_prefiltered, env_prefiltered_sampler, sky_direction(sky_params, R), roughness * MAX_REFLECTION_LOD ).rgb * sky_params.intensity;
     var envBRDF  = textureSample(brdf_lut, brdf_lut_sampler, vec2(max(dot(N, V), 0.0), roughness)).rg;
     var specular = prefiltered_color * (F * envBRDF.x + envBRDF.y);
 
-    var color = (kD * diffuse + specular); //* ao;  //ambient
-
-    color += ambient * albedo;
-    return vec4<f32>(color, 1.0);
+    var occlusion = mix(1.0, c_occlusion, material.occlusion_strength);
+    var color = (kD * diffuse + specular) * occlusion;
+
+    // Clearcoat, a smooth dielectric layer over the geometric normal ---
+    var clearcoat = saturate(material.clearcoat);
+    var clearcoat_roughness = saturate(material.clearcoat_roughness);
+    var clearcoat_roughness4 = max(pow(clearcoat_roughness, 4.0), 0.0001);
+    var Nc = surface_normal;
+    var NcoV = saturate(dot(Nc, V));
+    var clearcoat_fresnel = fresnelSchlick(NcoV, vec3(0.04)).x * clearcoat;
+
+    var clearcoat_prefiltered = textureSampleLevel(env_map_prefiltered, env_prefiltered_sampler, sky_direction(sky_params, reflect(-V, Nc)), clearcoat_roughness * MAX_REFLECTION_LOD).rgb * sky_params.intensity;
+    var clearcoat_brdf = textureSample(brdf_lut, brdf_lut_sampler, vec2(NcoV, clearcoat_roughness)).rg;
+    color = color * (1.0 - clearcoat_fresnel)
+        + clearcoat_prefiltered * (0.04 * clearcoat_brdf.x + clearcoat_brdf.y) * clearcoat * occlusion;
+
+    // Sheen only comes from the direct lights, the BRDF LUT has no term for its lobe.
+    var sheen_color = material.sheen_color;
+    var sheen_roughness = saturate(material.sheen_roughness);
+
+    // Direct lights ---
+    var direct_specular_color = mix( vec3( 0.04 ), base_color, metallic );
+    // Keeps the highlight of perfectly smooth surfaces finite.
+    var direct_roughness4 = max(roughness4, 0.0001);
+    for (var i = 0u; i < lights.count; i++) {
+        var light = lights.lights[i];
+
+        var L = vec3f(0.0);
+        var radiance = getLightRadiance(light, in.world_position, &L);
+        if (i == shadows.light_index) {
+            radiance *= getShadow(in.world_position, surface_normal, L);
+        }
+        var H = normalize(V + L);
+
+        var NoL = saturate(dot(N, L));
+        var NoH = saturate(dot(N, H));
+        var VoH = saturate(dot(V, H));
+
+        var D = getNormalDistribution(direct_roughness4, NoH) / PI;
+        var Vis = getGeometricShadowing(direct_roughness4, NoV, NoL, VoH, L, V);
+        var F_light = fresnelSchlick(VoH, direct_specular_color);
+
+        var light_kD = (1.0 - F_light) * (1.0 - metallic);
+        var light_specular = D * Vis * F_light;
+        var light_sheen = sheen_color * getSheenDistribution(sheen_roughness, NoH) * getSheenVisibility(NoV, NoL);
+        var base_lobe = (light_kD * base_color / PI + light_specular + light_sheen) * NoL;
+
+        var NcoL = saturate(dot(Nc, L));
+        var NcoH = saturate(dot(Nc, H));
+        var F_coat = fresnelSchlick(VoH, vec3(0.04)).x * clearcoat;
+        var coat_lobe = getNormalDistribution(clearcoat_roughness4, NcoH) / PI
+            * getGeometricShadowing(clearcoat_roughness4, NcoV, NcoL, VoH, L, V) * F_coat * NcoL;
+
+        color += (base_lobe * (1.0 - F_coat) + coat_lobe) * radiance;
+    }
+
+    color += ambient * albedo * occlusion;
+    color += material.emissive * c_emissive;
+    return vec4<f32>(color, alpha);
 }
\ No newline at end of file
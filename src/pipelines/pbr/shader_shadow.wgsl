@@ -0,0 +1,56 @@
+struct ModelUniform {
+    model_matrix : mat4x4<f32>,
+}
+
+struct CascadeUniform {
+    light_view_proj : mat4x4<f32>,
+}
+
+@group(0) @binding(1)
+var<uniform> modelUniform: ModelUniform;
+
+@group(1) @binding(0)
+var<uniform> cascade: CascadeUniform;
+
+fn light_clip_position(position: vec3<f32>) -> vec4<f32> {
+    return cascade.light_view_proj * modelUniform.model_matrix * vec4(position, 1.0);
+}
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return light_clip_position(position);
+}
+
+// Masked casters ---
+// Drawn with the material bound at group 2, texels below the alpha cutoff don't cast shadows.
+
+#include "pbr_material.wgsl"
+
+@group(2) @binding(0)
+var<uniform> material: MaterialUniform;
+
+@group(2) @binding(1)
+var t_albedo: texture_2d<f32>;
+@group(2) @binding(2)
+var s_albedo: sampler;
+
+struct MaskedOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_masked(@location(0) position: vec3<f32>, @location(1) uv: vec2<f32>) -> MaskedOutput {
+    var out: MaskedOutput;
+    out.clip_position = light_clip_position(position);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_masked(in: MaskedOutput) {
+    let alpha = material.alpha * textureSample(t_albedo, s_albedo, in.uv).a;
+    if (alpha < material.alpha_cutoff) {
+        discard;
+    }
+}
@@ -0,0 +1,453 @@
+use std::rc::Rc;
+
+use crate::factories::texture::TextureBundle;
+use crate::factories::DepthTextureFactory;
+use crate::frame_graph::{DepthAttachment, FrameGraph, PassOptions};
+use crate::helpers::cameras::{CameraTrait, PespectiveCamera};
+use crate::state::State;
+
+use super::{Light, LightKind};
+
+pub const MAX_CASCADES: usize = 4;
+
+/// Shader value for "no light casts shadows".
+const NO_SHADOW_LIGHT: u32 = u32::MAX;
+
+/// Parameters of the shadows, they can be changed every frame.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    /// Shadows end this far from the camera, the cascades split the distance up to it.
+    pub max_distance: f32,
+    /// Blend between evenly spaced (0) and logarithmic (1) cascade splits.
+    pub split_lambda: f32,
+    /// Subtracted from the fragment's depth in light space before the comparison.
+    pub depth_bias: f32,
+    /// Moves the lookup along the surface normal, in texels of the cascade.
+    pub normal_bias: f32,
+    /// Distance between the PCF taps, in texels.
+    pub pcf_radius: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            max_distance: 50.0,
+            split_lambda: 0.75,
+            depth_bias: 0.0005,
+            normal_bias: 1.5,
+            pcf_radius: 1.0,
+        }
+    }
+}
+
+pub struct ShadowOptions {
+    /// Width and height of every cascade.
+    pub resolution: u32,
+    /// Clamped to [`MAX_CASCADES`].
+    pub cascade_count: u32,
+    pub settings: ShadowSettings,
+}
+
+impl Default for ShadowOptions {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            cascade_count: 4,
+            settings: ShadowSettings::default(),
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(super) struct ShadowUniform {
+    light_view_proj: [[f32; 16]; MAX_CASCADES],
+    /// View space depth where each cascade ends.
+    splits: [f32; MAX_CASCADES],
+    /// World space size of a texel of each cascade.
+    texel_sizes: [f32; MAX_CASCADES],
+    depth_bias: f32,
+    normal_bias: f32,
+    pcf_radius: f32,
+    cascade_count: u32,
+    light_index: u32,
+    _pad: [u32; 3],
+}
+
+/// One cascade fitted by [`ShadowMaps::fit_cascades`].
+#[derive(Clone, Copy, Debug)]
+pub struct Cascade {
+    /// Light view and orthographic projection covering the cascade.
+    pub view_proj: glam::Mat4,
+    /// View space depth where the cascade ends.
+    pub split: f32,
+    /// World space size of a shadow map texel.
+    pub texel_size: f32,
+}
+
+/// Cascaded shadow maps for the first directional light of a [`super::LightSet`], which owns them.
+///
+/// Call [`super::LightSet::update_shadows`] once the camera moved, then
+/// [`ShadowMaps::add_passes`] before the scene pass. PBR draws sample them through the light
+/// set's bind group.
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+
+    /// Layout of the light matrix of the cascade being drawn, bound at group 1 in the shadow pass.
+    pub cascade_layout: wgpu::BindGroupLayout,
+    /// One layer per cascade, a single texel placeholder until a directional light shows up.
+    pub shadow_map: TextureBundle,
+
+    resolution: u32,
+    cascade_count: u32,
+
+    cascade_views: Vec<wgpu::TextureView>,
+
+    /// Bound next to the lights by [`super::LightSet`], with [`ShadowMaps::shadow_map`].
+    pub(super) uniform_buffer: wgpu::Buffer,
+    cascade_buffer: wgpu::Buffer,
+    cascade_stride: wgpu::BufferAddress,
+    cascade_bind_group: wgpu::BindGroup,
+
+    splits: [f32; MAX_CASCADES],
+    light_index: Option<usize>,
+}
+
+impl ShadowMaps {
+    pub fn new(state: &State, options: ShadowOptions) -> Self {
+        puffin::profile_function!();
+        let State { device, .. } = state;
+
+        let cascade_count = options.cascade_count.clamp(1, MAX_CASCADES as u32);
+        // Most scenes never get a directional light, the full map waits for one.
+        let shadow_map = DepthTextureFactory::new_shadow_map(device, 1, 1, "Shadow map");
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow uniform"),
+            size: std::mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let matrix_size = std::mem::size_of::<glam::Mat4>() as wgpu::BufferAddress;
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let cascade_stride = matrix_size.div_ceil(alignment) * alignment;
+        let cascade_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow cascades"),
+            size: cascade_stride * cascade_count as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cascade_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow cascade layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(matrix_size),
+                },
+                count: None,
+            }],
+        });
+
+        let cascade_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow cascade bind group"),
+            layout: &cascade_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &cascade_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(matrix_size),
+                }),
+            }],
+        });
+
+        let shadow_maps = Self {
+            settings: options.settings,
+
+            cascade_layout,
+            shadow_map,
+
+            resolution: options.resolution,
+            cascade_count,
+
+            cascade_views: Vec::new(),
+
+            uniform_buffer,
+            cascade_buffer,
+            cascade_stride,
+            cascade_bind_group,
+
+            splits: [0.0; MAX_CASCADES],
+            light_index: None,
+        };
+
+        // Until the first update nothing is shadowed.
+        state.queue.write_buffer(
+            &shadow_maps.uniform_buffer,
+            0,
+            bytemuck::bytes_of(&ShadowUniform {
+                light_index: NO_SHADOW_LIGHT,
+                ..bytemuck::Zeroable::zeroed()
+            }),
+        );
+
+        shadow_maps
+    }
+
+    /// Whether the shadow map has been allocated for a directional light.
+    pub fn is_allocated(&self) -> bool {
+        !self.cascade_views.is_empty()
+    }
+
+    fn allocate(&mut self, device: &wgpu::Device) {
+        self.shadow_map = DepthTextureFactory::new_shadow_map(
+            device,
+            self.resolution,
+            self.cascade_count,
+            "Shadow map",
+        );
+        self.cascade_views = (0..self.cascade_count)
+            .map(|layer| {
+                self.shadow_map
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor {
+                        label: Some("Shadow cascade"),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_array_layer: layer,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    })
+            })
+            .collect();
+    }
+
+    /// Fits the cascades to the part of the camera frustum within
+    /// [`ShadowSettings::max_distance`] and uploads them. Returns `true` when the shadow map was
+    /// allocated, bind groups holding [`ShadowMaps::shadow_map`] have to be recreated then.
+    ///
+    /// `view` is the camera's view matrix, e.g. [`crate::helpers::cameras::OrbitControls::get_view_matrix`]
+    /// when `camera` belongs to orbit controls.
+    pub(super) fn update(
+        &mut self,
+        state: &State,
+        camera: &PespectiveCamera,
+        view: glam::Mat4,
+        lights: &[Light],
+    ) -> bool {
+        puffin::profile_function!();
+
+        self.light_index = lights
+            .iter()
+            .position(|light| light.kind == LightKind::Directional);
+
+        let allocated = self.light_index.is_some() && !self.is_allocated();
+        if allocated {
+            self.allocate(&state.device);
+        }
+
+        let mut uniform = ShadowUniform {
+            cascade_count: self.cascade_count,
+            depth_bias: self.settings.depth_bias,
+            normal_bias: self.settings.normal_bias,
+            pcf_radius: self.settings.pcf_radius,
+            light_index: NO_SHADOW_LIGHT,
+            ..bytemuck::Zeroable::zeroed()
+        };
+
+        if let Some(light_index) = self.light_index {
+            let cascades = Self::fit_cascades(
+                camera,
+                view,
+                lights[light_index].direction,
+                &self.settings,
+                self.cascade_count,
+                self.resolution,
+            );
+
+            for (index, cascade) in cascades.iter().enumerate() {
+                self.splits[index] = cascade.split;
+                uniform.light_view_proj[index] = cascade.view_proj.to_cols_array();
+                uniform.splits[index] = cascade.split;
+                uniform.texel_sizes[index] = cascade.texel_size;
+
+                state.queue.write_buffer(
+                    &self.cascade_buffer,
+                    self.cascade_stride * index as u64,
+                    bytemuck::cast_slice(&cascade.view_proj.to_cols_array()),
+                );
+            }
+
+            uniform.light_index = light_index as u32;
+        }
+
+        state
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+
+        allocated
+    }
+
+    /// Adds a depth pass per cascade. `draw` runs once for each of them with the cascade bound at
+    /// group 1, it draws the shadow casters, e.g. with [`super::PbrPipeline::draw_shadow_mesh`].
+    pub fn add_passes<'a, F>(&'a self, graph: &mut FrameGraph<'a>, draw: F)
+    where
+        F: Fn(&mut wgpu::RenderPass<'a>) + 'a,
+    {
+        if self.light_index.is_none() {
+            return;
+        }
+
+        let draw = Rc::new(draw);
+        for (cascade, view) in self.cascade_views.iter().enumerate() {
+            let target = graph.import_view(view);
+            let offset = (self.cascade_stride * cascade as u64) as wgpu::DynamicOffset;
+            let draw = draw.clone();
+
+            graph.add_pass(
+                PassOptions {
+                    label: Some("Shadow Cascade Pass"),
+                    depth_attachment: Some(DepthAttachment::new(target, wgpu::LoadOp::Clear(1.0))),
+                    ..Default::default()
+                },
+                move |_, mut render_pass| {
+                    render_pass.set_bind_group(1, &self.cascade_bind_group, &[offset]);
+                    draw(&mut render_pass);
+                },
+            );
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.settings;
+
+        ui.add(egui::Slider::new(&mut settings.max_distance, 1.0..=500.0).text("Distance"));
+        ui.add(egui::Slider::new(&mut settings.split_lambda, 0.0..=1.0).text("Split lambda"));
+        ui.add(
+            egui::Slider::new(&mut settings.depth_bias, 0.0..=0.01)
+                .logarithmic(true)
+                .text("Depth bias"),
+        );
+        ui.add(egui::Slider::new(&mut settings.normal_bias, 0.0..=5.0).text("Normal bias"));
+        ui.add(egui::Slider::new(&mut settings.pcf_radius, 0.0..=4.0).text("PCF radius"));
+
+        let splits = &self.splits[..self.cascade_count as usize];
+        ui.label(format!("Splits: {:.1?}", splits));
+    }
+
+    /// Fits `cascade_count` cascades of a light shining along `direction` to the part of the
+    /// camera frustum within [`ShadowSettings::max_distance`]. `view` is the camera's view
+    /// matrix, see [`super::LightSet::update_shadows`].
+    pub fn fit_cascades(
+        camera: &PespectiveCamera,
+        view: glam::Mat4,
+        direction: glam::Vec3,
+        settings: &ShadowSettings,
+        cascade_count: u32,
+        resolution: u32,
+    ) -> Vec<Cascade> {
+        let direction = direction.try_normalize().unwrap_or(glam::Vec3::NEG_Y);
+        let cascade_count = cascade_count.clamp(1, MAX_CASCADES as u32);
+
+        let near = camera.near;
+        let far = camera.far.min(settings.max_distance).max(near);
+        let splits = Self::cascade_splits(near, far, cascade_count, settings.split_lambda);
+
+        // Corners of the whole frustum, the cascades slide along the rays between them.
+        let inverse_view_proj = (camera.get_perspective_matrix() * view).inverse();
+        let ndc_corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let near_corners =
+            ndc_corners.map(|(x, y)| inverse_view_proj.project_point3(glam::vec3(x, y, 0.0)));
+        let far_corners =
+            ndc_corners.map(|(x, y)| inverse_view_proj.project_point3(glam::vec3(x, y, 1.0)));
+        let depth_at = |distance: f32| (distance - camera.near) / (camera.far - camera.near);
+
+        let mut cascade_start = near;
+        splits[..cascade_count as usize]
+            .iter()
+            .map(|&cascade_end| {
+                let corners: Vec<glam::Vec3> = [depth_at(cascade_start), depth_at(cascade_end)]
+                    .iter()
+                    .flat_map(|&t| {
+                        near_corners
+                            .iter()
+                            .zip(&far_corners)
+                            .map(move |(near, far)| near.lerp(*far, t))
+                    })
+                    .collect();
+                cascade_start = cascade_end;
+
+                let (view_proj, texel_size) =
+                    Self::fit_cascade(&corners, direction, far, resolution);
+                Cascade {
+                    view_proj,
+                    split: cascade_end,
+                    texel_size,
+                }
+            })
+            .collect()
+    }
+
+    /// View space distances where each cascade ends, mixing the logarithmic and uniform schemes
+    /// with [`ShadowSettings::split_lambda`]. Entries past `count` are `far`.
+    pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> [f32; MAX_CASCADES] {
+        let mut splits = [far; MAX_CASCADES];
+        for (index, split) in splits.iter_mut().take(count as usize).enumerate() {
+            let fraction = (index + 1) as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+        }
+        splits
+    }
+
+    /// Orthographic light matrix around the bounding sphere of `corners`, which keeps its size
+    /// while the camera turns. The center snaps to whole texels so the edges don't shimmer.
+    fn fit_cascade(
+        corners: &[glam::Vec3],
+        direction: glam::Vec3,
+        caster_distance: f32,
+        resolution: u32,
+    ) -> (glam::Mat4, f32) {
+        let center = corners.iter().copied().sum::<glam::Vec3>() / corners.len() as f32;
+        let radius = corners
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+        let texel_size = 2.0 * radius / resolution as f32;
+
+        let up = if direction.y.abs() > 0.99 {
+            glam::Vec3::Z
+        } else {
+            glam::Vec3::Y
+        };
+
+        let rotation = glam::Mat4::look_to_lh(glam::Vec3::ZERO, direction, up);
+        let light_space_center = rotation.transform_point3(center);
+        let snapped = glam::vec3(
+            (light_space_center.x / texel_size).floor() * texel_size,
+            (light_space_center.y / texel_size).floor() * texel_size,
+            light_space_center.z,
+        );
+        let center = rotation.inverse().transform_point3(snapped);
+
+        // Pulled back so casters outside the camera frustum still land in the map.
+        let eye = center - direction * (radius + caster_distance);
+        let light_view = glam::Mat4::look_to_lh(eye, direction, up);
+        let projection = glam::Mat4::orthographic_lh(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            0.0,
+            2.0 * radius + caster_distance,
+        );
+
+        (projection * light_view, texel_size)
+    }
+}
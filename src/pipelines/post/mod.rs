@@ -0,0 +1,852 @@
+//! HDR post-processing: bloom, exposure, tonemapping, color grading, vignette and FXAA.
+//!
+//! The scene is drawn into [`SCENE_FORMAT`] targets from [`PostProcessing::scene_targets`] and
+//! [`PostProcessing::add_passes`] resolves it into the backbuffer. Pipelines drawing the scene
+//! have to target [`SCENE_FORMAT`], set [`crate::state::StateOptions::scene_format`] to it so
+//! [`crate::factories::RenderPipelineFactory`] picks it up.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use wgpu::util::DeviceExt;
+
+use crate::factories::render_pipeline::BlendConfig;
+use crate::factories::texture::{SamplerOptions, Texture2dOptions, TextureBundle};
+use crate::factories::{RenderPipelineFactory, Texture2dFactory};
+use crate::frame_graph::{
+    ColorAttachment, FrameGraph, FrameTargets, PassOptions, ResourceId, TransientTextureOptions,
+};
+#[cfg(feature = "hot-reload")]
+use crate::helpers::hot_reload::ShaderHotReload;
+use crate::helpers::hot_reload::ShaderSource;
+use crate::pipelines;
+use crate::state::{Size, State};
+use crate::Error;
+
+pub const SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Clamps the scene, only useful to inspect the raw values.
+    None,
+    Aces,
+    Uncharted2,
+    AgX,
+}
+
+impl Tonemapper {
+    const ALL: [Tonemapper; 4] = [
+        Tonemapper::None,
+        Tonemapper::Aces,
+        Tonemapper::Uncharted2,
+        Tonemapper::AgX,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Tonemapper::None => "None",
+            Tonemapper::Aces => "ACES",
+            Tonemapper::Uncharted2 => "Uncharted 2",
+            Tonemapper::AgX => "AgX",
+        }
+    }
+}
+
+/// Parameters of the chain, they can be changed every frame.
+#[derive(Clone, Copy, Debug)]
+pub struct PostSettings {
+    /// In stops, the scene is multiplied by `2^exposure` before tonemapping.
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+
+    pub bloom: bool,
+    pub bloom_intensity: f32,
+    /// Pixels brighter than this contribute to the bloom.
+    pub bloom_threshold: f32,
+    /// Softens the threshold, as a fraction of it.
+    pub bloom_knee: f32,
+
+    pub fxaa: bool,
+
+    pub vignette_intensity: f32,
+    /// Distance from the center where the vignette is fully dark, 1 is the corners.
+    pub vignette_radius: f32,
+    pub vignette_smoothness: f32,
+
+    /// Blend between the ungraded image and the one from the LUT, see [`PostProcessing::set_lut`].
+    pub lut_strength: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            tonemapper: Tonemapper::Aces,
+
+            bloom: true,
+            bloom_intensity: 0.05,
+            bloom_threshold: 1.0,
+            bloom_knee: 0.5,
+
+            fxaa: true,
+
+            vignette_intensity: 0.0,
+            vignette_radius: 1.2,
+            vignette_smoothness: 0.8,
+
+            lut_strength: 0.0,
+        }
+    }
+}
+
+pub struct PostProcessingOptions {
+    /// Number of downsampled levels the bloom is spread over, the first one is half the scene size.
+    pub bloom_levels: u32,
+    pub settings: PostSettings,
+}
+
+impl Default for PostProcessingOptions {
+    fn default() -> Self {
+        Self {
+            bloom_levels: 5,
+            settings: PostSettings::default(),
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostUniform {
+    exposure: f32,
+    bloom_intensity: f32,
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    tonemapper: u32,
+    lut_strength: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    encode_srgb: u32,
+    _pad: [f32; 2],
+}
+
+/// Bind groups of the passes by the views they bind, so they aren't created every frame. Like
+/// the [`crate::frame_graph::TexturePool`], entries no pass used during the previous frame are
+/// dropped, which releases the views of textures the pool dropped.
+struct BindGroupCache<K> {
+    entries: HashMap<K, CachedBindGroup>,
+}
+
+struct CachedBindGroup {
+    bind_group: wgpu::BindGroup,
+    used_this_frame: bool,
+}
+
+impl<K: Hash + Eq> BindGroupCache<K> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    fn begin_frame(&mut self) {
+        self.entries.retain(|_, entry| entry.used_this_frame);
+        for entry in self.entries.values_mut() {
+            entry.used_this_frame = false;
+        }
+    }
+
+    fn get_or_create(
+        &mut self,
+        key: K,
+        create: impl FnOnce() -> wgpu::BindGroup,
+    ) -> wgpu::BindGroup {
+        let entry = self.entries.entry(key).or_insert_with(|| CachedBindGroup {
+            bind_group: create(),
+            used_this_frame: false,
+        });
+        entry.used_this_frame = true;
+        entry.bind_group.clone()
+    }
+}
+
+// The shader sources are only read back when reloading.
+#[cfg_attr(not(feature = "hot-reload"), allow(dead_code))]
+pub struct PostProcessing {
+    pub settings: PostSettings,
+
+    bloom_levels: u32,
+    output_format: wgpu::TextureFormat,
+
+    uniform_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    black_texture: TextureBundle,
+    lut: TextureBundle,
+
+    bloom_layout: wgpu::BindGroupLayout,
+    composite_layout: wgpu::BindGroupLayout,
+    // Passes only hold `&self`.
+    bloom_bind_groups: RefCell<BindGroupCache<wgpu::TextureView>>,
+    // By the scene and bloom views.
+    composite_bind_groups: RefCell<BindGroupCache<(wgpu::TextureView, wgpu::TextureView)>>,
+
+    prefilter_pipeline: wgpu::RenderPipeline,
+    downsample_pipeline: wgpu::RenderPipeline,
+    upsample_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    fxaa_pipeline: wgpu::RenderPipeline,
+
+    // Kept to rebuild the pipelines when the shaders reload.
+    bloom_shader: ShaderSource,
+    composite_shader: ShaderSource,
+    fxaa_shader: ShaderSource,
+}
+
+impl PostProcessing {
+    pub fn new(state: &State, options: PostProcessingOptions) -> Self {
+        puffin::profile_function!();
+        let State { device, .. } = state;
+
+        // The chain ends in the backbuffer, which always has the surface format.
+        let output_format = state.config.format;
+
+        let bloom_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post bloom layout"),
+            entries: &[
+                Self::texture_entry(0, wgpu::TextureViewDimension::D2),
+                Self::sampler_entry(1),
+                Self::uniform_entry(2),
+            ],
+        });
+
+        let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post composite layout"),
+            entries: &[
+                Self::texture_entry(0, wgpu::TextureViewDimension::D2),
+                Self::sampler_entry(1),
+                Self::texture_entry(2, wgpu::TextureViewDimension::D2),
+                Self::texture_entry(3, wgpu::TextureViewDimension::D3),
+                Self::uniform_entry(4),
+            ],
+        });
+
+        let bloom_shader = crate::include_shader!("shader_bloom.wgsl");
+        let composite_shader = crate::include_shader!("shader_composite.wgsl");
+        let fxaa_shader = crate::include_shader!("shader_fxaa.wgsl");
+
+        let (prefilter_pipeline, downsample_pipeline, upsample_pipeline) =
+            Self::create_bloom_pipelines(state, &bloom_shader.create_module(device), &bloom_layout);
+        let composite_pipeline = Self::fullscreen_pipeline(
+            state,
+            "Post composite",
+            &composite_shader.create_module(device),
+            "fs_main",
+            output_format,
+            BlendConfig::None,
+            &composite_layout,
+        );
+        let fxaa_pipeline = Self::fullscreen_pipeline(
+            state,
+            "Post FXAA",
+            &fxaa_shader.create_module(device),
+            "fs_main",
+            output_format,
+            BlendConfig::None,
+            &bloom_layout,
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Bound in place of the bloom while it is disabled.
+        let black_texture = Texture2dFactory::new_with_options(
+            state,
+            [1, 1],
+            Texture2dOptions {
+                format: SCENE_FORMAT,
+                label: Some("Post black texture"),
+                ..Default::default()
+            },
+            SamplerOptions::default(),
+            &[0; 8],
+        );
+
+        let lut = Self::create_lut(
+            state,
+            IDENTITY_LUT_SIZE,
+            &Self::identity_lut(IDENTITY_LUT_SIZE),
+        );
+
+        Self {
+            settings: options.settings,
+
+            bloom_levels: options.bloom_levels,
+            output_format,
+
+            uniform_buffer: pipelines::create_uniform_buffer::<PostUniform>(1, device),
+            sampler,
+            black_texture,
+            lut,
+
+            bloom_layout,
+            composite_layout,
+            bloom_bind_groups: RefCell::new(BindGroupCache::new()),
+            composite_bind_groups: RefCell::new(BindGroupCache::new()),
+
+            prefilter_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            fxaa_pipeline,
+
+            bloom_shader,
+            composite_shader,
+            fxaa_shader,
+        }
+    }
+
+    /// Prefilter, downsample and upsample pipelines of the bloom.
+    fn create_bloom_pipelines(
+        state: &State,
+        shader_module: &wgpu::ShaderModule,
+        bloom_layout: &wgpu::BindGroupLayout,
+    ) -> (
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+        wgpu::RenderPipeline,
+    ) {
+        let prefilter_pipeline = Self::fullscreen_pipeline(
+            state,
+            "Post bloom prefilter",
+            shader_module,
+            "fs_prefilter",
+            SCENE_FORMAT,
+            BlendConfig::None,
+            bloom_layout,
+        );
+        let downsample_pipeline = Self::fullscreen_pipeline(
+            state,
+            "Post bloom downsample",
+            shader_module,
+            "fs_downsample",
+            SCENE_FORMAT,
+            BlendConfig::None,
+            bloom_layout,
+        );
+        let upsample_pipeline = Self::fullscreen_pipeline(
+            state,
+            "Post bloom upsample",
+            shader_module,
+            "fs_upsample",
+            SCENE_FORMAT,
+            BlendConfig::Custom(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            }),
+            bloom_layout,
+        );
+
+        (prefilter_pipeline, downsample_pipeline, upsample_pipeline)
+    }
+
+    fn fullscreen_pipeline(
+        state: &State,
+        label: &'static str,
+        shader_module: &wgpu::ShaderModule,
+        entry: &'static str,
+        format: wgpu::TextureFormat,
+        blend: BlendConfig,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let mut pipeline_factory = RenderPipelineFactory::new();
+        pipeline_factory.set_label(label);
+        pipeline_factory.set_frag_entry(Some(entry));
+        pipeline_factory.set_color_target_format(Some(format));
+        pipeline_factory.set_sample_count(Some(1));
+        pipeline_factory.set_blend_config(blend);
+        pipeline_factory.create_render_pipeline(state, shader_module, &[Some(layout)])
+    }
+
+    /// Rebuilds the pipelines whose shader file changed, see [`ShaderHotReload`].
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, state: &State, hot_reload: &mut ShaderHotReload) {
+        let bloom = hot_reload.reload(state, &self.bloom_shader, |module| {
+            Self::create_bloom_pipelines(state, module, &self.bloom_layout)
+        });
+        if let Some((prefilter_pipeline, downsample_pipeline, upsample_pipeline)) = bloom {
+            self.prefilter_pipeline = prefilter_pipeline;
+            self.downsample_pipeline = downsample_pipeline;
+            self.upsample_pipeline = upsample_pipeline;
+        }
+
+        let composite = hot_reload.reload(state, &self.composite_shader, |module| {
+            Self::fullscreen_pipeline(
+                state,
+                "Post composite",
+                module,
+                "fs_main",
+                self.output_format,
+                BlendConfig::None,
+                &self.composite_layout,
+            )
+        });
+        if let Some(composite_pipeline) = composite {
+            self.composite_pipeline = composite_pipeline;
+        }
+
+        let fxaa = hot_reload.reload(state, &self.fxaa_shader, |module| {
+            Self::fullscreen_pipeline(
+                state,
+                "Post FXAA",
+                module,
+                "fs_main",
+                self.output_format,
+                BlendConfig::None,
+                &self.bloom_layout,
+            )
+        });
+        if let Some(fxaa_pipeline) = fxaa {
+            self.fxaa_pipeline = fxaa_pipeline;
+        }
+    }
+
+    /// Declares the HDR color targets the scene is drawn into, multisampled like the state.
+    ///
+    /// `backbuffer` of the returned targets is the resolved scene that goes into
+    /// [`Self::add_passes`], the depth target is the state's.
+    pub fn scene_targets(&self, state: &State, graph: &mut FrameGraph) -> FrameTargets {
+        let size = state.window_size;
+        let scene = graph.create_texture(TransientTextureOptions {
+            label: Some("HDR Scene"),
+            size,
+            format: SCENE_FORMAT,
+            sample_count: 1,
+        });
+
+        let sample_count = state.get_sample_count();
+        let multisampled = (sample_count > 1).then(|| {
+            graph.create_texture(TransientTextureOptions {
+                label: Some("Multisampled HDR Scene"),
+                size,
+                format: SCENE_FORMAT,
+                sample_count,
+            })
+        });
+
+        FrameTargets {
+            backbuffer: scene,
+            multisampled,
+            depth: graph.targets().depth,
+        }
+    }
+
+    /// Adds the passes that take the HDR `scene` to the backbuffer.
+    pub fn add_passes<'a>(
+        &'a self,
+        state: &'a State,
+        graph: &mut FrameGraph<'a>,
+        scene: ResourceId,
+    ) {
+        pipelines::write_uniform_buffer(
+            &[self.uniform()],
+            &self.uniform_buffer,
+            &state.queue,
+            &state.device,
+        );
+        self.bloom_bind_groups.borrow_mut().begin_frame();
+        self.composite_bind_groups.borrow_mut().begin_frame();
+
+        let size = state.window_size;
+        let bloom = (self.settings.bloom && self.bloom_levels > 0)
+            .then(|| self.add_bloom_passes(state, graph, scene, size));
+
+        let output = graph.targets().backbuffer;
+        let composite_target = if self.settings.fxaa {
+            graph.create_texture(TransientTextureOptions {
+                label: Some("Tonemapped Scene"),
+                size,
+                format: self.output_format,
+                sample_count: 1,
+            })
+        } else {
+            output
+        };
+
+        graph.add_pass(
+            PassOptions {
+                label: Some("Post Composite Pass"),
+                color_attachments: vec![ColorAttachment::new(
+                    composite_target,
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                )],
+                reads: std::iter::once(scene).chain(bloom).collect(),
+                ..Default::default()
+            },
+            move |resources, mut render_pass| {
+                let bloom_view = match bloom {
+                    Some(bloom) => resources.view(bloom),
+                    None => &self.black_texture.view,
+                };
+
+                let scene_view = resources.view(scene);
+                let bind_group = self.composite_bind_groups.borrow_mut().get_or_create(
+                    (scene_view.clone(), bloom_view.clone()),
+                    || {
+                        state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("Post composite bind group"),
+                            layout: &self.composite_layout,
+                            entries: &[
+                                wgpu::BindGroupEntry {
+                                    binding: 0,
+                                    resource: wgpu::BindingResource::TextureView(scene_view),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 1,
+                                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 2,
+                                    resource: wgpu::BindingResource::TextureView(bloom_view),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 3,
+                                    resource: wgpu::BindingResource::TextureView(&self.lut.view),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 4,
+                                    resource: self.uniform_buffer.as_entire_binding(),
+                                },
+                            ],
+                        })
+                    },
+                );
+
+                render_pass.set_pipeline(&self.composite_pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            },
+        );
+
+        if self.settings.fxaa {
+            self.add_fullscreen_pass(
+                state,
+                graph,
+                "Post FXAA Pass",
+                &self.fxaa_pipeline,
+                composite_target,
+                ColorAttachment::new(output, wgpu::LoadOp::Clear(wgpu::Color::BLACK)),
+            );
+        }
+    }
+
+    /// Returns the largest bloom level, with every smaller level added on top.
+    fn add_bloom_passes<'a>(
+        &'a self,
+        state: &'a State,
+        graph: &mut FrameGraph<'a>,
+        scene: ResourceId,
+        size: Size,
+    ) -> ResourceId {
+        let levels: Vec<ResourceId> = (0..self.bloom_levels)
+            .map(|level| {
+                graph.create_texture(TransientTextureOptions {
+                    label: Some("Bloom level"),
+                    size: Size::new(
+                        (size.width >> (level + 1)).max(1),
+                        (size.height >> (level + 1)).max(1),
+                    ),
+                    format: SCENE_FORMAT,
+                    sample_count: 1,
+                })
+            })
+            .collect();
+
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+
+        self.add_fullscreen_pass(
+            state,
+            graph,
+            "Post Bloom Prefilter Pass",
+            &self.prefilter_pipeline,
+            scene,
+            ColorAttachment::new(levels[0], clear),
+        );
+
+        for pair in levels.windows(2) {
+            self.add_fullscreen_pass(
+                state,
+                graph,
+                "Post Bloom Downsample Pass",
+                &self.downsample_pipeline,
+                pair[0],
+                ColorAttachment::new(pair[1], clear),
+            );
+        }
+
+        for pair in levels.windows(2).rev() {
+            self.add_fullscreen_pass(
+                state,
+                graph,
+                "Post Bloom Upsample Pass",
+                &self.upsample_pipeline,
+                pair[1],
+                ColorAttachment::new(pair[0], wgpu::LoadOp::Load),
+            );
+        }
+
+        levels[0]
+    }
+
+    /// A pass drawing a fullscreen triangle with a pipeline using `bloom_layout`.
+    fn add_fullscreen_pass<'a>(
+        &'a self,
+        state: &'a State,
+        graph: &mut FrameGraph<'a>,
+        label: &'static str,
+        pipeline: &'a wgpu::RenderPipeline,
+        source: ResourceId,
+        target: ColorAttachment,
+    ) {
+        graph.add_pass(
+            PassOptions {
+                label: Some(label),
+                color_attachments: vec![target],
+                reads: vec![source],
+                ..Default::default()
+            },
+            move |resources, mut render_pass| {
+                let source_view = resources.view(source);
+                let bind_group =
+                    self.bloom_bind_groups
+                        .borrow_mut()
+                        .get_or_create(source_view.clone(), || {
+                            state.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                                label: Some("Post fullscreen bind group"),
+                                layout: &self.bloom_layout,
+                                entries: &[
+                                    wgpu::BindGroupEntry {
+                                        binding: 0,
+                                        resource: wgpu::BindingResource::TextureView(source_view),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 1,
+                                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                                    },
+                                    wgpu::BindGroupEntry {
+                                        binding: 2,
+                                        resource: self.uniform_buffer.as_entire_binding(),
+                                    },
+                                ],
+                            })
+                        });
+
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+            },
+        );
+    }
+
+    /// Number of bind groups kept for the views of the last frames.
+    pub fn bind_group_count(&self) -> usize {
+        self.bloom_bind_groups.borrow().entries.len()
+            + self.composite_bind_groups.borrow().entries.len()
+    }
+
+    /// Replaces the color grading LUT, the strength is set with [`PostSettings::lut_strength`].
+    ///
+    /// `image` is a horizontal strip of `size` slices of `size` x `size` pixels, blue selects the
+    /// slice, red goes left to right and green top to bottom. This is the layout most color
+    /// grading tools export, e.g. a 1024x32 PNG for a 32 entry LUT.
+    pub fn set_lut(&mut self, state: &State, image: &image::RgbaImage) -> Result<(), Error> {
+        let size = image.height();
+        if size < 2 || image.width() != size * size {
+            return Err(format!(
+                "a LUT strip of height {} must be {} pixels wide, got {}",
+                size,
+                size * size,
+                image.width()
+            )
+            .into());
+        }
+
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    data.extend_from_slice(&image.get_pixel(blue * size + red, green).0);
+                }
+            }
+        }
+
+        self.lut = Self::create_lut(state, size, &data);
+        // They bind the previous LUT.
+        self.composite_bind_groups.get_mut().entries.clear();
+        Ok(())
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let settings = &mut self.settings;
+
+        ui.add(egui::Slider::new(&mut settings.exposure, -8.0..=8.0).text("Exposure"));
+
+        egui::ComboBox::from_label("Tonemapper")
+            .selected_text(settings.tonemapper.label())
+            .show_ui(ui, |ui| {
+                for tonemapper in Tonemapper::ALL {
+                    ui.selectable_value(&mut settings.tonemapper, tonemapper, tonemapper.label());
+                }
+            });
+
+        ui.checkbox(&mut settings.bloom, "Bloom");
+        ui.add_enabled_ui(settings.bloom, |ui| {
+            ui.add(egui::Slider::new(&mut settings.bloom_intensity, 0.0..=1.0).text("Intensity"));
+            ui.add(egui::Slider::new(&mut settings.bloom_threshold, 0.0..=10.0).text("Threshold"));
+            ui.add(egui::Slider::new(&mut settings.bloom_knee, 0.0..=1.0).text("Knee"));
+        });
+
+        ui.checkbox(&mut settings.fxaa, "FXAA");
+
+        ui.add(egui::Slider::new(&mut settings.vignette_intensity, 0.0..=1.0).text("Vignette"));
+        ui.add_enabled_ui(settings.vignette_intensity > 0.0, |ui| {
+            ui.add(egui::Slider::new(&mut settings.vignette_radius, 0.0..=1.5).text("Radius"));
+            ui.add(
+                egui::Slider::new(&mut settings.vignette_smoothness, 0.0..=1.0).text("Smoothness"),
+            );
+        });
+
+        ui.add(egui::Slider::new(&mut settings.lut_strength, 0.0..=1.0).text("LUT"));
+    }
+
+    fn uniform(&self) -> PostUniform {
+        let settings = &self.settings;
+        PostUniform {
+            exposure: settings.exposure.exp2(),
+            bloom_intensity: if settings.bloom {
+                settings.bloom_intensity
+            } else {
+                0.0
+            },
+            bloom_threshold: settings.bloom_threshold,
+            bloom_knee: settings.bloom_knee,
+            tonemapper: match settings.tonemapper {
+                Tonemapper::None => 0,
+                Tonemapper::Aces => 1,
+                Tonemapper::Uncharted2 => 2,
+                Tonemapper::AgX => 3,
+            },
+            lut_strength: settings.lut_strength,
+            vignette_intensity: settings.vignette_intensity,
+            vignette_radius: settings.vignette_radius,
+            vignette_smoothness: settings.vignette_smoothness,
+            encode_srgb: u32::from(!self.output_format.is_srgb()),
+            _pad: [0.0; 2],
+        }
+    }
+
+    fn identity_lut(size: u32) -> Vec<u8> {
+        let scale = 255.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+        for blue in 0..size {
+            for green in 0..size {
+                for red in 0..size {
+                    data.extend_from_slice(&[
+                        (red as f32 * scale).round() as u8,
+                        (green as f32 * scale).round() as u8,
+                        (blue as f32 * scale).round() as u8,
+                        255,
+                    ]);
+                }
+            }
+        }
+        data
+    }
+
+    fn create_lut(state: &State, size: u32, data: &[u8]) -> TextureBundle {
+        let texture = state.device.create_texture_with_data(
+            &state.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Post LUT"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::default(),
+            data,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = state.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post LUT sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        TextureBundle {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    fn texture_entry(
+        binding: u32,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        }
+    }
+
+    fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        }
+    }
+
+    fn uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+struct PostUniform {
+    exposure: f32,
+    bloom_intensity: f32,
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    tonemapper: u32,
+    lut_strength: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    encode_srgb: u32,
+    _pad: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> post: PostUniform;
+
+// Four bilinear taps, each one averages a 2x2 block of the source.
+fn downsample(uv: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+
+    var color = textureSample(source_texture, source_sampler, uv + texel * vec2(-1.0, -1.0)).rgb;
+    color += textureSample(source_texture, source_sampler, uv + texel * vec2(1.0, -1.0)).rgb;
+    color += textureSample(source_texture, source_sampler, uv + texel * vec2(-1.0, 1.0)).rgb;
+    color += textureSample(source_texture, source_sampler, uv + texel * vec2(1.0, 1.0)).rgb;
+    return color * 0.25;
+}
+
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Keeps a single bright pixel from blowing up the whole chain.
+    let color = min(downsample(in.uv), vec3(65000.0));
+
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = max(post.bloom_threshold * post.bloom_knee, 0.0001);
+    var soft = clamp(brightness - post.bloom_threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+
+    let contribution = max(soft, brightness - post.bloom_threshold) / max(brightness, 0.0001);
+    return vec4(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4(downsample(in.uv), 1.0);
+}
+
+// 3x3 tent filter, the result is added on top of the next larger level.
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+
+    var color = textureSample(source_texture, source_sampler, in.uv).rgb * 4.0;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(-1.0, 0.0)).rgb * 2.0;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(1.0, 0.0)).rgb * 2.0;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(0.0, -1.0)).rgb * 2.0;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(0.0, 1.0)).rgb * 2.0;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(-1.0, -1.0)).rgb;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(1.0, -1.0)).rgb;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(-1.0, 1.0)).rgb;
+    color += textureSample(source_texture, source_sampler, in.uv + texel * vec2(1.0, 1.0)).rgb;
+    return vec4(color / 16.0, 1.0);
+}
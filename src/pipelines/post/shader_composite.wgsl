@@ -0,0 +1,100 @@
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+struct PostUniform {
+    exposure: f32,
+    bloom_intensity: f32,
+    bloom_threshold: f32,
+    bloom_knee: f32,
+    tonemapper: u32,
+    lut_strength: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    encode_srgb: u32,
+    _pad: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var linear_sampler: sampler;
+@group(0) @binding(2)
+var bloom_texture: texture_2d<f32>;
+@group(0) @binding(3)
+var lut_texture: texture_3d<f32>;
+@group(0) @binding(4)
+var<uniform> post: PostUniform;
+
+const TONEMAPPER_ACES: u32 = 1u;
+const TONEMAPPER_UNCHARTED2: u32 = 2u;
+const TONEMAPPER_AGX: u32 = 3u;
+
+#include "tonemapping.wgsl"
+
+fn tonemap(hdr: vec3<f32>) -> vec3<f32> {
+    switch post.tonemapper {
+        case TONEMAPPER_ACES: {
+            return aces(hdr);
+        }
+        case TONEMAPPER_UNCHARTED2: {
+            return uncharted2(hdr);
+        }
+        case TONEMAPPER_AGX: {
+            return agx(hdr);
+        }
+        default: {
+            return hdr;
+        }
+    }
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let higher = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    let lower = color * 12.92;
+    return select(higher, lower, color < vec3(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let higher = pow((color + 0.055) / 1.055, vec3(2.4));
+    let lower = color / 12.92;
+    return select(higher, lower, color < vec3(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    var color = textureLoad(scene_texture, vec2<i32>(in.clip_position.xy), 0).rgb;
+    color += textureSampleLevel(bloom_texture, linear_sampler, in.uv, 0.0).rgb * post.bloom_intensity;
+
+    color = saturate(tonemap(color * post.exposure));
+
+    // The LUT is indexed and stored in sRGB, like the strips color grading tools export.
+    let lut_size = f32(textureDimensions(lut_texture).x);
+    let lut_coords = linear_to_srgb(color) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    let graded = srgb_to_linear(textureSampleLevel(lut_texture, linear_sampler, lut_coords, 0.0).rgb);
+    color = mix(color, graded, post.lut_strength);
+
+    // 0 in the center and 1 in the corners.
+    let distance = length(in.uv - 0.5) * 1.41421356;
+    let smoothness = max(post.vignette_smoothness, 0.0001);
+    let vignette = 1.0 - smoothstep(post.vignette_radius - smoothness, post.vignette_radius, distance);
+    color *= mix(1.0, vignette, post.vignette_intensity);
+
+    if post.encode_srgb != 0u {
+        color = linear_to_srgb(color);
+    }
+
+    return vec4(color, 1.0);
+}
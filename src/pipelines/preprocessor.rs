@@ -0,0 +1,360 @@
+//! A small WGSL preprocessor for sharing code between shaders.
+//!
+//! Directives start a line with `#`:
+//!
+//! - `#include "camera.wgsl"` pastes a chunk registered with [`ShaderPreprocessor::add_chunk`] or
+//!   one of the [`BUILTIN_CHUNKS`]. Each chunk is pasted once, later includes are skipped.
+//! - `#define NAME` and `#define NAME value`, the value replaces `NAME` in the following lines.
+//! - `#undef NAME`, `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif`.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Chunks every [`ShaderPreprocessor`] starts with.
+///
+/// - `camera.wgsl`: `CameraUniform`, the layout of [`super::ViewUniform`]. Define
+///   `CAMERA_VIEW_PROJ_ONLY` when only its first matrix is bound.
+/// - `constants.wgsl`: `PI`.
+/// - `pbr_material.wgsl`: `MaterialUniform`, the layout of a
+///   [`PbrMaterial`](super::pbr::PbrMaterial)'s uniform, and the `ALPHA_*` modes.
+/// - `sampling.wgsl`: Hammersley points and GGX importance sampling.
+/// - `sky_params.wgsl`: `SkyParams`, the layout of the sky's
+///   [`params_buffer`](super::sky::SkyRenderer::params_buffer), and `sky_direction`.
+/// - `spherical_harmonics.wgsl`: the band 2 basis and its evaluation, see
+///   [`SkyRenderer::irradiance_sh`](super::sky::SkyRenderer::irradiance_sh).
+/// - `tonemapping.wgsl`: the `aces`, `uncharted2` and `agx` curves.
+pub const BUILTIN_CHUNKS: &[(&str, &str)] = &[
+    ("camera.wgsl", include_str!("shaders/camera.wgsl")),
+    ("constants.wgsl", include_str!("shaders/constants.wgsl")),
+    (
+        "pbr_material.wgsl",
+        include_str!("shaders/pbr_material.wgsl"),
+    ),
+    ("sampling.wgsl", include_str!("shaders/sampling.wgsl")),
+    ("sky_params.wgsl", include_str!("shaders/sky_params.wgsl")),
+    (
+        "spherical_harmonics.wgsl",
+        include_str!("shaders/spherical_harmonics.wgsl"),
+    ),
+    ("tonemapping.wgsl", include_str!("shaders/tonemapping.wgsl")),
+];
+
+/// Directory the [`BUILTIN_CHUNKS`] are embedded from, hot reload reads them from there.
+const BUILTIN_CHUNK_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/pipelines/shaders");
+
+/// Resolves includes and conditionals, see the [module docs](self).
+#[derive(Clone, Debug)]
+pub struct ShaderPreprocessor {
+    chunks: HashMap<String, Cow<'static, str>>,
+    /// Files the chunks were embedded from, when known.
+    chunk_paths: HashMap<String, PathBuf>,
+    defines: HashMap<String, String>,
+}
+
+impl Default for ShaderPreprocessor {
+    fn default() -> Self {
+        let mut preprocessor = Self {
+            chunks: HashMap::new(),
+            chunk_paths: HashMap::new(),
+            defines: HashMap::new(),
+        };
+        for (name, source) in BUILTIN_CHUNKS {
+            preprocessor.add_chunk_file(*name, *source, Path::new(BUILTIN_CHUNK_DIR).join(name));
+        }
+        preprocessor
+    }
+}
+
+impl ShaderPreprocessor {
+    /// Starts with the [`BUILTIN_CHUNKS`] and no defines.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `source` available to `#include "name"`, replacing a chunk with the same name.
+    pub fn add_chunk(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<Cow<'static, str>>,
+    ) -> &mut Self {
+        let name = name.into();
+        self.chunk_paths.remove(&name);
+        self.chunks.insert(name, source.into());
+        self
+    }
+
+    /// Like [`ShaderPreprocessor::add_chunk`], and remembers the file `source` was embedded from
+    /// so shader hot reload reads the chunk from there and watches it.
+    pub fn add_chunk_file(
+        &mut self,
+        name: impl Into<String>,
+        source: impl Into<Cow<'static, str>>,
+        path: impl Into<PathBuf>,
+    ) -> &mut Self {
+        let name = name.into();
+        self.chunk_paths.insert(name.clone(), path.into());
+        self.chunks.insert(name, source.into());
+        self
+    }
+
+    /// The file of a chunk added with [`ShaderPreprocessor::add_chunk_file`].
+    pub fn chunk_path(&self, name: &str) -> Option<&Path> {
+        self.chunk_paths.get(name).map(PathBuf::as_path)
+    }
+
+    /// Replaces every chunk that has a file with what the file contains now.
+    pub fn read_chunk_files(&mut self) -> std::io::Result<()> {
+        for (name, path) in &self.chunk_paths {
+            let source = std::fs::read_to_string(path)?;
+            self.chunks.insert(name.clone(), source.into());
+        }
+        Ok(())
+    }
+
+    /// Defines `name` before the first line, like `#define name value`. Pass an empty value to
+    /// only make `#ifdef name` true.
+    pub fn define(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn undefine(&mut self, name: &str) -> &mut Self {
+        self.defines.remove(name);
+        self
+    }
+
+    /// Expands `source`, `file` names it in errors and in [`ProcessedShader::original_location`].
+    pub fn process(&self, file: &str, source: &str) -> Result<ProcessedShader, PreprocessError> {
+        let mut context = Context {
+            chunks: &self.chunks,
+            defines: self.defines.clone(),
+            included: HashSet::new(),
+            shader: ProcessedShader::default(),
+        };
+        context.process_file(file, source)?;
+
+        Ok(context.shader)
+    }
+}
+
+/// Output of [`ShaderPreprocessor::process`].
+#[derive(Clone, Debug, Default)]
+pub struct ProcessedShader {
+    pub source: String,
+    files: Vec<String>,
+    /// Index into `files` and 1-based line of each line of `source`.
+    lines: Vec<(usize, u32)>,
+}
+
+impl ProcessedShader {
+    /// File and line a 1-based line of [`ProcessedShader::source`] came from, e.g. to point
+    /// compiler errors at the included chunk instead of the expanded source.
+    pub fn original_location(&self, line_number: u32) -> Option<(&str, u32)> {
+        let (file, line) = *self.lines.get((line_number as usize).checked_sub(1)?)?;
+        Some((&self.files[file], line))
+    }
+
+    /// Names of the chunks that were included, in the order they were first included.
+    pub fn included_chunks(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().skip(1).map(String::as_str)
+    }
+
+    fn push_line(&mut self, file: usize, line: u32, text: &str) {
+        self.source.push_str(text);
+        self.source.push('\n');
+        self.lines.push((file, line));
+    }
+}
+
+#[derive(Debug)]
+pub enum PreprocessErrorKind {
+    /// `#include` of a chunk that wasn't registered.
+    UnknownInclude(String),
+    UnknownDirective(String),
+    /// A directive without the name or path it needs.
+    MissingArgument(&'static str),
+    UnexpectedElse,
+    UnexpectedEndif,
+    /// `#ifdef` or `#ifndef` without an `#endif` before the end of the file.
+    UnterminatedIf,
+}
+
+/// A directive that can't be expanded, at its line in the original file.
+#[derive(Debug)]
+pub struct PreprocessError {
+    pub file: String,
+    pub line: u32,
+    pub kind: PreprocessErrorKind,
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.file, self.line)?;
+        match &self.kind {
+            PreprocessErrorKind::UnknownInclude(name) => write!(f, "unknown include \"{}\"", name),
+            PreprocessErrorKind::UnknownDirective(directive) => {
+                write!(f, "unknown directive #{}", directive)
+            }
+            PreprocessErrorKind::MissingArgument(directive) => {
+                write!(f, "#{} is missing its argument", directive)
+            }
+            PreprocessErrorKind::UnexpectedElse => write!(f, "#else without #ifdef or #ifndef"),
+            PreprocessErrorKind::UnexpectedEndif => write!(f, "#endif without #ifdef or #ifndef"),
+            PreprocessErrorKind::UnterminatedIf => write!(f, "#ifdef or #ifndef without #endif"),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+/// An open `#ifdef` or `#ifndef`.
+struct Conditional {
+    line: u32,
+    /// Whether the lines of the current branch are kept.
+    active: bool,
+    /// Whether the enclosing block is kept, an `#else` can't activate lines in a dropped block.
+    parent_active: bool,
+    seen_else: bool,
+}
+
+struct Context<'a> {
+    chunks: &'a HashMap<String, Cow<'static, str>>,
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+    shader: ProcessedShader,
+}
+
+impl<'a> Context<'a> {
+    fn process_file(&mut self, file: &str, source: &str) -> Result<(), PreprocessError> {
+        let file_index = self.shader.files.len();
+        self.shader.files.push(file.to_string());
+
+        let error = |line: u32, kind: PreprocessErrorKind| PreprocessError {
+            file: file.to_string(),
+            line,
+            kind,
+        };
+
+        let mut conditionals: Vec<Conditional> = Vec::new();
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index as u32 + 1;
+            let active = conditionals
+                .last()
+                .is_none_or(|conditional| conditional.active);
+
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    let expanded = self.expand_defines(text);
+                    self.shader.push_line(file_index, line, &expanded);
+                }
+                continue;
+            };
+
+            let (name, argument) = match directive.trim().split_once(char::is_whitespace) {
+                Some((name, argument)) => (name, argument.trim()),
+                None => (directive.trim(), ""),
+            };
+
+            match name {
+                "ifdef" | "ifndef" => {
+                    if argument.is_empty() && active {
+                        let directive = if name == "ifdef" { "ifdef" } else { "ifndef" };
+                        return Err(error(line, PreprocessErrorKind::MissingArgument(directive)));
+                    }
+                    let defined = self.defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        line,
+                        active: active && defined == (name == "ifdef"),
+                        parent_active: active,
+                        seen_else: false,
+                    });
+                }
+                "else" => match conditionals.last_mut() {
+                    Some(conditional) if !conditional.seen_else => {
+                        conditional.active = conditional.parent_active && !conditional.active;
+                        conditional.seen_else = true;
+                    }
+                    _ => return Err(error(line, PreprocessErrorKind::UnexpectedElse)),
+                },
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(error(line, PreprocessErrorKind::UnexpectedEndif));
+                    }
+                }
+                // The rest only applies to kept lines.
+                _ if !active => {}
+                "include" => {
+                    let chunk = argument.trim_matches('"');
+                    if chunk.is_empty() {
+                        return Err(error(line, PreprocessErrorKind::MissingArgument("include")));
+                    }
+                    let Some(chunk_source) = self.chunks.get(chunk) else {
+                        return Err(error(
+                            line,
+                            PreprocessErrorKind::UnknownInclude(chunk.to_string()),
+                        ));
+                    };
+                    if self.included.insert(chunk.to_string()) {
+                        self.process_file(chunk, chunk_source)?;
+                    }
+                }
+                "define" => {
+                    let (define, value) = match argument.split_once(char::is_whitespace) {
+                        Some((define, value)) => (define, value.trim()),
+                        None => (argument, ""),
+                    };
+                    if define.is_empty() {
+                        return Err(error(line, PreprocessErrorKind::MissingArgument("define")));
+                    }
+                    self.defines.insert(define.to_string(), value.to_string());
+                }
+                "undef" => {
+                    self.defines.remove(argument);
+                }
+                _ => {
+                    return Err(error(
+                        line,
+                        PreprocessErrorKind::UnknownDirective(name.to_string()),
+                    ))
+                }
+            }
+        }
+
+        if let Some(conditional) = conditionals.last() {
+            return Err(error(conditional.line, PreprocessErrorKind::UnterminatedIf));
+        }
+
+        Ok(())
+    }
+
+    /// Replaces whole identifiers that have a non empty define.
+    fn expand_defines<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        if self.defines.values().all(|value| value.is_empty()) {
+            return Cow::Borrowed(text);
+        }
+
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            let (before, identifier_start) = rest.split_at(start);
+            expanded.push_str(before);
+
+            let end = identifier_start
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(identifier_start.len());
+            let (identifier, after) = identifier_start.split_at(end);
+
+            match self.defines.get(identifier) {
+                Some(value) if !value.is_empty() => expanded.push_str(value),
+                _ => expanded.push_str(identifier),
+            }
+            rest = after;
+        }
+        expanded.push_str(rest);
+
+        Cow::Owned(expanded)
+    }
+}
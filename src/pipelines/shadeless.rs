@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::sync::Arc;
 
 use crate::helpers::geometry::{self, attribute_names, GeometryData};
 use crate::state::State;
@@ -9,60 +9,11 @@ use wgpu::{PrimitiveTopology, VertexAttribute};
 
 use wgpu::util::DeviceExt;
 
+#[cfg(feature = "hot-reload")]
+use crate::helpers::hot_reload::ShaderHotReload;
+use crate::helpers::hot_reload::ShaderSource;
 use super::{create_global_uniform, create_uniform_buffer, ModelUniform};
 
-const SHADER_SRC: &'static str = " 
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) uv: vec2<f32>,
-    @location(2) color: vec4<f32>,
-    @location(3) pad : vec3<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
-    @location(1) color: vec4<f32>,
-};
-
-struct CameraUniform {
-    view_proj : mat4x4<f32>,
-}
-
-struct ModelUniform {
-    model_matrix : mat4x4<f32>,
-    //TODO: add tint color
-}
-
-@group(0) @binding(0) // 1.
-var<uniform> camera: CameraUniform;
-
-@group(0) @binding(1) // 1.
-var<uniform> modelUniform: ModelUniform;
-
-@group(1) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(1) @binding(1)
-var s_diffuse: sampler;
-
-@vertex
-fn vs_main( model : VertexInput ) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position =  camera.view_proj * modelUniform.model_matrix * vec4(model.position, 1.0);
-    out.uv = model.uv;
-    out.color = model.color;
-    return out;
-}
-
-
-@fragment
-fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
-    // let flipped_uv = vec2<f32>(in.uv.x, 1.0 - in.uv.y);
-    return textureSample(t_diffuse, s_diffuse, in.uv) * in.color;
-}
-";
-
 #[repr(C, align(16))]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -83,9 +34,13 @@ impl Vertex {
     }
 }
 
+/// Pipelines with the same topology, depth mode and texture layout share their shader and
+/// pipeline through [`State::pipeline_cache`].
+// The shader source and options are only read back when reloading.
+#[cfg_attr(not(feature = "hot-reload"), allow(dead_code))]
 pub struct ShadelessPipeline {
     pub shader_module: wgpu::ShaderModule,
-    pub pipeline: wgpu::RenderPipeline,
+    pub pipeline: Arc<wgpu::RenderPipeline>,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 
@@ -94,6 +49,12 @@ pub struct ShadelessPipeline {
 
     pub global_uniform_buffer: Option<wgpu::Buffer>,
     pub model_uniform_buffer: Option<wgpu::Buffer>,
+
+    // Kept to rebuild the pipeline when the shader reloads.
+    shader: ShaderSource,
+    topology: PrimitiveTopology,
+    enable_depth: bool,
+    sample_count: u32,
 }
 
 impl ShadelessPipeline {
@@ -109,15 +70,11 @@ impl ShadelessPipeline {
         custom_texture_bind_group: Option<(wgpu::BindGroupLayout, wgpu::BindGroup)>
 
     ) -> Self {
-        let shader_module = ctx
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("Shader"),
-                source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER_SRC)),
-            });
-
-        let attribs = ShadelessPipeline::get_vertex_attrib_layout_array();
-        let stride = std::mem::size_of::<Vertex>() as u64;
+        let shader = crate::include_shader!("shader_shadeless.wgsl");
+        let source = shader.processed().source;
+        let (_, shader_module) = ctx
+            .pipeline_cache
+            .shader_module(&ctx.device, "Shadeless shader", &source);
 
         let global_uniform_buffer = create_global_uniform(&ctx.device);
         let model_uniform_buffer = create_uniform_buffer::<ModelUniform>(1024, &ctx.device);
@@ -133,7 +90,7 @@ impl ShadelessPipeline {
             &model_uniform_buffer,
             wgpu::BufferSize::new(std::mem::size_of::<glam::Mat4>() as _),
         );
-        let (bind_group_layout, bind_group) = bind_factory.build(&ctx.device);
+        let (bind_group_layout, bind_group) = bind_factory.build_cached(ctx);
 
         let (texture_bind_group_layout, texture_bind_group) = if let Some(bg_bundle) = custom_texture_bind_group {
             bg_bundle
@@ -144,13 +101,46 @@ impl ShadelessPipeline {
                 &texture.view,
                 &texture.sampler,
             );
-            texture_bind_group_factory.build(&ctx.device)
+            texture_bind_group_factory.build_cached(ctx)
         };
             
 
+        let attribs = ShadelessPipeline::get_vertex_attrib_layout_array();
+        let pipeline = Self::pipeline_factory(&attribs, topology, enable_depth)
+            .create_cached_render_pipeline(
+                ctx,
+                "Shadeless shader",
+                &source,
+                &[Some(&bind_group_layout), Some(&texture_bind_group_layout)],
+            );
+
+        Self {
+            pipeline,
+            shader_module,
+            bind_group_layout,
+            bind_group,
+
+            texture_bind_group_layout: Some(texture_bind_group_layout),
+            texture_bind_group: Some(texture_bind_group),
+
+            global_uniform_buffer: Some(global_uniform_buffer),
+            model_uniform_buffer: Some(model_uniform_buffer),
+
+            shader,
+            topology,
+            enable_depth,
+            sample_count: ctx.get_sample_count(),
+        }
+    }
+
+    fn pipeline_factory(
+        attribs: &[VertexAttribute],
+        topology: PrimitiveTopology,
+        enable_depth: bool,
+    ) -> RenderPipelineFactory<'_> {
         let mut pipeline_factory = RenderPipelineFactory::new();
 
-        pipeline_factory.add_vertex_attributes(&attribs, stride);
+        pipeline_factory.add_vertex_attributes(attribs, Self::get_array_stride());
         // .add_instance_attributes(&instance_attribs, std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress)
 
         if enable_depth {
@@ -163,24 +153,51 @@ impl ShadelessPipeline {
         }
         pipeline_factory.set_topology(topology);
         pipeline_factory.set_blend_config(crate::factories::render_pipeline::BlendConfig::Default);
+        pipeline_factory
+    }
 
-        let pipeline = pipeline_factory.create_render_pipeline(
-            &ctx,
-            &shader_module,
-            &[Some(&bind_group_layout), Some(&texture_bind_group_layout)],
-        );
+    /// Rebuilds [`ShadelessPipeline::pipeline`] for the current sample count after
+    /// [`State::set_sample_count`], does nothing when it already matches.
+    pub fn recreate_pipelines(&mut self, ctx: &State) {
+        if self.sample_count == ctx.get_sample_count() {
+            return;
+        }
 
-        Self {
-            pipeline,
-            shader_module,
-            bind_group_layout,
-            bind_group,
+        let attribs = ShadelessPipeline::get_vertex_attrib_layout_array();
+        let layouts = [
+            Some(&self.bind_group_layout),
+            self.texture_bind_group_layout.as_ref(),
+        ];
+        let pipeline = Self::pipeline_factory(&attribs, self.topology, self.enable_depth)
+            .create_render_pipeline(ctx, &self.shader_module, &layouts);
+        self.pipeline = Arc::new(pipeline);
+        self.sample_count = ctx.get_sample_count();
+    }
 
-            texture_bind_group_layout: Some(texture_bind_group_layout),
-            texture_bind_group: Some(texture_bind_group),
+    /// The sample count [`ShadelessPipeline::pipeline`] was built for.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
 
-            global_uniform_buffer: Some(global_uniform_buffer),
-            model_uniform_buffer: Some(model_uniform_buffer),
+    /// Rebuilds [`ShadelessPipeline::pipeline`] when its shader file changed, see
+    /// [`ShaderHotReload`]. The new pipeline is this one's own, others keep sharing the cached
+    /// one until they reload too.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, ctx: &State, hot_reload: &mut ShaderHotReload) {
+        let attribs = ShadelessPipeline::get_vertex_attrib_layout_array();
+        let layouts = [
+            Some(&self.bind_group_layout),
+            self.texture_bind_group_layout.as_ref(),
+        ];
+        let reloaded = hot_reload.reload(ctx, &self.shader, |module| {
+            let pipeline = Self::pipeline_factory(&attribs, self.topology, self.enable_depth)
+                .create_render_pipeline(ctx, module, &layouts);
+            (module.clone(), pipeline)
+        });
+        if let Some((shader_module, pipeline)) = reloaded {
+            self.shader_module = shader_module;
+            self.pipeline = Arc::new(pipeline);
+            self.sample_count = ctx.get_sample_count();
         }
     }
 
@@ -0,0 +1,47 @@
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) pad : vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+#define CAMERA_VIEW_PROJ_ONLY
+#include "camera.wgsl"
+
+struct ModelUniform {
+    model_matrix : mat4x4<f32>,
+    //TODO: add tint color
+}
+
+@group(0) @binding(0) // 1.
+var<uniform> camera: CameraUniform;
+
+@group(0) @binding(1) // 1.
+var<uniform> modelUniform: ModelUniform;
+
+@group(1) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(1) @binding(1)
+var s_diffuse: sampler;
+
+@vertex
+fn vs_main( model : VertexInput ) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position =  camera.view_proj_matrix * modelUniform.model_matrix * vec4(model.position, 1.0);
+    out.uv = model.uv;
+    out.color = model.color;
+    return out;
+}
+
+
+@fragment
+fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
+    // let flipped_uv = vec2<f32>(in.uv.x, 1.0 - in.uv.y);
+    return textureSample(t_diffuse, s_diffuse, in.uv) * in.color;
+}
@@ -0,0 +1,10 @@
+// Layout of `ViewUniform`. Pipelines that only bind the first matrix define
+// CAMERA_VIEW_PROJ_ONLY before including this.
+struct CameraUniform {
+    view_proj_matrix : mat4x4<f32>,
+#ifndef CAMERA_VIEW_PROJ_ONLY
+    view_matrix : mat4x4<f32>,
+    perspective_matrix : mat4x4<f32>,
+    position : vec3<f32>,
+#endif
+}
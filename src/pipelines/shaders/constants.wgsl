@@ -0,0 +1 @@
+const PI : f32 = 3.1415926535897932384626433832795;
@@ -0,0 +1,26 @@
+// Layout of the uniform of a `PbrMaterial`, bound at group 2 binding 0 of the PBR shaders.
+struct MaterialUniform {
+    albedo : vec3<f32>,
+    roughness : f32,
+
+    ambient : vec3<f32>,
+    metallic : f32,
+
+    emissive : vec3<f32>,
+    normal_strength : f32,
+
+    sheen_color : vec3<f32>,
+    sheen_roughness : f32,
+
+    alpha : f32,
+    alpha_cutoff : f32,
+    alpha_mode : u32,
+    occlusion_strength : f32,
+
+    clearcoat : f32,
+    clearcoat_roughness : f32,
+}
+
+const ALPHA_OPAQUE : u32 = 0u;
+const ALPHA_MASK : u32 = 1u;
+const ALPHA_BLEND : u32 = 2u;
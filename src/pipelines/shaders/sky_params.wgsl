@@ -0,0 +1,16 @@
+// Layout of the uniform `SkyParams` writes, shared by the sky background and the PBR lookups.
+struct SkyParams {
+    // Solid background color, used when w is 1.
+    background_color : vec4<f32>,
+    // Cosine and sine of the rotation from world directions to environment directions.
+    rotation : vec2<f32>,
+    intensity : f32,
+    background_mip : f32,
+}
+
+// Direction to sample the environment maps at for the world space `direction`.
+fn sky_direction(params : SkyParams, direction : vec3<f32>) -> vec3<f32> {
+    let c = params.rotation.x;
+    let s = params.rotation.y;
+    return vec3<f32>(c * direction.x + s * direction.z, direction.y, c * direction.z - s * direction.x);
+}
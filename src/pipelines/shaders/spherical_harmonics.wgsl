@@ -0,0 +1,25 @@
+// Real spherical harmonics up to band 2, the order `SkyRenderer::irradiance_sh` is stored in.
+fn sh_basis(d : vec3<f32>) -> array<f32, 9> {
+    return array<f32, 9>(
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    );
+}
+
+// Sum of the rgb of `coefficients` weighted by the basis in `direction`.
+fn sh_evaluate(coefficients : array<vec4<f32>, 9>, direction : vec3<f32>) -> vec3<f32> {
+    var basis = sh_basis(direction);
+    var values = coefficients;
+    var result = vec3<f32>(0.0);
+    for (var i = 0u; i < 9u; i++) {
+        result += values[i].rgb * basis[i];
+    }
+    return result;
+}
@@ -0,0 +1,511 @@
+//! Baked sky textures on disk, so a [`SkyRenderer`] doesn't have to bake them on every launch.
+//!
+//! # File format
+//!
+//! Numbers are little endian `u32`s.
+//!
+//! 1. The magic bytes `PIRASKY\0` and the format version, currently 2.
+//! 2. The [`IrradianceSource`], 0 for the cube map and 1 for spherical harmonics. The
+//!    coefficients aren't stored, they are projected from the environment again when loading.
+//! 3. Four textures: the environment cube map, the irradiance cube map, the prefiltered specular
+//!    cube map and the BRDF lookup table. Each starts with its format (0 for `Rgba32Float`, 1 for
+//!    `Rgba16Float`), width, height, array layer count and mip count. The texels of every mip
+//!    level follow, tightly packed as returned by [`crate::readback::read_texture_data`]: the
+//!    layers of each mip in cube face order, the rows of each layer without padding.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use wgpu::util::DeviceExt;
+
+use super::{IrradianceSource, SkyRenderer, SkyRendererOptions};
+use crate::factories::texture::TextureBundle;
+use crate::readback;
+use crate::state::State;
+use crate::Error;
+
+const MAGIC: &[u8; 8] = b"PIRASKY\0";
+const VERSION: u32 = 2;
+
+/// Part of [`SkyRenderer::bake_hash`], bump it when the bake shaders change their output.
+const BAKE_VERSION: u32 = 2;
+
+const FORMATS: [wgpu::TextureFormat; 2] = [
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+impl SkyRenderer {
+    /// Writes the baked textures to `path`, see the [format](self).
+    pub fn save_baked(&self, state: &State, path: impl AsRef<Path>) -> Result<(), Error> {
+        puffin::profile_function!();
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        write_u32(&mut file, VERSION)?;
+        write_u32(&mut file, irradiance_source_index(self.irradiance_source))?;
+
+        for bundle in [
+            &self.textures,
+            &self.iradiance_texture,
+            &self.specular_reflection_texture,
+            &self.brdf_lut,
+        ] {
+            let texture = &bundle.texture;
+            let Some(format) = FORMATS
+                .iter()
+                .position(|format| *format == texture.format())
+            else {
+                return Err(readback::CaptureError::UnsupportedFormat(texture.format()).into());
+            };
+
+            for value in [
+                format as u32,
+                texture.width(),
+                texture.height(),
+                texture.depth_or_array_layers(),
+                texture.mip_level_count(),
+            ] {
+                write_u32(&mut file, value)?;
+            }
+
+            let is_cube_map = texture.depth_or_array_layers() == 6;
+            let data = if is_cube_map && state.adapter.get_info().backend == wgpu::Backend::Gl {
+                read_cube_map(state, texture)?
+            } else {
+                readback::read_texture_data_blocking(&state.device, &state.queue, texture)?
+            };
+            file.write_all(&data)?;
+        }
+
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Creates a renderer from textures written by [`SkyRenderer::save_baked`], without baking.
+    pub fn load_baked(state: &State, path: impl AsRef<Path>) -> Result<Self, Error> {
+        puffin::profile_function!();
+        let mut file = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a baked sky file").into());
+        }
+        let version = read_u32(&mut file)?;
+        if version != VERSION {
+            let message = format!("unsupported baked sky version {}", version);
+            return Err(invalid_data(&message).into());
+        }
+
+        let irradiance_source = match read_u32(&mut file)? {
+            0 => IrradianceSource::CubeMap,
+            1 => IrradianceSource::SphericalHarmonics,
+            source => {
+                let message = format!("unknown irradiance source {}", source);
+                return Err(invalid_data(&message).into());
+            }
+        };
+
+        let device = &state.device;
+        let textures = read_texture(&mut file, state, "Sky texture", true)?;
+        let iradiance_texture = read_texture(&mut file, state, "Irradiance cube map", true)?;
+        let specular_texture = read_texture(&mut file, state, "Specular conv cube map", true)?;
+        let brdf_lut = read_texture(&mut file, state, "BRDF Lut Texture", false)?;
+
+        Ok(Self::from_textures(
+            state,
+            TextureBundle {
+                view: cube_view(&textures),
+                sampler: Self::create_nearest_sampler(device, Some("Sky sampler")),
+                texture: textures,
+            },
+            TextureBundle {
+                view: cube_view(&iradiance_texture),
+                sampler: Self::create_ibl_sampler(device, Some("Cube Sampler")),
+                texture: iradiance_texture,
+            },
+            TextureBundle {
+                view: cube_view(&specular_texture),
+                sampler: Self::create_ibl_sampler(device, Some("Conv specular Cube Sampler")),
+                texture: specular_texture,
+            },
+            TextureBundle {
+                view: brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()),
+                sampler: Self::create_nearest_sampler(device, Some("BRDF Lut sampler")),
+                texture: brdf_lut,
+            },
+            irradiance_source,
+        ))
+    }
+
+    /// Loads the bake of `image` with `options` from `cache_dir`, or bakes it and saves it there.
+    ///
+    /// Files are named after [`SkyRenderer::bake_hash`], so a changed image or option bakes
+    /// again. Failing to save is only reported, the baked renderer is returned either way.
+    pub fn new_cached(
+        state: &State,
+        image: &image::DynamicImage,
+        options: SkyRendererOptions,
+        cache_dir: impl AsRef<Path>,
+    ) -> Self {
+        puffin::profile_function!();
+        let file_name = format!("sky_{:016x}.bin", Self::bake_hash(image, &options));
+        let path = cache_dir.as_ref().join(file_name);
+
+        match Self::load_baked(state, &path) {
+            Ok(sky) => return sky,
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => eprintln!(
+                "Baking the sky again, can't load {}: {}",
+                path.display(),
+                err
+            ),
+        }
+
+        let sky = Self::new(state, image, options);
+        if let Err(err) = sky.save_baked(state, &path) {
+            eprintln!(
+                "Failed to save the baked sky to {}: {}",
+                path.display(),
+                err
+            );
+        }
+        sky
+    }
+
+    /// Hash of everything that changes the baked textures of `image`, the same on every run and
+    /// platform.
+    pub fn bake_hash(image: &image::DynamicImage, options: &SkyRendererOptions) -> u64 {
+        let mut hasher = Fnv1a::new();
+        for value in [
+            BAKE_VERSION,
+            image.width(),
+            image.height(),
+            options.dst_size,
+            options.irradiance_size,
+            options.irradiance_sample_count,
+            options.specular_size,
+            options.specular_mip_count,
+            options.specular_sample_count,
+            irradiance_source_index(options.irradiance_source),
+        ] {
+            hasher.write(&value.to_le_bytes());
+        }
+        hasher.write(format!("{:?}", image.color()).as_bytes());
+        hasher.write(image.as_bytes());
+        hasher.finish()
+    }
+}
+
+/// Reads one texture header and its texels, and uploads them. A `cube_map` has 6 square layers,
+/// other textures a single one.
+fn read_texture(
+    file: &mut impl Read,
+    state: &State,
+    label: &str,
+    cube_map: bool,
+) -> Result<wgpu::Texture, Error> {
+    let format = read_u32(file)?;
+    let Some(&format) = FORMATS.get(format as usize) else {
+        return Err(invalid_data(&format!("{}: unknown format {}", label, format)).into());
+    };
+    let width = read_u32(file)?;
+    let height = read_u32(file)?;
+    let layers = read_u32(file)?;
+    let mip_level_count = read_u32(file)?;
+
+    let max_size = state.device.limits().max_texture_dimension_2d;
+    let full_mip_chain = 32 - width.max(height).max(1).leading_zeros();
+    if width == 0 || height == 0 || width > max_size || height > max_size {
+        return Err(invalid_data(&format!("{}: invalid size {}x{}", label, width, height)).into());
+    }
+    let expected_layers = if cube_map { 6 } else { 1 };
+    if layers != expected_layers || !(1..=full_mip_chain).contains(&mip_level_count) {
+        let message = format!(
+            "{}: invalid layer count {} or mip count {}",
+            label, layers, mip_level_count
+        );
+        return Err(invalid_data(&message).into());
+    }
+    if cube_map && width != height {
+        let message = format!(
+            "{}: cube map faces aren't square, {}x{}",
+            label, width, height
+        );
+        return Err(invalid_data(&message).into());
+    }
+
+    let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    let bytes_per_pixel = format.block_copy_size(None).unwrap();
+    for mip_level in 0..mip_level_count {
+        let size = texture
+            .size()
+            .mip_level_size(mip_level, wgpu::TextureDimension::D2);
+        let bytes_per_row = size.width * bytes_per_pixel;
+
+        // Large cube maps take more than 4 GB, e.g. 8192² Rgba32Float faces.
+        let Some(length) = (bytes_per_row as u64)
+            .checked_mul(size.height as u64)
+            .and_then(|length| length.checked_mul(layers as u64))
+            .and_then(|length| usize::try_from(length).ok())
+        else {
+            return Err(invalid_data(&format!("{}: mip {} is too large", label, mip_level)).into());
+        };
+        let mut data = vec![0; length];
+        file.read_exact(&mut data)?;
+
+        state.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+
+    Ok(texture)
+}
+
+/// Reads a cube map back like [`readback::read_texture_data`], but samples it in a compute
+/// shader because the GL backend can't copy cube maps to buffers.
+fn read_cube_map(
+    state: &State,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>, readback::CaptureError> {
+    let State { device, queue, .. } = state;
+    let bytes_per_pixel = texture.format().block_copy_size(None).unwrap();
+
+    let shader_module = crate::include_shader!("shader_cube_readback.wgsl").create_module(device);
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Cube readback layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::Cube,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    });
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[Some(&bind_group_layout)],
+        immediate_size: 0,
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Cube readback pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader_module,
+        entry_point: Some("main"),
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        cache: state.pipeline_cache.wgpu_cache(),
+    });
+
+    // Each dispatch writes a band of rows of one face, small enough for a storage binding.
+    let limits = device.limits();
+    let max_binding_size =
+        (limits.max_storage_buffer_binding_size as wgpu::BufferAddress).min(limits.max_buffer_size);
+    let mut regions = Vec::new();
+    let mut scratch_size = 0;
+    for mip_level in 0..texture.mip_level_count() {
+        let face_size = (texture.width() >> mip_level).max(1);
+        let bytes_per_row = face_size * bytes_per_pixel;
+        let max_rows = readback::rows_per_buffer(bytes_per_row, max_binding_size)?;
+
+        for face in 0..6 {
+            for first_row in (0..face_size).step_by(max_rows as usize) {
+                let region = readback::ReadbackRegion {
+                    source: (mip_level, face, first_row, face_size),
+                    rows: max_rows.min(face_size - first_row),
+                    bytes_per_row,
+                    padded_bytes_per_row: bytes_per_row,
+                };
+                scratch_size = scratch_size.max(region_size(&region));
+                regions.push(region);
+            }
+        }
+    }
+
+    let scratch_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Cube readback texels"),
+        size: scratch_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+
+    let view = cube_view(texture);
+    let sampler = SkyRenderer::create_nearest_sampler(device, Some("Cube readback sampler"));
+    let half_float = texture.format() == wgpu::TextureFormat::Rgba16Float;
+
+    let read = readback::read_regions(device, queue, regions, |encoder, region, buffer, offset| {
+        let (mip_level, face, first_row, face_size) = region.source;
+        let params = [
+            face_size,
+            mip_level,
+            face,
+            first_row,
+            region.rows,
+            half_float as u32,
+            0,
+            0,
+        ];
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cube readback params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let size = region_size(region);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cube readback bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &scratch_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(size),
+                    }),
+                },
+            ],
+        });
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Cube readback pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(face_size.div_ceil(8), region.rows.div_ceil(8), 1);
+        }
+        encoder.copy_buffer_to_buffer(&scratch_buffer, 0, buffer, offset, size);
+    });
+
+    pollster::block_on(read)
+}
+
+fn region_size<T>(region: &readback::ReadbackRegion<T>) -> wgpu::BufferAddress {
+    region.bytes_per_row as wgpu::BufferAddress * region.rows as wgpu::BufferAddress
+}
+
+fn irradiance_source_index(source: IrradianceSource) -> u32 {
+    match source {
+        IrradianceSource::CubeMap => 0,
+        IrradianceSource::SphericalHarmonics => 1,
+    }
+}
+
+fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(file: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    file.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u32(file: &mut impl Write, value: u32) -> io::Result<()> {
+    file.write_all(&value.to_le_bytes())
+}
+
+/// 64 bit FNV-1a, unlike the std hashers its output is specified and can name files.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
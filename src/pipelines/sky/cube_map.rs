@@ -0,0 +1,307 @@
+//! Environment cube maps that don't need the projection from an equirectangular image: six face
+//! images, or a KTX2 cube map that may already hold the prefiltered specular mips.
+
+use image::EncodableLayout;
+
+use super::{SkyRenderer, SkyRendererOptions};
+use crate::factories::texture::TextureBundle;
+use crate::state::State;
+use crate::Error;
+
+/// Face of a cube map, in the order of its array layers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    /// The faces in the order of the array layers.
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    fn layer(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Which cube face each of the images given to [`SkyRenderer::new_from_faces`] is.
+///
+/// Only the order differs between the conventions, every image is expected to be oriented like
+/// the cube map face it becomes: seen from the center of the cube, with +Y up on the side faces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CubeFaceOrder {
+    /// +X, -X, +Y, -Y, +Z, -Z, the layer order of wgpu, Vulkan, OpenGL, Direct3D and KTX2. Also
+    /// the "right, left, top, bottom, front, back" of many skybox packs.
+    #[default]
+    Standard,
+    /// Front (+Z), back (-Z), left (+X), right (-X), up (+Y), down (-Y), the slots of Unity's
+    /// 6 sided skybox.
+    Unity,
+    /// The face of every image, in the order they are given.
+    Custom([CubeFace; 6]),
+}
+
+impl CubeFaceOrder {
+    pub fn faces(&self) -> [CubeFace; 6] {
+        use CubeFace::*;
+        match self {
+            CubeFaceOrder::Standard => CubeFace::ALL,
+            CubeFaceOrder::Unity => [
+                PositiveZ, NegativeZ, PositiveX, NegativeX, PositiveY, NegativeY,
+            ],
+            CubeFaceOrder::Custom(faces) => *faces,
+        }
+    }
+}
+
+impl SkyRenderer {
+    /// Bakes the maps of a cube map given as six square images of the same size, `order` tells
+    /// which image is which face. [`SkyRendererOptions::dst_size`] is ignored, the faces keep
+    /// their size.
+    pub fn new_from_faces(
+        state: &State,
+        faces: &[image::DynamicImage; 6],
+        order: CubeFaceOrder,
+        options: SkyRendererOptions,
+    ) -> Result<Self, Error> {
+        puffin::profile_function!();
+        let cube_map_texture =
+            Self::create_cube_map_textures_from_faces(state, faces, order, &options)?;
+        Ok(Self::from_environment(
+            state,
+            cube_map_texture,
+            None,
+            &options,
+        ))
+    }
+
+    pub fn create_cube_map_textures_from_faces(
+        state: &State,
+        faces: &[image::DynamicImage; 6],
+        order: CubeFaceOrder,
+        options: &SkyRendererOptions,
+    ) -> Result<TextureBundle, Error> {
+        puffin::profile_function!();
+        let size = faces[0].width();
+        if let Some(face) = faces
+            .iter()
+            .find(|face| face.width() != size || face.height() != size)
+        {
+            return Err(format!(
+                "cube map faces must be square and of the same size, got {}x{} and {}x{}",
+                size,
+                faces[0].height(),
+                face.width(),
+                face.height()
+            )
+            .into());
+        }
+
+        let order = order.faces();
+        if let Some(face) = CubeFace::ALL.iter().find(|face| !order.contains(face)) {
+            return Err(format!("cube face order {:?} is missing {:?}", order, face).into());
+        }
+
+        let cube = Self::create_environment_cube(&state.device, options.label, size);
+        for (image, face) in faces.iter().zip(order) {
+            let image = image.to_rgba32f();
+            state.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &cube.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: face.layer(),
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                image.as_bytes(),
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(size * 16),
+                    rows_per_image: Some(size),
+                },
+                wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        Ok(cube)
+    }
+
+    /// Creates a renderer from a KTX2 cube map. Supports the uncompressed `R8G8B8A8`,
+    /// `R16G16B16A16_SFLOAT`, `R32G32B32A32_SFLOAT`, `B10G11R11_UFLOAT` and `E5B9G9R9_UFLOAT`
+    /// formats without supercompression.
+    ///
+    /// A file with more than one mip is taken as already prefiltered, e.g. by an IBL baker: its
+    /// first mip is the environment and the whole chain the specular map, with the roughness
+    /// going linearly from 0 to 1 over the mips. Only the irradiance is baked then. Files with a
+    /// single mip, or in a format the device can't filter, are baked like any environment.
+    pub fn new_from_ktx2(
+        state: &State,
+        bytes: &[u8],
+        options: SkyRendererOptions,
+    ) -> Result<Self, Error> {
+        puffin::profile_function!();
+        let texture = Self::create_cube_map_texture_from_ktx2(state, bytes, options.label)?;
+
+        let filterable = state
+            .adapter
+            .get_texture_format_features(texture.format())
+            .flags
+            .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE);
+        let specular_texture =
+            (texture.mip_level_count() > 1 && filterable).then(|| TextureBundle {
+                view: texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Prefiltered specular cube view"),
+                    dimension: Some(wgpu::TextureViewDimension::Cube),
+                    ..Default::default()
+                }),
+                sampler: Self::create_ibl_sampler(
+                    &state.device,
+                    Some("Conv specular Cube Sampler"),
+                ),
+                texture: texture.clone(),
+            });
+
+        // The environment is only the first mip, the bakes and the background sample it with
+        // implicit levels.
+        let cube_map_texture = TextureBundle {
+            view: texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Sky texture view"),
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                mip_level_count: Some(1),
+                ..Default::default()
+            }),
+            sampler: Self::create_nearest_sampler(&state.device, options.label),
+            texture,
+        };
+
+        Ok(Self::from_environment(
+            state,
+            cube_map_texture,
+            specular_texture,
+            &options,
+        ))
+    }
+
+    /// Uploads every mip of a KTX2 cube map, see [`SkyRenderer::new_from_ktx2`] for the formats.
+    pub fn create_cube_map_texture_from_ktx2(
+        state: &State,
+        bytes: &[u8],
+        label: Option<&str>,
+    ) -> Result<wgpu::Texture, Error> {
+        puffin::profile_function!();
+        let reader = ktx2::Reader::new(bytes)?;
+        let header = reader.header();
+
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(format!("unsupported KTX2 supercompression {:?}", scheme).into());
+        }
+        let Some(format) = header.format.and_then(ktx2_format) else {
+            return Err(format!("unsupported KTX2 format {:?}", header.format).into());
+        };
+        if header.face_count != 6 || header.layer_count > 1 || header.pixel_depth > 1 {
+            return Err(format!(
+                "KTX2 file isn't a cube map: {} faces, {} layers, depth {}",
+                header.face_count, header.layer_count, header.pixel_depth
+            )
+            .into());
+        }
+        let size = header.pixel_width;
+        let max_size = state.device.limits().max_texture_dimension_2d;
+        if size != header.pixel_height || size > max_size {
+            return Err(format!(
+                "invalid KTX2 cube map size {}x{}",
+                size, header.pixel_height
+            )
+            .into());
+        }
+
+        // Only the levels actually in the file, a mip left without data would be read as a
+        // prefiltered one.
+        let full_mip_chain = 32 - size.leading_zeros();
+        let mip_level_count = (reader.levels().count() as u32).clamp(1, full_mip_chain);
+
+        let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let bytes_per_pixel = format.block_copy_size(None).unwrap();
+        for (mip_level, level) in (0..mip_level_count).zip(reader.levels()) {
+            let size = texture
+                .size()
+                .mip_level_size(mip_level, wgpu::TextureDimension::D2);
+            let bytes_per_row = size.width * bytes_per_pixel;
+            // 64 bit, the first mip of 8192² Rgba32Float faces is larger than 4 GB.
+            let expected = bytes_per_row as u64 * size.height as u64 * 6;
+            if level.data.len() as u64 != expected {
+                return Err(format!(
+                    "KTX2 mip {} has {} bytes instead of {}",
+                    mip_level,
+                    level.data.len(),
+                    expected
+                )
+                .into());
+            }
+
+            state.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level.data,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(size.height),
+                },
+                size,
+            );
+        }
+
+        Ok(texture)
+    }
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    Some(match format {
+        ktx2::Format::R8G8B8A8_UNORM => wgpu::TextureFormat::Rgba8Unorm,
+        ktx2::Format::R8G8B8A8_SRGB => wgpu::TextureFormat::Rgba8UnormSrgb,
+        ktx2::Format::R16G16B16A16_SFLOAT => wgpu::TextureFormat::Rgba16Float,
+        ktx2::Format::R32G32B32A32_SFLOAT => wgpu::TextureFormat::Rgba32Float,
+        ktx2::Format::B10G11R11_UFLOAT_PACK32 => wgpu::TextureFormat::Rg11b10Ufloat,
+        ktx2::Format::E5B9G9R9_UFLOAT_PACK32 => wgpu::TextureFormat::Rgb9e5Ufloat,
+        _ => return None,
+    })
+}
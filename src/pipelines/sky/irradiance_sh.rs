@@ -0,0 +1,201 @@
+use wgpu::util::DeviceExt;
+
+use super::{SkyRenderer, IBL_FORMAT};
+use crate::factories::texture::TextureBundle;
+use crate::state::State;
+
+/// Where the PBR pipelines take the diffuse lighting of a [`SkyRenderer`] from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IrradianceSource {
+    /// Samples the convolved [`SkyRenderer::iradiance_texture`].
+    #[default]
+    CubeMap,
+    /// Evaluates [`SkyRenderer::irradiance_sh`], the convolution is skipped and the irradiance
+    /// texture left as a 1x1 placeholder. Smoother and cheaper to bake, but loses the detail of
+    /// small bright sources.
+    SphericalHarmonics,
+}
+
+/// Size of the coefficients in [`SkyRenderer::irradiance_sh`].
+pub const IRRADIANCE_SH_SIZE: wgpu::BufferAddress = 9 * 16;
+
+impl SkyRenderer {
+    /// Projects the environment cube map onto the first 9 spherical harmonics and convolves them
+    /// into diffuse irradiance, see [`SkyRenderer::irradiance_sh`].
+    pub fn create_irradiance_sh(state: &State, input: &TextureBundle) -> wgpu::Buffer {
+        puffin::profile_function!();
+        let State { device, queue, .. } = state;
+
+        let size = input.texture.width();
+        let workgroups = size.div_ceil(8);
+        let partial_count = workgroups * workgroups * 6;
+
+        let shader_module =
+            crate::include_shader!("shader_irradiance_sh.wgsl").create_module(device);
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Irradiance SH params"),
+            contents: bytemuck::cast_slice(&[size, partial_count, 0, 0]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let partials_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Irradiance SH partial sums"),
+            size: partial_count as wgpu::BufferAddress * IRRADIANCE_SH_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let coefficients_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Irradiance SH"),
+            size: IRRADIANCE_SH_SIZE,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Irradiance SH layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(3),
+                storage_entry(4),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Irradiance SH pipeline layout"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: state.pipeline_cache.wgpu_cache(),
+            })
+        };
+        let project_pipeline = create_pipeline("project");
+        let reduce_pipeline = create_pipeline("reduce");
+
+        let sampler = Self::create_nearest_sampler(device, Some("Irradiance SH sampler"));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Irradiance SH bind group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&input.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: partials_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: coefficients_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Irradiance SH"),
+                timestamp_writes: None,
+            });
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_pipeline(&project_pipeline);
+            pass.dispatch_workgroups(workgroups, workgroups, 6);
+            pass.set_pipeline(&reduce_pipeline);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+        queue.submit([encoder.finish()]);
+
+        coefficients_buffer
+    }
+
+    /// Zeroed coefficients, bound by the PBR pipelines with [`IrradianceSource::CubeMap`].
+    pub(super) fn create_empty_irradiance_sh(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Irradiance SH"),
+            size: IRRADIANCE_SH_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        })
+    }
+
+        /// Stands in for the irradiance cube map with [`IrradianceSource::SphericalHarmonics`].
+    pub(super) fn create_placeholder_irradiance(state: &State) -> TextureBundle {
+        let texture = state.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Irradiance placeholder"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: IBL_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        TextureBundle {
+            view: texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            }),
+            sampler: Self::create_ibl_sampler(&state.device, Some("Cube Sampler")),
+            texture,
+        }
+    }
+}
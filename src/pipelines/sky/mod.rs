@@ -1,17 +1,33 @@
 use crate::factories::texture::{SamplerOptions, Texture2dOptions, TextureBundle};
 
 use crate::factories::{BindGroupFactory, RenderPipelineFactory};
+use crate::helpers;
+#[cfg(feature = "hot-reload")]
+use crate::helpers::hot_reload::ShaderHotReload;
+use crate::helpers::hot_reload::ShaderSource;
+use crate::helpers::cameras::{self, CameraTrait};
 use crate::helpers::geometry::GeometryFactory;
-use crate::helpers::{self, cameras};
 use crate::pipelines::shadeless::{self, ShadelessPipeline};
 use crate::state::State;
 use crate::{factories, pipelines};
 use image::EncodableLayout;
+use wgpu::util::DeviceExt;
 use wgpu::{
-    BufferBinding, PrimitiveTopology, SamplerDescriptor, ShaderModuleDescriptor,
-    TextureViewDescriptor, TextureViewDimension,
+    BufferBinding, PrimitiveTopology, SamplerDescriptor, TextureViewDescriptor,
+    TextureViewDimension,
 };
 
+mod baked;
+mod cube_map;
+mod irradiance_sh;
+mod params;
+mod physical;
+
+pub use cube_map::{CubeFace, CubeFaceOrder};
+pub use irradiance_sh::{IrradianceSource, IRRADIANCE_SH_SIZE};
+pub use params::SkyParams;
+pub use physical::PhysicalSky;
+
 /*
 Notes:
 2. Correct rotation on cube map (prob will have to use a camera matrix)
@@ -28,20 +44,74 @@ pub struct Uniform {
     pub inv_view: [f32; 16],
 }
 
+impl Uniform {
+    pub fn new(view_pos: glam::Vec3, view: glam::Mat4, projection: glam::Mat4) -> Self {
+        Self {
+            view_pos: view_pos.extend(1.0).to_array(),
+            view: view.to_cols_array(),
+            view_proj: (projection * view).to_cols_array(),
+            inv_proj: projection.inverse().to_cols_array(),
+            inv_view: view.inverse().to_cols_array(),
+        }
+    }
+}
+
+// The shader source is only read back when reloading.
+#[cfg_attr(not(feature = "hot-reload"), allow(dead_code))]
 pub struct SkyRenderer {
     pub textures: TextureBundle,
     pub iradiance_texture: TextureBundle,
     pub specular_reflection_texture: TextureBundle,
     pub brdf_lut: TextureBundle,
+    /// Diffuse irradiance divided by pi as 9 spherical harmonics coefficients, each a `vec4`
+    /// with the color in rgb. Only projected with [`IrradianceSource::SphericalHarmonics`], zeros
+    /// otherwise.
+    pub irradiance_sh: wgpu::Buffer,
+    pub irradiance_source: IrradianceSource,
 
     pub pipeline: wgpu::RenderPipeline,
+    /// Layout of [`SkyRenderer::bind_group`].
+    pub environment_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
     pub uniform_buffer: wgpu::Buffer,
+
+    /// Rotation, exposure and background of the environment, see [`SkyRenderer::write_params`].
+    pub params: SkyParams,
+    /// Uniform with the last written [`SkyRenderer::params`], also bound by the PBR pipelines.
+    pub params_buffer: wgpu::Buffer,
+
+    // Kept to rebuild the pipeline when the shader reloads.
+    shader: ShaderSource,
+    sample_count: u32,
+}
+
+/// Roughness and sample count of one convolution pass, at group 0 binding 2 of the bake shaders.
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ConvolutionUniform {
+    roughness: f32,
+    sample_count: u32,
+    _pad: [u32; 2],
 }
 
 pub struct SkyRendererOptions<'a> {
     pub label: Option<&'a str>,
+    /// Size of the cube map faces the equirectangular image is projected to. Face images and
+    /// KTX2 files keep their own size.
     pub dst_size: u32,
+    /// Face size of the diffuse irradiance cube map.
+    pub irradiance_size: u32,
+    /// Samples along the elevation of the hemisphere for each irradiance texel, four times as
+    /// many are taken around it.
+    pub irradiance_sample_count: u32,
+    /// Face size of the first mip of the prefiltered specular cube map.
+    pub specular_size: u32,
+    /// Mips of the specular cube map, from roughness 0 to 1. Clamped to the full mip chain.
+    pub specular_mip_count: u32,
+    /// Importance samples for each specular texel.
+    pub specular_sample_count: u32,
+    /// Diffuse lighting from the convolved cube map or from spherical harmonics.
+    pub irradiance_source: IrradianceSource,
 }
 
 impl<'a> Default for SkyRendererOptions<'a> {
@@ -49,10 +119,26 @@ impl<'a> Default for SkyRendererOptions<'a> {
         Self {
             label: Some("Sky Renderer"),
             dst_size: 512,
+            irradiance_size: 64,
+            irradiance_sample_count: 32,
+            specular_size: 512,
+            specular_mip_count: 6,
+            specular_sample_count: 1024,
+            irradiance_source: IrradianceSource::CubeMap,
         }
     }
 }
 
+/// Format of the baked irradiance and specular maps, filterable and renderable everywhere.
+pub const IBL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Limits the sky needs beyond [`wgpu::Limits::downlevel_webgl2_defaults`], the bakes run compute
+/// shaders with storage textures and buffers. Return it from
+/// [`crate::framework::Application::required_limits`].
+pub fn required_limits() -> wgpu::Limits {
+    wgpu::Limits::downlevel_defaults()
+}
+
 impl SkyRenderer {
     fn get_cube_face_view_matrices() -> [glam::Mat4; 6] {
         [
@@ -89,24 +175,18 @@ impl SkyRenderer {
         ]
     }
 
-    pub fn create_cube_map_textures_from_equi(
-        state: &State,
-        image: &image::DynamicImage,
-        options: &SkyRendererOptions,
+    /// Empty environment cube map the compute shaders write the faces of.
+    fn create_environment_cube(
+        device: &wgpu::Device,
+        label: Option<&str>,
+        size: u32,
     ) -> TextureBundle {
-        puffin::profile_function!();
-        let State { device, queue, .. } = state;
-
-        let image = image.to_rgba32f();
-        let SkyRendererOptions { label, dst_size } = *options;
-
-        // This will be the result bundle image
         // TODO: create this texture via the texture factory
-        let cube_texture = device.create_texture(&wgpu::TextureDescriptor {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size: wgpu::Extent3d {
-                width: dst_size,
-                height: dst_size,
+                width: size,
+                height: size,
                 depth_or_array_layers: 6,
             },
             mip_level_count: 1,
@@ -115,33 +195,46 @@ impl SkyRenderer {
             format: wgpu::TextureFormat::Rgba32Float,
             usage: wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::STORAGE_BINDING,
             view_formats: &[],
         });
 
-        let cube_view = cube_texture.create_view(&wgpu::TextureViewDescriptor {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("SKy texture view"),
             dimension: Some(wgpu::TextureViewDimension::Cube),
             array_layer_count: Some(6),
             ..Default::default()
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label,
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::MipmapFilterMode::Nearest,
-            ..Default::default()
-        });
+        TextureBundle {
+            sampler: Self::create_nearest_sampler(device, label),
+            view,
+            texture,
+        }
+    }
+
+    pub fn create_cube_map_textures_from_equi(
+        state: &State,
+        image: &image::DynamicImage,
+        options: &SkyRendererOptions,
+    ) -> TextureBundle {
+        puffin::profile_function!();
+        let State { device, queue, .. } = state;
+
+        let image = image.to_rgba32f();
+        let SkyRendererOptions { label, dst_size, .. } = *options;
+
+        // This will be the result bundle image
+        let TextureBundle {
+            texture: cube_texture,
+            view: cube_view,
+            sampler,
+        } = Self::create_environment_cube(device, label, dst_size);
 
         // -------- Compute shader pipeline -----------
-        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Equirectangular to cubemap compute"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader_env_to_cubemap.wgsl").into()),
-        });
+        let shader_module =
+            crate::include_shader!("shader_env_to_cubemap.wgsl").create_module(device);
 
         //create equirectangular texture
         let env_texture_bundle = factories::Texture2dFactory::new_with_options(
@@ -249,14 +342,12 @@ impl SkyRenderer {
         state: &State,
         unit_cube: &shadeless::GpuMesh,
         input: &TextureBundle,
+        options: &SkyRendererOptions,
     ) -> TextureBundle {
         puffin::profile_function!();
         let State { device, queue, .. } = state;
 
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Equirectangular to cubemap shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader_irradiance_cubemap.wgsl").into()),
-        });
+        let shader = crate::include_shader!("shader_irradiance_cubemap.wgsl").create_module(device);
 
         let uniform_buffer = pipelines::create_uniform_buffer::<glam::Mat4>(1, device);
 
@@ -281,8 +372,22 @@ impl SkyRenderer {
             &model_uniform_buffer,
             wgpu::BufferSize::new(std::mem::size_of::<glam::Mat4>() as _),
         );
+        let convolution_uniform_buffer =
+            pipelines::create_uniform_buffer::<ConvolutionUniform>(1, device);
+        bind_factory.add_uniform(
+            wgpu::ShaderStages::FRAGMENT,
+            &convolution_uniform_buffer,
+            wgpu::BufferSize::new(std::mem::size_of::<ConvolutionUniform>() as _),
+        );
         let (bind_group_layout, bind_group) = bind_factory.build(&state.device);
 
+        let convolution = ConvolutionUniform {
+            roughness: 0.0,
+            sample_count: options.irradiance_sample_count.max(1),
+            _pad: [0; 2],
+        };
+        queue.write_buffer(&convolution_uniform_buffer, 0, bytemuck::bytes_of(&convolution));
+
         let mut texture_bind_group_factory: BindGroupFactory<'_> = BindGroupFactory::new();
         texture_bind_group_factory.add_texture_sky_sampler(
             wgpu::ShaderStages::VERTEX_FRAGMENT,
@@ -298,7 +403,7 @@ impl SkyRenderer {
         pipeline_factory.set_sample_count(Some(1));
         // .add_instance_attributes(&instance_attribs, std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress)
         pipeline_factory.set_blend_config(factories::render_pipeline::BlendConfig::None);
-        pipeline_factory.set_color_target_format(Some(wgpu::TextureFormat::Rgba32Float));
+        pipeline_factory.set_color_target_format(Some(IBL_FORMAT));
         pipeline_factory.set_topology(PrimitiveTopology::TriangleList);
 
         let pipeline = pipeline_factory.create_render_pipeline(
@@ -311,18 +416,18 @@ impl SkyRenderer {
         let render_target = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: wgpu::Extent3d {
-                width: 64,  //input.texture.width() as u32,
-                height: 64, //input.texture.height() as u32,
+                width: options.irradiance_size,
+                height: options.irradiance_size,
                 depth_or_array_layers: 6,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
+            format: IBL_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+            view_formats: &[IBL_FORMAT],
         });
 
         let matrices = Self::get_cube_face_view_matrices();
@@ -370,7 +475,7 @@ impl SkyRenderer {
                         multiview_mask: None,
                     });
                 render_pass.set_pipeline(&pipeline);
-                render_pass.set_bind_group(0, &bind_group, &[0, 0]);
+                render_pass.set_bind_group(0, &bind_group, &[0, 0, 0]);
                 render_pass.set_bind_group(1, &texture_bind_group, &[]);
                 render_pass.set_vertex_buffer(0, unit_cube.vertex_buffer.slice(..));
                 render_pass
@@ -389,10 +494,7 @@ impl SkyRenderer {
             ..Default::default()
         });
 
-        let dst_cube_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("Cube Sampler"),
-            ..Default::default()
-        });
+        let dst_cube_sampler = Self::create_ibl_sampler(device, Some("Cube Sampler"));
 
         println!("Done generating cube map");
         TextureBundle {
@@ -406,14 +508,12 @@ impl SkyRenderer {
         state: &State,
         unit_cube: &shadeless::GpuMesh,
         cube_map_texture: &TextureBundle,
+        options: &SkyRendererOptions,
     ) -> TextureBundle {
         puffin::profile_function!();
         let State { device, queue, .. } = state;
 
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Specular conv shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader_convolve_specular.wgsl").into()),
-        });
+        let shader = crate::include_shader!("shader_convolve_specular.wgsl").create_module(device);
 
         let uniform_buffer = pipelines::create_uniform_buffer::<glam::Mat4>(1, device);
 
@@ -438,6 +538,13 @@ impl SkyRenderer {
             &model_uniform_buffer,
             wgpu::BufferSize::new(std::mem::size_of::<glam::Mat4>() as _),
         );
+        let convolution_uniform_buffer =
+            pipelines::create_uniform_buffer::<ConvolutionUniform>(1, device);
+        bind_factory.add_uniform(
+            wgpu::ShaderStages::FRAGMENT,
+            &convolution_uniform_buffer,
+            wgpu::BufferSize::new(std::mem::size_of::<ConvolutionUniform>() as _),
+        );
         let (bind_group_layout, bind_group) = bind_factory.build(&state.device);
 
         let mut texture_bind_group_factory: BindGroupFactory<'_> = BindGroupFactory::new();
@@ -455,7 +562,7 @@ impl SkyRenderer {
         pipeline_factory.set_sample_count(Some(1));
         // .add_instance_attributes(&instance_attribs, std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress)
         pipeline_factory.set_blend_config(factories::render_pipeline::BlendConfig::None);
-        pipeline_factory.set_color_target_format(Some(wgpu::TextureFormat::Rgba32Float));
+        pipeline_factory.set_color_target_format(Some(IBL_FORMAT));
         pipeline_factory.set_topology(PrimitiveTopology::TriangleList);
 
         let pipeline = pipeline_factory.create_render_pipeline(
@@ -465,36 +572,47 @@ impl SkyRenderer {
         );
 
         //-----------------------------------------------
+        let full_mip_chain = 32 - options.specular_size.max(1).leading_zeros();
+        let mip_count = options.specular_mip_count.clamp(1, full_mip_chain);
+
         let render_target = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Specular conv cube map"),
             size: wgpu::Extent3d {
-                width: 512,  //input.texture.width() as u32,
-                height: 512, //input.texture.height() as u32,
+                width: options.specular_size,
+                height: options.specular_size,
                 depth_or_array_layers: 6,
             },
-            mip_level_count: 6,
+            mip_level_count: mip_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
+            format: IBL_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+            view_formats: &[IBL_FORMAT],
         });
 
         let matrices = Self::get_cube_face_view_matrices();
-        let r_uniform = [0.0, 0.2, 0.4, 0.6, 0.8, 1.0];
 
-        for mip in 0..6 {
-            let r = r_uniform[mip];
+        for mip in 0..mip_count {
+            // The last mip is fully rough, whatever the mip count.
+            let r = mip as f32 / (mip_count - 1).max(1) as f32;
 
             println!("Writting mip: {}", r);
 
-            for i in 0..6 {
-                let mut mat = glam::Mat4::IDENTITY;
-                mat.as_mut()[0] = r_uniform[mip];
+            let convolution = ConvolutionUniform {
+                roughness: r,
+                sample_count: options.specular_sample_count.max(1),
+                _pad: [0; 2],
+            };
+            queue.write_buffer(&convolution_uniform_buffer, 0, bytemuck::bytes_of(&convolution));
 
-                pipelines::write_global_uniform_buffer(mat, &global_uniform_buffer, &state.queue);
+            for i in 0..6 {
+                pipelines::write_global_uniform_buffer(
+                    glam::Mat4::IDENTITY,
+                    &global_uniform_buffer,
+                    &state.queue,
+                );
 
                 pipelines::write_uniform_buffer(
                     matrices[i].as_ref(),
@@ -508,7 +626,7 @@ impl SkyRenderer {
                     base_array_layer: i as u32,
                     array_layer_count: Some(1),
                     label: Some(format!("Spec Conv Temp view {}", i).as_str()),
-                    base_mip_level: mip as u32,
+                    base_mip_level: mip,
                     mip_level_count: Some(1),
                     ..Default::default()
                 });
@@ -534,7 +652,7 @@ impl SkyRenderer {
                             multiview_mask: None,
                         });
                     render_pass.set_pipeline(&pipeline);
-                    render_pass.set_bind_group(0, &bind_group, &[0, 0]);
+                    render_pass.set_bind_group(0, &bind_group, &[0, 0, 0]);
                     render_pass.set_bind_group(1, &texture_bind_group, &[]);
                     render_pass.set_vertex_buffer(0, unit_cube.vertex_buffer.slice(..));
                     render_pass.set_index_buffer(
@@ -550,17 +668,14 @@ impl SkyRenderer {
 
         let dst_cube_view = render_target.create_view(&TextureViewDescriptor {
             array_layer_count: Some(6),
-            mip_level_count: Some(6),
+            mip_level_count: Some(mip_count),
             dimension: Some(TextureViewDimension::Cube),
             label: Some("Conv specular Cube view"),
             ..Default::default()
         });
 
-        let dst_cube_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("Conv specular Cube Sampler"),
-            mipmap_filter: wgpu::MipmapFilterMode::Linear,
-            ..Default::default()
-        });
+        let dst_cube_sampler =
+            Self::create_ibl_sampler(device, Some("Conv specular Cube Sampler"));
 
         println!("Done generating Specular conv");
         TextureBundle {
@@ -570,16 +685,41 @@ impl SkyRenderer {
         }
     }
 
+    /// Sampler for the environment cube map and the BRDF lookup table, they aren't filterable.
+    fn create_nearest_sampler(device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        device.create_sampler(&SamplerDescriptor {
+            label,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+            ..Default::default()
+        })
+    }
+
+    /// Trilinear sampler for the baked irradiance and specular maps.
+    fn create_ibl_sampler(device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        device.create_sampler(&SamplerDescriptor {
+            label,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::MipmapFilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
     pub fn create_brdf_lut(state: &State) -> TextureBundle {
         puffin::profile_function!();
         let State { device, queue, .. } = state;
 
         let table_size = 512;
 
-        let shader_module = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Equirectangular to cubemap compute"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader_brdf_lut.wgsl").into()),
-        });
+        let shader_module = crate::include_shader!("shader_brdf_lut.wgsl").create_module(device);
 
         //        let texture_bundle = texture::Texture2dFactory::new(table_size, table_size)
         //            .get_texture_and_sampler(device, queue, &[]);
@@ -596,6 +736,7 @@ impl SkyRenderer {
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba32Float,
             usage: wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC
                 | wgpu::TextureUsages::STORAGE_BINDING
                 | wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
@@ -608,16 +749,7 @@ impl SkyRenderer {
             ..Default::default()
         });
 
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("BRDF Lut sampler"),
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::MipmapFilterMode::Nearest,
-            ..Default::default()
-        });
+        let sampler = Self::create_nearest_sampler(device, Some("BRDF Lut sampler"));
 
         let texture_bundle = TextureBundle {
             texture,
@@ -665,7 +797,7 @@ impl SkyRenderer {
             }],
         });
 
-        let num_workgroups = table_size + 15 / 16;
+        let num_workgroups = table_size.div_ceil(16);
         let mut encoder = device.create_command_encoder(&Default::default());
         {
             let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
@@ -681,25 +813,76 @@ impl SkyRenderer {
         texture_bundle
     }
 
+    /// Bakes the maps of `image`, see [`SkyRenderer::new_cached`] to keep them between launches.
     pub fn new(state: &State, image: &image::DynamicImage, options: SkyRendererOptions) -> Self {
         puffin::profile_function!();
-        let State { device, .. } = state;
+        let cube_map_texture =
+            SkyRenderer::create_cube_map_textures_from_equi(state, image, &options);
+        Self::from_environment(state, cube_map_texture, None, &options)
+    }
 
+    /// Bakes the irradiance and specular maps of an environment cube map, the specular one only
+    /// if it isn't given already prefiltered.
+    fn from_environment(
+        state: &State,
+        cube_map_texture: TextureBundle,
+        specular_texture: Option<TextureBundle>,
+        options: &SkyRendererOptions,
+    ) -> Self {
         let mut cube_geo = helpers::geometry::cube::Cube::new(1.0);
         cube_geo.texture_coords();
 
         let unit_cube =
             shadeless::ShadelessPipeline::get_buffers_from_geometry(state, &cube_geo.geometry);
 
-        let cube_map_texture =
-            SkyRenderer::create_cube_map_textures_from_equi(state, image, &options);
-        let iradiance_texture =
-            SkyRenderer::create_iradiance_map(state, &unit_cube, &cube_map_texture);
-        let specular_texture =
-            SkyRenderer::create_specular_map(state, &unit_cube, &cube_map_texture);
+        let iradiance_texture = match options.irradiance_source {
+            IrradianceSource::CubeMap => {
+                SkyRenderer::create_iradiance_map(state, &unit_cube, &cube_map_texture, options)
+            }
+            IrradianceSource::SphericalHarmonics => Self::create_placeholder_irradiance(state),
+        };
+        let specular_texture = specular_texture.unwrap_or_else(|| {
+            SkyRenderer::create_specular_map(state, &unit_cube, &cube_map_texture, options)
+        });
+        let brdf_lut = SkyRenderer::create_brdf_lut(&state);
+
+        Self::from_textures(
+            state,
+            cube_map_texture,
+            iradiance_texture,
+            specular_texture,
+            brdf_lut,
+            options.irradiance_source,
+        )
+    }
+
+    /// Creates the renderer around already baked textures, e.g. loaded with
+    /// [`SkyRenderer::load_baked`].
+    fn from_textures(
+        state: &State,
+        cube_map_texture: TextureBundle,
+        iradiance_texture: TextureBundle,
+        specular_texture: TextureBundle,
+        brdf_lut: TextureBundle,
+        irradiance_source: IrradianceSource,
+    ) -> Self {
+        let State { device, .. } = state;
+
+        let irradiance_sh = match irradiance_source {
+            IrradianceSource::SphericalHarmonics => {
+                Self::create_irradiance_sh(state, &cube_map_texture)
+            }
+            IrradianceSource::CubeMap => Self::create_empty_irradiance_sh(device),
+        };
+
         let uniform_buffer = pipelines::create_uniform_buffer::<Uniform>(1, device);
 
-        let brdf_lut = SkyRenderer::create_brdf_lut(&state);
+        let params = SkyParams::default();
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky params"),
+            contents: bytemuck::bytes_of(&params::SkyParamsUniform::from(&params)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
         let environment_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -731,6 +914,33 @@ impl SkyRenderer {
                         },
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // The prefiltered specular map, drawn when the background is blurred.
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
                 ],
             });
 
@@ -758,34 +968,105 @@ impl SkyRenderer {
                         size: wgpu::BufferSize::new(std::mem::size_of::<Uniform>() as _),
                     }),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&specular_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(&specular_texture.sampler),
+                },
             ],
         });
 
-        let shader = device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("Sky"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader_sky_render.wgsl").into()),
-        });
-
-        let mut sky_render_pipeline = factories::RenderPipelineFactory::new();
-        sky_render_pipeline
-            .add_depth_stencil(factories::render_pipeline::DepthConfig::DefaultDontWrite);
-        sky_render_pipeline.set_cull_mode(Some(wgpu::Face::Back));
-
-        let pipeline =
-            sky_render_pipeline.create_render_pipeline(state, &shader, &[Some(&environment_layout)]);
+        let shader = crate::include_shader!("shader_sky_render.wgsl");
+        let pipeline = Self::create_render_pipeline(
+            state,
+            &shader.create_module(device),
+            &environment_layout,
+        );
 
         Self {
             pipeline,
+            environment_layout,
+            shader,
             textures: cube_map_texture,
             specular_reflection_texture: specular_texture,
             brdf_lut,
             iradiance_texture,
+            irradiance_sh,
+            irradiance_source,
             bind_group: environment_bind_group,
             uniform_buffer,
+            params,
+            params_buffer,
+            sample_count: state.get_sample_count(),
+        }
+    }
+
+    fn create_render_pipeline(
+        state: &State,
+        shader_module: &wgpu::ShaderModule,
+        environment_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::RenderPipeline {
+        let mut sky_render_pipeline = factories::RenderPipelineFactory::new();
+        sky_render_pipeline
+            .add_depth_stencil(factories::render_pipeline::DepthConfig::DefaultDontWrite);
+        sky_render_pipeline.set_cull_mode(Some(wgpu::Face::Back));
+
+        sky_render_pipeline.create_render_pipeline(
+            state,
+            shader_module,
+            &[Some(environment_layout)],
+        )
+    }
+
+    /// Rebuilds [`SkyRenderer::pipeline`] for the current sample count after
+    /// [`State::set_sample_count`], does nothing when it already matches.
+    pub fn recreate_pipelines(&mut self, state: &State) {
+        if self.sample_count == state.get_sample_count() {
+            return;
+        }
+
+        let shader_module = self.shader.create_module(&state.device);
+        self.pipeline =
+            Self::create_render_pipeline(state, &shader_module, &self.environment_layout);
+        self.sample_count = state.get_sample_count();
+    }
+
+    /// The sample count [`SkyRenderer::pipeline`] was built for.
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Rebuilds [`SkyRenderer::pipeline`] when its shader file changed, see [`ShaderHotReload`].
+    /// The bakes aren't redone, a changed bake shader only applies to the next sky created.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&mut self, state: &State, hot_reload: &mut ShaderHotReload) {
+        let pipeline = hot_reload.reload(state, &self.shader, |module| {
+            Self::create_render_pipeline(state, module, &self.environment_layout)
+        });
+        if let Some(pipeline) = pipeline {
+            self.pipeline = pipeline;
+            self.sample_count = state.get_sample_count();
         }
     }
 
-    pub fn set_uniform_buffer(&mut self, _camera: cameras::PespectiveCamera) {}
+    /// Writes the view of `camera` to [`SkyRenderer::uniform_buffer`].
+    pub fn set_uniform_buffer(&self, state: &State, camera: &cameras::PespectiveCamera) {
+        let uniform = Uniform::new(
+            camera.position,
+            camera.get_view_matrix(),
+            camera.get_perspective_matrix(),
+        );
+        state
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
 
     pub fn draw<'rpass>(&'rpass self, render_pass: &mut wgpu::RenderPass<'rpass>) {
         render_pass.set_bind_group(0, &self.bind_group, &[]);
@@ -0,0 +1,59 @@
+use super::SkyRenderer;
+use crate::state::State;
+
+/// How the environment of a [`SkyRenderer`] is placed and shown, used by its background and by
+/// the image based lighting of the PBR pipelines built with it.
+///
+/// Change [`SkyRenderer::params`] and call [`SkyRenderer::write_params`], no re-bake is needed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SkyParams {
+    /// Turns the environment around the y axis, in radians.
+    pub rotation: f32,
+    /// Scales the environment by `2^exposure`.
+    pub exposure: f32,
+    /// Mip of the prefiltered specular map drawn as the background, blurring it. 0 draws the
+    /// sharp environment cube map, the last mip is the blurriest.
+    pub background_mip: f32,
+    /// Linear color drawn as the background instead of the environment, which still lights the
+    /// scene.
+    pub background_color: Option<glam::Vec3>,
+}
+
+/// Layout of `SkyParams` in `sky_params.wgsl`.
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(super) struct SkyParamsUniform {
+    background_color: [f32; 4],
+    rotation: [f32; 2],
+    intensity: f32,
+    background_mip: f32,
+}
+
+impl From<&SkyParams> for SkyParamsUniform {
+    fn from(params: &SkyParams) -> Self {
+        let background_color = match params.background_color {
+            Some(color) => color.extend(1.0),
+            None => glam::Vec4::ZERO,
+        };
+        // World directions are turned back by the rotation to find the environment direction.
+        let (sin, cos) = (-params.rotation).sin_cos();
+
+        Self {
+            background_color: background_color.to_array(),
+            rotation: [cos, sin],
+            intensity: params.exposure.exp2(),
+            background_mip: params.background_mip.max(0.0),
+        }
+    }
+}
+
+impl SkyRenderer {
+    /// Uploads [`SkyRenderer::params`].
+    pub fn write_params(&self, state: &State) {
+        state.queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&SkyParamsUniform::from(&self.params)),
+        );
+    }
+}
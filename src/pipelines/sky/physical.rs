@@ -0,0 +1,305 @@
+//! Procedural daylight sky, an alternative to starting a [`SkyRenderer`] from an HDR image.
+//!
+//! Uses the analytic model of Preetham, Shirley and Smits, "A Practical Analytic Model for
+//! Daylight" (1999), evaluated on the GPU into the environment cube map. The irradiance and
+//! specular maps are then baked from it like from an image, so moving the sun means creating a
+//! new renderer.
+
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use super::{SkyRenderer, SkyRendererOptions};
+use crate::factories::texture::TextureBundle;
+use crate::state::State;
+
+/// Sun and atmosphere of a [`SkyRenderer::new_physical`] sky.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalSky {
+    /// Direction towards the sun, y up. The model ends at sunset, a sun below the horizon is
+    /// treated as if on it.
+    pub sun_direction: Vec3,
+    /// Haziness of the atmosphere, from 2 for a clear sky to 10 for a hazy one. Clamped to the
+    /// range the model was fitted to, 1.7 to 10.
+    pub turbidity: f32,
+    /// Linear color of the ground below the horizon, lit by the sky and the sun.
+    pub ground_albedo: Vec3,
+    /// Converts the luminance of the model, in kcd/m², to the values written to the cube map.
+    pub intensity: f32,
+    /// Angular radius of the sun disk in radians, 0 to leave it out.
+    pub sun_disk_radius: f32,
+    /// Brightness of the sun disk relative to the sky around it.
+    pub sun_disk_intensity: f32,
+}
+
+impl Default for PhysicalSky {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vec3::new(0.5, 0.7, 0.5),
+            turbidity: 2.5,
+            ground_albedo: Vec3::splat(0.3),
+            intensity: 0.05,
+            sun_disk_radius: 0.0047,
+            sun_disk_intensity: 100.0,
+        }
+    }
+}
+
+/// Uniform of `shader_physical_sky.wgsl`.
+#[repr(C, align(16))]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PhysicalSkyUniform {
+    perez: [[f32; 4]; 5],
+    zenith: [f32; 4],
+    sun_direction: [f32; 4],
+    ground: [f32; 4],
+}
+
+/// The model for one sun position and turbidity, the luminance Y and chromaticities x and y are
+/// kept together in a `Vec3`.
+struct Preetham {
+    sun_direction: Vec3,
+    perez: [Vec3; 5],
+    /// Zenith values divided by the Perez function at the zenith.
+    zenith: Vec3,
+}
+
+impl Preetham {
+    fn new(sky: &PhysicalSky) -> Self {
+        let t = sky.turbidity.clamp(1.7, 10.0);
+        let sun = sky.sun_direction.try_normalize().unwrap_or(Vec3::Y);
+        let sun_direction = Vec3::new(sun.x, sun.y.max(0.0), sun.z)
+            .try_normalize()
+            .unwrap_or(Vec3::X);
+        let theta_s = sun_direction.y.clamp(-1.0, 1.0).acos();
+
+        let perez = [
+            Vec3::new(
+                0.1787 * t - 1.4630,
+                -0.0193 * t - 0.2592,
+                -0.0167 * t - 0.2608,
+            ),
+            Vec3::new(
+                -0.3554 * t + 0.4275,
+                -0.0665 * t + 0.0008,
+                -0.0950 * t + 0.0092,
+            ),
+            Vec3::new(
+                -0.0227 * t + 5.3251,
+                -0.0004 * t + 0.2125,
+                -0.0079 * t + 0.2102,
+            ),
+            Vec3::new(
+                0.1206 * t - 2.5771,
+                -0.0641 * t - 0.8989,
+                -0.0441 * t - 1.6537,
+            ),
+            Vec3::new(
+                -0.0670 * t + 0.3703,
+                -0.0033 * t + 0.0452,
+                -0.0109 * t + 0.0529,
+            ),
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta = Vec3::new(theta_s.powi(3), theta_s.powi(2), theta_s);
+        let chromaticity = |coefficients: [[f32; 4]; 3]| {
+            let [t2, t1, t0] = coefficients.map(|c| theta.dot(Vec3::new(c[0], c[1], c[2])) + c[3]);
+            t * t * t2 + t * t1 + t0
+        };
+        let x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let mut model = Self {
+            sun_direction,
+            perez,
+            zenith: Vec3::ONE,
+        };
+        model.zenith = Vec3::new(luminance, x, y) / model.perez(1.0, theta_s);
+        model
+    }
+
+    fn perez(&self, cos_theta: f32, gamma: f32) -> Vec3 {
+        let [a, b, c, d, e] = self.perez;
+        let cos_gamma = gamma.cos();
+        (Vec3::ONE + a * (b / cos_theta.max(0.01)).exp())
+            * (Vec3::ONE + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+
+    /// Linear sRGB radiance of the sky in `direction`, without the sun disk and before scaling
+    /// by [`PhysicalSky::intensity`].
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let yxy = self.zenith * self.perez(direction.y, gamma);
+        let (luminance, x, y) = (yxy.x, yxy.y, yxy.z);
+        let xyz = Vec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        Vec3::new(
+            Vec3::new(3.2406, -1.5372, -0.4986).dot(xyz),
+            Vec3::new(-0.9689, 1.8758, 0.0415).dot(xyz),
+            Vec3::new(0.0557, -0.2040, 1.0570).dot(xyz),
+        )
+        .max(Vec3::ZERO)
+    }
+
+    /// Irradiance of the sky and the sun disk on the ground, integrated over the hemisphere.
+    fn ground_irradiance(&self, sky: &PhysicalSky) -> Vec3 {
+        const THETA_STEPS: u32 = 16;
+        const PHI_STEPS: u32 = 32;
+        let d_theta = std::f32::consts::FRAC_PI_2 / THETA_STEPS as f32;
+        let d_phi = std::f32::consts::TAU / PHI_STEPS as f32;
+
+        let mut irradiance = Vec3::ZERO;
+        for i in 0..THETA_STEPS {
+            let theta = (i as f32 + 0.5) * d_theta;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            for j in 0..PHI_STEPS {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let direction = Vec3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                irradiance += self.radiance(direction) * cos_theta * sin_theta * d_theta * d_phi;
+            }
+        }
+
+        let sun_solid_angle = std::f32::consts::TAU * (1.0 - sky.sun_disk_radius.cos());
+        let sun = self.radiance(self.sun_direction) * sky.sun_disk_intensity;
+        irradiance + sun * sun_solid_angle * self.sun_direction.y
+    }
+}
+
+impl PhysicalSky {
+    fn uniform(&self) -> PhysicalSkyUniform {
+        let model = Preetham::new(self);
+        let ground = self.ground_albedo * model.ground_irradiance(self) * self.intensity
+            / std::f32::consts::PI;
+
+        PhysicalSkyUniform {
+            perez: model
+                .perez
+                .map(|coefficients| coefficients.extend(0.0).to_array()),
+            zenith: model.zenith.extend(self.intensity).to_array(),
+            sun_direction: model
+                .sun_direction
+                .extend(self.sun_disk_radius.cos())
+                .to_array(),
+            ground: ground.extend(self.sun_disk_intensity).to_array(),
+        }
+    }
+}
+
+impl SkyRenderer {
+    /// Bakes the maps of a procedural daylight sky, e.g. for a time of day without HDR files.
+    /// [`SkyRendererOptions::dst_size`] is the face size of its cube map.
+    pub fn new_physical(state: &State, sky: &PhysicalSky, options: SkyRendererOptions) -> Self {
+        puffin::profile_function!();
+        let cube_map_texture = Self::create_cube_map_textures_from_physical(state, sky, &options);
+        Self::from_environment(state, cube_map_texture, None, &options)
+    }
+
+    pub fn create_cube_map_textures_from_physical(
+        state: &State,
+        sky: &PhysicalSky,
+        options: &SkyRendererOptions,
+    ) -> TextureBundle {
+        puffin::profile_function!();
+        let State { device, queue, .. } = state;
+        let SkyRendererOptions {
+            label, dst_size, ..
+        } = *options;
+
+        let cube = Self::create_environment_cube(device, label, dst_size);
+
+        let shader_module =
+            crate::include_shader!("shader_physical_sky.wgsl").create_module(device);
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Physical sky uniform"),
+            contents: bytemuck::bytes_of(&sky.uniform()),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Physical sky layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Physical sky pipeline layout"),
+            bind_group_layouts: &[Some(&layout)],
+            immediate_size: 0,
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Physical sky pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: Some("main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: state.pipeline_cache.wgpu_cache(),
+        });
+
+        let dst_view = cube.texture.create_view(&wgpu::TextureViewDescriptor {
+            label,
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Physical sky bind group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&dst_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label,
+                timestamp_writes: None,
+            });
+
+            let workgroups = dst_size.div_ceil(16);
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups, workgroups, 6);
+        }
+        queue.submit([encoder.finish()]);
+
+        cube
+    }
+}
@@ -1,4 +1,4 @@
-const PI: f32 = 3.1415926535897932384626433832795;
+#include "sampling.wgsl"
 
 
 @group(0)
@@ -6,22 +6,6 @@ const PI: f32 = 3.1415926535897932384626433832795;
 var dst: texture_storage_2d<rgba32float, write>;
 
 
-fn RadicalInverse_VdC(in_bits : u32) -> f32
-{
-    var bits = (in_bits << 16u) | (in_bits >> 16u);
-    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
-    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
-    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
-    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
-    return f32(bits) * 2.3283064365386963e-10; // / 0x100000000
-}
-// ----------------------------------------------------------------------------
-fn Hammersley(i : u32, N : u32) -> vec2<f32>
-{
-    return vec2<f32>(f32(i)/f32(N), RadicalInverse_VdC(i));
-}  
-
-
 fn GeometrySchlickGGX( NdotV : f32, roughness : f32) -> f32
 {
     var  a = roughness;
@@ -43,34 +27,6 @@ fn GeometrySmith( N : vec3<f32>, V : vec3<f32>, L : vec3<f32>, roughness : f32)
     return ggx1 * ggx2;
 } 
 
-fn ImportanceSampleGGX(Xi : vec2<f32>, N : vec3<f32>, roughness : f32) -> vec3<f32>
-{
-    var a = roughness*roughness;
-	
-    var phi = 2.0 * PI * Xi.x;
-    var cosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a*a - 1.0) * Xi.y));
-    var sinTheta = sqrt(1.0 - cosTheta*cosTheta);
-	
-    // from spherical coordinates to cartesian coordinates
-    var H : vec3<f32>;
-    H.x = cos(phi) * sinTheta;
-    H.y = sin(phi) * sinTheta;
-    H.z = cosTheta;
-	
-    // from tangent-space vector to world-space sample vector
-    var up = vec3(1.0, 0.0, 0.0);
-
-    if( abs(N.z) < 0.999 ){
-        up = vec3(0.0, 0.0, 1.0);
-    }
-
-    var tangent   = normalize(cross(up, N));
-    var bitangent = cross(N, tangent);
-	
-    var sampleVec = tangent * H.x + bitangent * H.y + N * H.z;
-    return normalize(sampleVec);
-}  
-
 fn IntegrateBRDF( NdotV : f32, roughness : f32) -> vec2<f32>
 {
     var V : vec3<f32> = vec3<f32>(0.0);
@@ -11,9 +11,8 @@ struct VertexOutput {
     @location(1) color: vec3<f32>,
 };
 
-struct CameraUniform {
-    view_proj : mat4x4<f32>,
-}
+#define CAMERA_VIEW_PROJ_ONLY
+#include "camera.wgsl"
 
 struct ModelUniform {
     model_matrix : mat4x4<f32>,
@@ -26,8 +25,13 @@ var<uniform> camera: CameraUniform;
 @group(0) @binding(1) // 1.
 var<uniform> modelUniform: ModelUniform;
 
+struct ConvolutionUniform {
+    roughness : f32,
+    sample_count : u32,
+}
+
 @group(0) @binding(2)
-var<uniform> roughnessUniform : f32;
+var<uniform> convolution : ConvolutionUniform;
 
 @group(1) @binding(0)
 var env_map: texture_cube<f32>;
@@ -44,7 +48,7 @@ fn vs_main( model : VertexInput ) -> VertexOutput {
 }
 
 
-const PI : f32 =3.14159265359;
+#include "sampling.wgsl"
 
 // Rotation matrix around the X axis.
 fn rotateX(theta : f32) -> mat3x3<f32> {
@@ -66,60 +70,24 @@ fn SampleSphericalMap(v : vec3<f32>) -> vec2<f32>
     return uv;
 }
 
-fn RadicalInverse_VdC(in_bits : u32) -> f32
-{
-    var bits = (in_bits << 16u) | (in_bits >> 16u);
-    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
-    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
-    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
-    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
-    return f32(bits) * 2.3283064365386963e-10; // / 0x100000000
-}
-// ----------------------------------------------------------------------------
-fn Hammersley(i : u32, N : u32) -> vec2<f32>
-{
-    return vec2<f32>(f32(i)/f32(N), RadicalInverse_VdC(i));
-}  
-
-fn ImportanceSampleGGX( Xi : vec2<f32>, N : vec3<f32>, roughness : f32)-> vec3<f32>{
-	let a = roughness * roughness;
-	let phi = 2.0 * PI * Xi.x;
-	let cosTheta = sqrt((1.0 - Xi.y) / (1.0 + (a*a - 1.0) * Xi.y));
-	let sinTheta = sqrt(1.0 - cosTheta*cosTheta);
-
-	var H = vec3(0.0);
-	H.x = cos(phi) * sinTheta;
-	H.y = sin(phi) * sinTheta;
-	H.z = cosTheta;
-
-	var up        = vec3<f32>(1.0, 0.0, 0.0);
-	if abs(N.z) < 0.999 {
-		up = vec3(0.0, 0.0, 1.0);
-	}
-
-	let tangent   = normalize(cross(up, N));
-	let bitangent = cross(N, tangent);
-	
-
-	let sampleVec = tangent * H.x + bitangent * H.y + N * H.z;
-	return normalize(sampleVec);
-}
-
 @fragment
 fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
+    // Each face pass draws the far side of the unit cube, which is the face's direction turned
+    // half way around the y axis.
     var coords = normalize(in.color);
+    coords = vec3(-coords.x, coords.y, -coords.z);
 
     var normal = coords.xyz;
     let N = normal;
     let V = normal;
 
-	let SAMPLE_COUNT : u32 = 1024u;
+	let SAMPLE_COUNT : u32 = convolution.sample_count;
 
 	var total_weight = 0.0;
 	var f_color = vec3<f32>(0.0);
 
-    var mip_level = camera.view_proj[0][0];
+    var mip_level = convolution.roughness;
 
 	for(var i : u32 = 0u; i < SAMPLE_COUNT; i++){
 		var x_i = Hammersley(i, SAMPLE_COUNT);
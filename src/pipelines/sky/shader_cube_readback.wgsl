@@ -0,0 +1,60 @@
+// Copies rows of one face of a cube map mip into a buffer, laid out like a buffer copy of them.
+// Used where the backend can't copy cube maps to buffers.
+
+struct Params {
+    size : u32,
+    mip : u32,
+    face : u32,
+    first_row : u32,
+    rows : u32,
+    // Pack the texels as Rgba16Float instead of Rgba32Float.
+    half_float : u32,
+    _pad0 : u32,
+    _pad1 : u32,
+}
+
+@group(0) @binding(0)
+var cube : texture_cube<f32>;
+@group(0) @binding(1)
+var cube_sampler : sampler;
+@group(0) @binding(2)
+var<uniform> params : Params;
+@group(0) @binding(3)
+var<storage, read_write> texels : array<u32>;
+
+// Direction through the texel at `st` (-1 to 1) of `face`, the inverse of the cube face selection.
+fn face_direction(face : u32, s : f32, t : f32) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -t, -s); }
+        case 1u: { return vec3<f32>(-1.0, -t, s); }
+        case 2u: { return vec3<f32>(s, 1.0, t); }
+        case 3u: { return vec3<f32>(s, -1.0, -t); }
+        case 4u: { return vec3<f32>(s, -t, 1.0); }
+        default: { return vec3<f32>(-s, -t, -1.0); }
+    }
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id : vec3<u32>) {
+    if (id.x >= params.size || id.y >= params.rows) {
+        return;
+    }
+
+    // Texel centres with a nearest sampler return the stored values unchanged.
+    let st = (vec2<f32>(f32(id.x), f32(id.y + params.first_row)) + 0.5) / f32(params.size) * 2.0 - 1.0;
+    let direction = face_direction(params.face, st.x, st.y);
+    let color = textureSampleLevel(cube, cube_sampler, direction, f32(params.mip));
+
+    let texel = id.y * params.size + id.x;
+    if (params.half_float != 0u) {
+        let index = texel * 2u;
+        texels[index] = pack2x16float(color.xy);
+        texels[index + 1u] = pack2x16float(color.zw);
+    } else {
+        let index = texel * 4u;
+        texels[index] = bitcast<u32>(color.x);
+        texels[index + 1u] = bitcast<u32>(color.y);
+        texels[index + 2u] = bitcast<u32>(color.z);
+        texels[index + 3u] = bitcast<u32>(color.w);
+    }
+}
@@ -1,4 +1,4 @@
-const PI: f32 = 3.1415926535897932384626433832795;
+#include "constants.wgsl"
 
 struct Face {
     forward: vec3<f32>,
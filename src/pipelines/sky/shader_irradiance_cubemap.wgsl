@@ -11,9 +11,8 @@ struct VertexOutput {
     @location(1) color: vec3<f32>,
 };
 
-struct CameraUniform {
-    view_proj : mat4x4<f32>,
-}
+#define CAMERA_VIEW_PROJ_ONLY
+#include "camera.wgsl"
 
 struct ModelUniform {
     model_matrix : mat4x4<f32>,
@@ -26,6 +25,14 @@ var<uniform> camera: CameraUniform;
 @group(0) @binding(1) // 1.
 var<uniform> modelUniform: ModelUniform;
 
+struct ConvolutionUniform {
+    roughness : f32,
+    sample_count : u32,
+}
+
+@group(0) @binding(2)
+var<uniform> convolution : ConvolutionUniform;
+
 @group(1) @binding(0)
 var env_map: texture_cube<f32>;
 @group(1) @binding(1)
@@ -40,14 +47,14 @@ var env_sampler: sampler;
 @vertex
 fn vs_main( model : VertexInput ) -> VertexOutput {
     var out: VertexOutput;
-    out.clip_position =  camera.view_proj * modelUniform.model_matrix * vec4(model.position, 1.0);
+    out.clip_position =  camera.view_proj_matrix * modelUniform.model_matrix * vec4(model.position, 1.0);
     out.uv = model.uv;
     out.color = model.position;
     return out;
 }
 
 
-const PI : f32 =3.14159265359;
+#include "constants.wgsl"
 
 // @group(0) @binding(2)
 // var<uniform> rotation_matrix : mat4x4<f32>;
@@ -80,8 +87,10 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
     //var cube_uv = SampleSphericalMap(spherical_coord);
 
 
+    // Each face pass draws the far side of the unit cube, which is the face's direction turned
+    // half way around the y axis.
     var coords = normalize(in.color);
-    coords.x = -coords.x;
+    coords = vec3(-coords.x, coords.y, -coords.z);
 
     var normal = coords.xyz;
     var irradiance = vec3(0.0);
@@ -91,11 +100,15 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
     up = normalize(cross(normal, right));
 
 
-    var sampleDelta = 0.05;
+    // sample_count steps over the elevation, four times as many around the normal
+    let theta_steps = convolution.sample_count;
+    let phi_steps = theta_steps * 4u;
     var nSamples = 0.0;
 
-    for(var phi = 0.0; phi < PI * 2.0; phi += sampleDelta){
-        for(var theta = 0.0; theta < PI * 0.5; theta += sampleDelta){   
+    for(var i = 0u; i < phi_steps; i++){
+        let phi = (f32(i) + 0.5) / f32(phi_steps) * PI * 2.0;
+        for(var j = 0u; j < theta_steps; j++){
+            let theta = (f32(j) + 0.5) / f32(theta_steps) * PI * 0.5;
             var tangent_sample = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
             var sample_vec = tangent_sample.x * right + tangent_sample.y * up + tangent_sample.z * normal;
 
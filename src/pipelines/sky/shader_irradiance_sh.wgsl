@@ -0,0 +1,116 @@
+// Projects an environment cube map onto 9 spherical harmonics and turns them into the diffuse
+// irradiance divided by PI, like the irradiance cube map stores.
+//
+// `project` sums 8x8 texels of a face per workgroup into `partials`, `reduce` adds the partial
+// sums up in a single workgroup.
+
+#include "constants.wgsl"
+#include "spherical_harmonics.wgsl"
+
+struct Params {
+    size : u32,
+    partial_count : u32,
+}
+
+@group(0) @binding(0)
+var env_map : texture_cube<f32>;
+@group(0) @binding(1)
+var env_sampler : sampler;
+@group(0) @binding(2)
+var<uniform> params : Params;
+// 9 sums per workgroup of `project`, the first one has the summed solid angle in w.
+@group(0) @binding(3)
+var<storage, read_write> partials : array<vec4<f32>>;
+@group(0) @binding(4)
+var<storage, read_write> coefficients : array<vec4<f32>, 9>;
+
+const WORKGROUP_SIZE : u32 = 64u;
+
+var<workgroup> sums : array<array<vec4<f32>, 9>, WORKGROUP_SIZE>;
+
+// Direction through the texel at `st` (-1 to 1) of `face`, the inverse of the cube face selection.
+fn face_direction(face : u32, s : f32, t : f32) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -t, -s); }
+        case 1u: { return vec3<f32>(-1.0, -t, s); }
+        case 2u: { return vec3<f32>(s, 1.0, t); }
+        case 3u: { return vec3<f32>(s, -1.0, -t); }
+        case 4u: { return vec3<f32>(s, -t, 1.0); }
+        default: { return vec3<f32>(-s, -t, -1.0); }
+    }
+}
+
+// Adds the sums of the other invocations into `sums[0]`.
+fn reduce_workgroup(index : u32) {
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+        workgroupBarrier();
+        if (index < stride) {
+            for (var i = 0u; i < 9u; i++) {
+                sums[index][i] += sums[index + stride][i];
+            }
+        }
+    }
+    workgroupBarrier();
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn project(
+    @builtin(global_invocation_id) id : vec3<u32>,
+    @builtin(local_invocation_index) index : u32,
+    @builtin(workgroup_id) workgroup : vec3<u32>,
+    @builtin(num_workgroups) workgroup_count : vec3<u32>,
+) {
+    for (var i = 0u; i < 9u; i++) {
+        sums[index][i] = vec4<f32>(0.0);
+    }
+
+    if (id.x < params.size && id.y < params.size) {
+        let st = (vec2<f32>(id.xy) + 0.5) / f32(params.size) * 2.0 - 1.0;
+        let texel = face_direction(id.z, st.x, st.y);
+        let direction = normalize(texel);
+        // Solid angle of the texel, it shrinks towards the corners of the face.
+        let texel_size = 2.0 / f32(params.size);
+        let solid_angle = texel_size * texel_size / pow(dot(texel, texel), 1.5);
+
+        let radiance = textureSampleLevel(env_map, env_sampler, direction, 0.0).rgb;
+        var basis = sh_basis(direction);
+        for (var i = 0u; i < 9u; i++) {
+            sums[index][i] = vec4<f32>(radiance * basis[i] * solid_angle, 0.0);
+        }
+        sums[index][0].w = solid_angle;
+    }
+
+    reduce_workgroup(index);
+
+    if (index == 0u) {
+        let partial = (workgroup.z * workgroup_count.y + workgroup.y) * workgroup_count.x
+            + workgroup.x;
+        for (var i = 0u; i < 9u; i++) {
+            partials[partial * 9u + i] = sums[0][i];
+        }
+    }
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn reduce(@builtin(local_invocation_index) index : u32) {
+    for (var i = 0u; i < 9u; i++) {
+        sums[index][i] = vec4<f32>(0.0);
+    }
+    for (var partial = index; partial < params.partial_count; partial += WORKGROUP_SIZE) {
+        for (var i = 0u; i < 9u; i++) {
+            sums[index][i] += partials[partial * 9u + i];
+        }
+    }
+
+    reduce_workgroup(index);
+
+    if (index == 0u) {
+        // The texel solid angles are approximate, scale them to add up to the whole sphere.
+        let normalization = 4.0 * PI / sums[0][0].w;
+        // Convolution with the clamped cosine, A_l / PI for each band.
+        var bands = array<f32, 9>(1.0, 2.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0, 0.25, 0.25, 0.25, 0.25, 0.25);
+        for (var i = 0u; i < 9u; i++) {
+            coefficients[i] = vec4<f32>(sums[0][i].rgb * bands[i] * normalization, 0.0);
+        }
+    }
+}
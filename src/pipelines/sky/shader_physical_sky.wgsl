@@ -0,0 +1,77 @@
+// Writes the Preetham daylight model into the faces of an environment cube map.
+
+struct PhysicalSky {
+    // Perez coefficients A to E, each for Y, x and y.
+    perez : array<vec4<f32>, 5>,
+    // Y, x and y at the zenith divided by the Perez function at the zenith, w is the intensity.
+    zenith : vec4<f32>,
+    // w is the cosine of the angular radius of the sun disk.
+    sun_direction : vec4<f32>,
+    // Radiance of the ground, w scales the sky around the sun into the sun disk.
+    ground : vec4<f32>,
+}
+
+@group(0) @binding(0)
+var dst : texture_storage_2d_array<rgba32float, write>;
+@group(0) @binding(1)
+var<uniform> sky : PhysicalSky;
+
+// Direction through the texel at `st` (-1 to 1) of `face`, the inverse of the cube face selection.
+fn face_direction(face : u32, s : f32, t : f32) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -t, -s); }
+        case 1u: { return vec3<f32>(-1.0, -t, s); }
+        case 2u: { return vec3<f32>(s, 1.0, t); }
+        case 3u: { return vec3<f32>(s, -1.0, -t); }
+        case 4u: { return vec3<f32>(s, -t, 1.0); }
+        default: { return vec3<f32>(-s, -t, -1.0); }
+    }
+}
+
+fn perez(cos_theta : f32, gamma : f32, cos_gamma : f32) -> vec3<f32> {
+    let a = sky.perez[0].xyz;
+    let b = sky.perez[1].xyz;
+    let c = sky.perez[2].xyz;
+    let d = sky.perez[3].xyz;
+    let e = sky.perez[4].xyz;
+    return (1.0 + a * exp(b / max(cos_theta, 0.01)))
+        * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn yxy_to_linear_srgb(yxy : vec3<f32>) -> vec3<f32> {
+    let luminance = yxy.x;
+    let xyz = vec3<f32>(
+        yxy.y / yxy.z * luminance,
+        luminance,
+        (1.0 - yxy.y - yxy.z) / yxy.z * luminance,
+    );
+    return vec3<f32>(
+        dot(vec3<f32>(3.2406, -1.5372, -0.4986), xyz),
+        dot(vec3<f32>(-0.9689, 1.8758, 0.0415), xyz),
+        dot(vec3<f32>(0.0557, -0.2040, 1.0570), xyz),
+    );
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id : vec3<u32>) {
+    let size = textureDimensions(dst).x;
+    if (id.x >= size || id.y >= size) {
+        return;
+    }
+
+    let st = (vec2<f32>(id.xy) + 0.5) / f32(size) * 2.0 - 1.0;
+    let direction = normalize(face_direction(id.z, st.x, st.y));
+
+    var color = sky.ground.rgb;
+    if (direction.y >= 0.0) {
+        let cos_gamma = clamp(dot(direction, sky.sun_direction.xyz), -1.0, 1.0);
+        let yxy = sky.zenith.xyz * perez(direction.y, acos(cos_gamma), cos_gamma);
+        color = max(yxy_to_linear_srgb(yxy), vec3<f32>(0.0)) * sky.zenith.w;
+
+        if (cos_gamma >= sky.sun_direction.w) {
+            color *= sky.ground.w;
+        }
+    }
+
+    textureStore(dst, id.xy, id.z, vec4<f32>(color, 1.0));
+}
@@ -1,3 +1,5 @@
+#include "sky_params.wgsl"
+
 struct VertexOutput {
     @builtin(position) frag_position  : vec4<f32>,
     @location(0) clip_position: vec4<f32>,
@@ -32,12 +34,27 @@ var env_sampler: sampler;
 @group(0) @binding(2)
 var<uniform> camera : CameraUniform;
 
+@group(0) @binding(3)
+var<uniform> params : SkyParams;
+@group(0) @binding(4)
+var blurred_env_map: texture_cube<f32>;
+@group(0) @binding(5)
+var blurred_env_sampler: sampler;
+
 @fragment
 fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
     let view_pos_homogeneous = camera.inv_proj * in.clip_position;
     let view_ray_direction = view_pos_homogeneous.xyz / view_pos_homogeneous.w;
     var ray_direction = normalize((camera.inv_view * vec4(view_ray_direction, 0.0)).xyz);
 
-    let sample = textureSample(env_map, env_sampler, ray_direction);
-    return sample;
+    if (params.background_color.w > 0.0) {
+        return vec4(params.background_color.rgb, 1.0);
+    }
+
+    let direction = sky_direction(params, ray_direction);
+    var sample = textureSample(env_map, env_sampler, direction);
+    if (params.background_mip > 0.0) {
+        sample = textureSampleLevel(blurred_env_map, blurred_env_sampler, direction, params.background_mip);
+    }
+    return vec4(sample.rgb * params.intensity, sample.a);
  }
\ No newline at end of file
@@ -0,0 +1,456 @@
+use std::fmt;
+
+/// Errors that can happen while reading a texture back to the CPU.
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Window states only keep a copy of the last frame after [`crate::state::State::set_frame_capture`] was enabled.
+    CaptureDisabled,
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// A single row of the texture, in bytes, is larger than the device allows buffers to be.
+    RowTooLarge(wgpu::BufferAddress),
+    Map(wgpu::BufferAsyncError),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::CaptureDisabled => write!(
+                f,
+                "frame capture is disabled, call State::set_frame_capture(true) before rendering"
+            ),
+            CaptureError::UnsupportedFormat(format) => {
+                write!(f, "can't read back textures with format {:?}", format)
+            }
+            CaptureError::RowTooLarge(size) => write!(
+                f,
+                "a texture row of {} bytes doesn't fit in a buffer of the device",
+                size
+            ),
+            CaptureError::Map(err) => write!(f, "failed to map the readback buffer: {}", err),
+            CaptureError::Image(err) => write!(f, "failed to encode the image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CaptureError::Map(err) => Some(err),
+            CaptureError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for CaptureError {
+    fn from(err: image::ImageError) -> Self {
+        CaptureError::Image(err)
+    }
+}
+
+/// Reads the first mip level of a 2d `texture` back into an sRGB encoded RGBA8 image.
+///
+/// The texture needs `COPY_SRC` usage. BGRA formats are swizzled and float formats are
+/// converted from linear to sRGB, so the result always looks like what is shown on screen.
+pub async fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<image::RgbaImage, CaptureError> {
+    let format = texture.format();
+    let bytes_per_pixel = match format.block_copy_size(None) {
+        Some(size) if is_supported_format(format) => size,
+        _ => return Err(CaptureError::UnsupportedFormat(format)),
+    };
+
+    let width = texture.width();
+    let height = texture.height();
+
+    let unpadded_bytes_per_row = width * bytes_per_pixel;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback buffer"),
+        size: padded_bytes_per_row as wgpu::BufferAddress * height as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback encoder"),
+    });
+
+    encoder.copy_texture_to_buffer(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::TexelCopyBufferInfo {
+            buffer: &output_buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    queue.submit(Some(encoder.finish()));
+
+    let buffer_slice = output_buffer.slice(..);
+    map_for_reading(device, buffer_slice).await?;
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    {
+        let data = buffer_slice.get_mapped_range();
+        for row in 0..height {
+            let start = row as usize * padded_bytes_per_row as usize;
+            let end = start + unpadded_bytes_per_row as usize;
+
+            for texel in data[start..end].chunks_exact(bytes_per_pixel as usize) {
+                pixels.extend_from_slice(&texel_to_srgb_rgba8(format, texel));
+            }
+        }
+    }
+    output_buffer.unmap();
+
+    Ok(image::RgbaImage::from_raw(width, height, pixels)
+        .expect("readback produced a buffer of the wrong size"))
+}
+
+/// Reads every mip level and array layer of `texture` back without any conversion.
+///
+/// The texels are tightly packed: mip levels in order, the array layers of each level, then the
+/// rows of each layer. The texture needs `COPY_SRC` usage and an uncompressed color format.
+pub async fn read_texture_data(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>, CaptureError> {
+    let format = texture.format();
+    let bytes_per_pixel = match format.block_copy_size(None) {
+        Some(size) if format.block_dimensions() == (1, 1) => size,
+        _ => return Err(CaptureError::UnsupportedFormat(format)),
+    };
+
+    let max_buffer_size = device.limits().max_buffer_size;
+    let mut regions = Vec::new();
+    for mip_level in 0..texture.mip_level_count() {
+        let size = texture
+            .size()
+            .mip_level_size(mip_level, texture.dimension());
+        let bytes_per_row = size.width * bytes_per_pixel;
+        let padded_bytes_per_row = bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let max_rows = rows_per_buffer(padded_bytes_per_row, max_buffer_size)?;
+
+        for layer in 0..size.depth_or_array_layers {
+            for first_row in (0..size.height).step_by(max_rows as usize) {
+                regions.push(ReadbackRegion {
+                    source: (mip_level, layer, first_row, size.width),
+                    rows: max_rows.min(size.height - first_row),
+                    bytes_per_row,
+                    padded_bytes_per_row,
+                });
+            }
+        }
+    }
+
+    read_regions(device, queue, regions, |encoder, region, buffer, offset| {
+        let (mip_level, layer, first_row, width) = region.source;
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: first_row,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset,
+                    bytes_per_row: Some(region.padded_bytes_per_row),
+                    rows_per_image: Some(region.rows),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height: region.rows,
+                depth_or_array_layers: 1,
+            },
+        );
+    })
+    .await
+}
+
+/// Rows of a texture copied into a readback buffer by [`read_regions`].
+pub(crate) struct ReadbackRegion<T> {
+    /// Where the rows come from, for the copy.
+    pub(crate) source: T,
+    pub(crate) rows: u32,
+    /// Bytes of a row kept in the result.
+    pub(crate) bytes_per_row: u32,
+    /// Distance between the rows in the readback buffer.
+    pub(crate) padded_bytes_per_row: u32,
+}
+
+impl<T> ReadbackRegion<T> {
+    fn size(&self) -> wgpu::BufferAddress {
+        self.padded_bytes_per_row as wgpu::BufferAddress * self.rows as wgpu::BufferAddress
+    }
+}
+
+/// How many rows of `padded_bytes_per_row` bytes fit in a buffer of `max_size` bytes.
+pub(crate) fn rows_per_buffer(
+    padded_bytes_per_row: u32,
+    max_size: wgpu::BufferAddress,
+) -> Result<u32, CaptureError> {
+    let rows = max_size / padded_bytes_per_row as wgpu::BufferAddress;
+    if rows == 0 {
+        return Err(CaptureError::RowTooLarge(
+            padded_bytes_per_row as wgpu::BufferAddress,
+        ));
+    }
+    Ok(rows.min(u32::MAX as wgpu::BufferAddress) as u32)
+}
+
+/// Reads `regions` back in order, each no larger than the device's `max_buffer_size`, and
+/// returns their rows tightly packed.
+///
+/// The regions are gathered into readback buffers of at most `max_buffer_size` bytes, `copy`
+/// records the copy of each region into its buffer at the given offset. Every buffer is
+/// submitted and read before the next one is filled, so huge textures don't need all their
+/// staging memory at once.
+pub(crate) async fn read_regions<T>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    regions: impl IntoIterator<Item = ReadbackRegion<T>>,
+    mut copy: impl FnMut(
+        &mut wgpu::CommandEncoder,
+        &ReadbackRegion<T>,
+        &wgpu::Buffer,
+        wgpu::BufferAddress,
+    ),
+) -> Result<Vec<u8>, CaptureError> {
+    let max_buffer_size = device.limits().max_buffer_size;
+    let mut data = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+
+    for region in regions {
+        if batch_size + region.size() > max_buffer_size && !batch.is_empty() {
+            read_batch(device, queue, &batch, batch_size, &mut copy, &mut data).await?;
+            batch.clear();
+            batch_size = 0;
+        }
+        batch_size += region.size();
+        batch.push(region);
+    }
+    if !batch.is_empty() {
+        read_batch(device, queue, &batch, batch_size, &mut copy, &mut data).await?;
+    }
+
+    Ok(data)
+}
+
+async fn read_batch<T>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    regions: &[ReadbackRegion<T>],
+    size: wgpu::BufferAddress,
+    copy: &mut impl FnMut(
+        &mut wgpu::CommandEncoder,
+        &ReadbackRegion<T>,
+        &wgpu::Buffer,
+        wgpu::BufferAddress,
+    ),
+    data: &mut Vec<u8>,
+) -> Result<(), CaptureError> {
+    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback encoder"),
+    });
+    let mut offset = 0;
+    for region in regions {
+        copy(&mut encoder, region, &output_buffer, offset);
+        offset += region.size();
+    }
+    queue.submit(Some(encoder.finish()));
+
+    let buffer_slice = output_buffer.slice(..);
+    map_for_reading(device, buffer_slice).await?;
+    {
+        let mapped = buffer_slice.get_mapped_range();
+        let mut offset = 0;
+        for region in regions {
+            for row in 0..region.rows as usize {
+                let start = offset + row * region.padded_bytes_per_row as usize;
+                data.extend_from_slice(&mapped[start..start + region.bytes_per_row as usize]);
+            }
+            offset += region.size() as usize;
+        }
+    }
+    output_buffer.unmap();
+
+    Ok(())
+}
+
+/// Blocking version of [`read_texture_data`].
+pub fn read_texture_data_blocking(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>, CaptureError> {
+    pollster::block_on(read_texture_data(device, queue, texture))
+}
+
+/// Maps `buffer_slice` and waits until it can be read.
+pub(crate) async fn map_for_reading(
+    device: &wgpu::Device,
+    buffer_slice: wgpu::BufferSlice<'_>,
+) -> Result<(), CaptureError> {
+    let (tx, rx) = futures::channel::oneshot::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+
+    // Native backends only run the map callback while the device is polled.
+    let _ = device.poll(wgpu::PollType::wait_indefinitely());
+
+    match rx.await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(CaptureError::Map(err)),
+        Err(_) => Err(CaptureError::Map(wgpu::BufferAsyncError)),
+    }
+}
+
+/// Blocking version of [`read_texture`].
+pub fn read_texture_blocking(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<image::RgbaImage, CaptureError> {
+    pollster::block_on(read_texture(device, queue, texture))
+}
+
+/// Saves `image` to `path`, picking the encoder from the file extension.
+pub fn save_image(image: &image::RgbaImage, path: impl AsRef<std::path::Path>) -> Result<(), CaptureError> {
+    let path = path.as_ref();
+    let format = image::ImageFormat::from_path(path)?;
+
+    match format {
+        // These encoders don't support an alpha channel.
+        image::ImageFormat::Jpeg | image::ImageFormat::Hdr => {
+            image::DynamicImage::ImageRgba8(image.clone())
+                .to_rgb8()
+                .save_with_format(path, format)?;
+        }
+        _ => image.save_with_format(path, format)?,
+    }
+
+    Ok(())
+}
+
+fn is_supported_format(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::*;
+    matches!(
+        format,
+        Rgba8Unorm
+            | Rgba8UnormSrgb
+            | Bgra8Unorm
+            | Bgra8UnormSrgb
+            | Rgb10a2Unorm
+            | Rgba16Float
+            | Rgba32Float
+    )
+}
+
+/// Converts a single texel to sRGB encoded RGBA8.
+///
+/// 8 bit and 10 bit formats are stored as-is: sRGB formats already hold encoded values and
+/// linear unorm swapchains show their bytes without any conversion.
+fn texel_to_srgb_rgba8(format: wgpu::TextureFormat, texel: &[u8]) -> [u8; 4] {
+    use wgpu::TextureFormat::*;
+    match format {
+        Rgba8Unorm | Rgba8UnormSrgb => [texel[0], texel[1], texel[2], texel[3]],
+        Bgra8Unorm | Bgra8UnormSrgb => [texel[2], texel[1], texel[0], texel[3]],
+        Rgb10a2Unorm => {
+            let packed = u32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]);
+            let channel = |shift: u32| (((packed >> shift) & 0x3ff) * 255 / 0x3ff) as u8;
+            [
+                channel(0),
+                channel(10),
+                channel(20),
+                ((packed >> 30) * 255 / 3) as u8,
+            ]
+        }
+        Rgba16Float => {
+            let channel = |i: usize| f16_to_f32(u16::from_le_bytes([texel[i * 2], texel[i * 2 + 1]]));
+            linear_to_srgb_rgba8([channel(0), channel(1), channel(2), channel(3)])
+        }
+        Rgba32Float => {
+            let channel = |i: usize| {
+                f32::from_le_bytes([
+                    texel[i * 4],
+                    texel[i * 4 + 1],
+                    texel[i * 4 + 2],
+                    texel[i * 4 + 3],
+                ])
+            };
+            linear_to_srgb_rgba8([channel(0), channel(1), channel(2), channel(3)])
+        }
+        _ => unreachable!("unsupported readback format {:?}", format),
+    }
+}
+
+fn linear_to_srgb_rgba8(color: [f32; 4]) -> [u8; 4] {
+    let encode = |c: f32| {
+        let c = c.clamp(0.0, 1.0);
+        if c <= 0.003_130_8 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    let to_u8 = |c: f32| (c * 255.0 + 0.5) as u8;
+
+    [
+        to_u8(encode(color[0])),
+        to_u8(encode(color[1])),
+        to_u8(encode(color[2])),
+        to_u8(color[3].clamp(0.0, 1.0)),
+    ]
+}
+
+/// Converts an IEEE 754 half precision float to `f32`.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
@@ -1,13 +1,17 @@
 use wgpu::{
-    self, AddressMode, CommandEncoder, CurrentSurfaceTexture, Features, TexelCopyTextureInfoBase, TextureFormat, TextureView
+    self, AddressMode, CommandEncoder, CurrentSurfaceTexture, Features, TextureFormat, TextureView
 };
 use winit::dpi::PhysicalSize;
 
-use crate::factories;
+use std::fmt;
+
+use crate::factories::PipelineCache;
+use crate::readback::{self, CaptureError};
+use crate::Error;
 
 use super::factories::texture::{DepthTextureFactory, Texture2dFactory, TextureBundle};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Size {
     pub width: u32,
     pub height: u32,
@@ -35,18 +39,32 @@ impl Size {
     }
 }
 
+/// Where the frames produced by [`State::render`] end up.
+pub enum RenderTarget {
+    /// Frames are presented to a window surface.
+    Window(wgpu::Surface<'static>),
+    /// Frames are rendered into an offscreen texture, no window or display is needed.
+    Offscreen(TextureBundle),
+}
+
 pub struct State {
     pub instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
 
+    /// Describes the render target, for offscreen targets only the size, format and usage are relevant.
     pub config: wgpu::SurfaceConfiguration,
-    pub window_surface: wgpu::Surface<'static>,
+    pub render_target: RenderTarget,
 
     pub depth_texture: Option<TextureBundle>,
 
+    /// Multisampled color target that gets resolved into the frame, `None` when `sample_count` is 1.
+    pub multisampled_texture: Option<TextureBundle>,
+
     pub default_white_texture_bundle: TextureBundle,
+    /// Flat tangent space normal map in a linear format, for materials without one.
+    pub default_normal_texture_bundle: TextureBundle,
 
     pub window_size: Size,
 
@@ -54,61 +72,530 @@ pub struct State {
 
     pub sample_count: u32,
 
+    /// Color format the scene is drawn in, pipelines built with
+    /// [`crate::factories::RenderPipelineFactory`] target it by default.
+    pub scene_format: wgpu::TextureFormat,
+
+    /// Copy of the last presented frame, only kept when enabled via [`State::set_frame_capture`].
+    pub frame_capture: Option<TextureBundle>,
+
+    /// Shared shader modules, layouts and pipelines, see [`PipelineCache`].
+    pub pipeline_cache: PipelineCache,
+}
+
+/// How presentation is synchronized with the display refresh.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VSync {
+    /// Wait for vertical blank, never tears. Supported everywhere.
+    On,
+    /// Present immediately, may tear. Falls back to [`VSync::Mailbox`] and then [`VSync::On`].
+    Off,
+    /// Replace the queued frame with the newest one, low latency without tearing. Falls back to [`VSync::On`].
+    Mailbox,
+}
+
+impl VSync {
+    fn present_mode(self, caps: &wgpu::SurfaceCapabilities) -> wgpu::PresentMode {
+        let preferred: &[wgpu::PresentMode] = match self {
+            VSync::On => &[wgpu::PresentMode::Fifo],
+            VSync::Off => &[wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox],
+            VSync::Mailbox => &[wgpu::PresentMode::Mailbox],
+        };
+
+        preferred
+            .iter()
+            .copied()
+            .find(|mode| caps.present_modes.contains(mode))
+            .unwrap_or(wgpu::PresentMode::Fifo)
+    }
+}
+
+/// Options used to create a [`State`] and configure its window surface.
+///
+/// Headless states ignore the surface related options.
+#[derive(Clone, Debug)]
+pub struct StateOptions {
+    /// Features the device must have, creating the state fails without them.
+    pub required_features: wgpu::Features,
+    /// Features that are enabled when the adapter supports them, check `device.features()` to see
+    /// which ones you got.
+    pub optional_features: wgpu::Features,
+    pub required_limits: wgpu::Limits,
+    pub required_downlevel_capabilities: wgpu::DownlevelCapabilities,
+
+    pub sample_count: u32,
+    pub vsync: VSync,
+    /// Pick an sRGB surface format when available, otherwise a linear one.
+    pub prefer_srgb: bool,
+    /// Use a non opaque alpha mode so the window can be composited with what is behind it.
+    pub transparent: bool,
+    pub desired_maximum_frame_latency: u32,
+    pub power_preference: wgpu::PowerPreference,
+    /// Format of the scene when it isn't drawn straight into the surface, e.g.
+    /// [`crate::pipelines::post::SCENE_FORMAT`]. `None` uses the surface format.
+    pub scene_format: Option<wgpu::TextureFormat>,
+    /// Directory the driver pipeline cache is loaded from and saved to with
+    /// [`PipelineCache::save`]. Only used on backends with [`wgpu::Features::PIPELINE_CACHE`].
+    pub pipeline_cache_dir: Option<std::path::PathBuf>,
+}
+
+impl Default for StateOptions {
+    fn default() -> Self {
+        Self {
+            required_features: wgpu::Features::empty(),
+            optional_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::default(),
+            required_downlevel_capabilities: wgpu::DownlevelCapabilities {
+                flags: wgpu::DownlevelFlags::empty(),
+                shader_model: wgpu::ShaderModel::Sm5,
+                ..wgpu::DownlevelCapabilities::default()
+            },
+
+            sample_count: 4,
+            vsync: VSync::On,
+            prefer_srgb: true,
+            transparent: false,
+            desired_maximum_frame_latency: 2,
+            power_preference: wgpu::PowerPreference::default(),
+            scene_format: None,
+            pipeline_cache_dir: None,
+        }
+    }
+}
 
-    // -----
-    pub temp_texture : Option<TextureBundle>,
+/// A limit the adapter can't provide, see [`DeviceRequirementsError::UnsupportedLimits`].
+#[derive(Clone, Debug)]
+pub struct LimitFailure {
+    pub name: &'static str,
+    pub requested: u64,
+    pub allowed: u64,
 }
 
+/// Why a [`State`] couldn't be created with the requested [`StateOptions`].
+#[derive(Debug)]
+pub enum DeviceRequirementsError {
+    NoAdapter(wgpu::RequestAdapterError),
+    MissingFeatures(wgpu::Features),
+    MissingDownlevelFlags(wgpu::DownlevelFlags),
+    UnsupportedShaderModel {
+        required: wgpu::ShaderModel,
+        supported: wgpu::ShaderModel,
+    },
+    UnsupportedLimits(Vec<LimitFailure>),
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for DeviceRequirementsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceRequirementsError::NoAdapter(err) => write!(f, "no suitable adapter: {}", err),
+            DeviceRequirementsError::MissingFeatures(features) => {
+                write!(f, "the adapter is missing required features: {}", features)
+            }
+            DeviceRequirementsError::MissingDownlevelFlags(flags) => {
+                write!(f, "the adapter is missing downlevel capabilities: {:?}", flags)
+            }
+            DeviceRequirementsError::UnsupportedShaderModel {
+                required,
+                supported,
+            } => write!(
+                f,
+                "the adapter supports shader model {:?}, {:?} is required",
+                supported, required
+            ),
+            DeviceRequirementsError::UnsupportedLimits(failures) => {
+                write!(f, "the adapter doesn't support the required limits:")?;
+                for failure in failures {
+                    write!(
+                        f,
+                        " {} (requested {}, allowed {})",
+                        failure.name, failure.requested, failure.allowed
+                    )?;
+                }
+                Ok(())
+            }
+            DeviceRequirementsError::RequestDevice(err) => {
+                write!(f, "failed to create the device: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeviceRequirementsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DeviceRequirementsError::NoAdapter(err) => Some(err),
+            DeviceRequirementsError::RequestDevice(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A sample count [`State::set_sample_count`] can't render with.
+#[derive(Clone, Debug)]
+pub struct UnsupportedSampleCount {
+    pub sample_count: u32,
+    /// The first of the surface and scene formats that doesn't support it.
+    pub format: wgpu::TextureFormat,
+}
+
+impl fmt::Display for UnsupportedSampleCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x MSAA is not supported for {:?}",
+            self.sample_count, self.format
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedSampleCount {}
+
 pub struct PerFrameData {
     pub encoder: CommandEncoder,
     pub view: TextureView,
-    pub multisampled_view: TextureView,
+    /// View of [`State::multisampled_texture`], `None` when rendering without MSAA.
+    pub multisampled_view: Option<TextureView>,
 }
 
 impl State {
+    /// Creates a state that renders to `window_surface`.
+    ///
+    /// # Panics
+    ///
+    /// When no adapter meets the requirements in `options`, see [`State::try_new`].
     pub async fn new(
-        sample_count: u32,
         instance: wgpu::Instance,
         window_surface: wgpu::Surface<'static>,
         window_size: Size,
+        options: &StateOptions,
     ) -> State {
+        Self::try_new(instance, window_surface, window_size, options)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub async fn try_new(
+        instance: wgpu::Instance,
+        window_surface: wgpu::Surface<'static>,
+        window_size: Size,
+        options: &StateOptions,
+    ) -> Result<State, Error> {
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: options.power_preference,
                 compatible_surface: Some(&window_surface),
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap();
+            .map_err(DeviceRequirementsError::NoAdapter)?;
+
+        let (device, queue) = Self::request_device(&adapter, options).await?;
+
+        let config = Self::surface_config(&window_surface, &adapter, window_size, options);
+        window_surface.configure(&device, &config);
+
+        Ok(Self::from_parts(
+            instance,
+            adapter,
+            device,
+            queue,
+            RenderTarget::Window(window_surface),
+            config,
+            window_size,
+            options.sample_count,
+            options.scene_format,
+            options.pipeline_cache_dir.as_deref(),
+        ))
+    }
+
+    /// The only place where window surfaces get their format, present mode and alpha mode.
+    fn surface_config(
+        surface: &wgpu::Surface,
+        adapter: &wgpu::Adapter,
+        size: Size,
+        options: &StateOptions,
+    ) -> wgpu::SurfaceConfiguration {
+        let caps = surface.get_capabilities(adapter);
+
+        let format = caps
+            .formats
+            .iter()
+            .copied()
+            .find(|format| format.is_srgb() == options.prefer_srgb)
+            .unwrap_or(caps.formats[0]);
+
+        let alpha_mode = if options.transparent {
+            [
+                wgpu::CompositeAlphaMode::PreMultiplied,
+                wgpu::CompositeAlphaMode::PostMultiplied,
+                wgpu::CompositeAlphaMode::Inherit,
+            ]
+            .into_iter()
+            .find(|mode| caps.alpha_modes.contains(mode))
+            .unwrap_or(caps.alpha_modes[0])
+        } else if caps.alpha_modes.contains(&wgpu::CompositeAlphaMode::Opaque) {
+            wgpu::CompositeAlphaMode::Opaque
+        } else {
+            caps.alpha_modes[0]
+        };
+
+        wgpu::SurfaceConfiguration {
+            // COPY_SRC is only needed for frame capture, not every platform supports it.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (caps.usages & wgpu::TextureUsages::COPY_SRC),
+            format,
+            view_formats: Vec::new(),
+            width: size.width,
+            height: size.height,
+            alpha_mode,
+            present_mode: options.vsync.present_mode(&caps),
+            desired_maximum_frame_latency: options.desired_maximum_frame_latency,
+        }
+    }
+
+    /// Changes the present mode of the window surface, does nothing for headless states.
+    pub fn set_vsync(&mut self, vsync: VSync) {
+        if let RenderTarget::Window(surface) = &self.render_target {
+            let caps = surface.get_capabilities(&self.adapter);
+            self.config.present_mode = vsync.present_mode(&caps);
+            surface.configure(&self.device, &self.config);
+        }
+    }
+
+    /// Creates a state that renders into an offscreen texture instead of a window surface.
+    ///
+    /// The backend can be picked with the `WGPU_BACKEND` environment variable, and if no hardware
+    /// adapter is found a fallback (software) adapter such as lavapipe is used.
+    ///
+    /// # Panics
+    ///
+    /// When no adapter is found, see [`State::new_headless_with_options`] for the other options.
+    pub async fn new_headless(size: Size, sample_count: u32, format: TextureFormat) -> State {
+        let options = StateOptions {
+            sample_count,
+            ..Default::default()
+        };
+        Self::new_headless_with_options(size, format, &options).await
+    }
+
+    /// Like [`State::new_headless`], with the features, limits and the rest of `options`.
+    ///
+    /// # Panics
+    ///
+    /// When no adapter meets the requirements in `options`, see [`State::try_new_headless`].
+    pub async fn new_headless_with_options(
+        size: Size,
+        format: TextureFormat,
+        options: &StateOptions,
+    ) -> State {
+        Self::try_new_headless(size, format, options)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub async fn try_new_headless(
+        size: Size,
+        format: TextureFormat,
+        options: &StateOptions,
+    ) -> Result<State, Error> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+
+        let adapter_options = wgpu::RequestAdapterOptions {
+            power_preference: options.power_preference,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        };
+
+        let adapter = match instance.request_adapter(&adapter_options).await {
+            Ok(adapter) => adapter,
+            Err(_) => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    force_fallback_adapter: true,
+                    ..adapter_options
+                })
+                .await
+                .map_err(DeviceRequirementsError::NoAdapter)?,
+        };
+
+        let (device, queue) = Self::request_device(&adapter, options).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            format,
+            view_formats: Vec::new(),
+            width: size.width,
+            height: size.height,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+        };
+
+        let offscreen_texture = Self::create_offscreen_texture(&device, &config);
+
+        Ok(Self::from_parts(
+            instance,
+            adapter,
+            device,
+            queue,
+            RenderTarget::Offscreen(offscreen_texture),
+            config,
+            size,
+            options.sample_count,
+            options.scene_format,
+            options.pipeline_cache_dir.as_deref(),
+        ))
+    }
+
+    /// Checks `options` against what the adapter offers and creates the device.
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+        options: &StateOptions,
+    ) -> Result<(wgpu::Device, wgpu::Queue), DeviceRequirementsError> {
+        let adapter_features = adapter.features();
+        let missing_features = options.required_features - adapter_features;
+        if !missing_features.is_empty() {
+            return Err(DeviceRequirementsError::MissingFeatures(missing_features));
+        }
+
+        let downlevel = adapter.get_downlevel_capabilities();
+        let required_downlevel = &options.required_downlevel_capabilities;
+        let missing_flags = required_downlevel.flags - downlevel.flags;
+        if !missing_flags.is_empty() {
+            return Err(DeviceRequirementsError::MissingDownlevelFlags(missing_flags));
+        }
+        if downlevel.shader_model < required_downlevel.shader_model {
+            return Err(DeviceRequirementsError::UnsupportedShaderModel {
+                required: required_downlevel.shader_model,
+                supported: downlevel.shader_model,
+            });
+        }
+
+        let adapter_limits = adapter.limits();
+        let mut limit_failures = Vec::new();
+        options.required_limits.check_limits_with_fail_fn(
+            &adapter_limits,
+            false,
+            |name, requested, allowed| {
+                limit_failures.push(LimitFailure {
+                    name,
+                    requested,
+                    allowed,
+                })
+            },
+        );
+        if !limit_failures.is_empty() {
+            return Err(DeviceRequirementsError::UnsupportedLimits(limit_failures));
+        }
+
+        // The built-in pipelines use adapter specific formats (e.g. float32 storage textures)
+        // whenever they can.
+        let mut optional_features =
+            options.optional_features | Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        if options.pipeline_cache_dir.is_some() {
+            optional_features |= Features::PIPELINE_CACHE;
+        }
 
-        let (device, queue) = adapter
+        adapter
             .request_device(&wgpu::DeviceDescriptor {
-                required_features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                required_limits: wgpu::Limits::default(),
+                required_features: options.required_features
+                    | (optional_features & adapter_features),
+                // Allow render targets as large as the adapter supports, e.g. for hi-dpi windows.
+                required_limits: options.required_limits.clone().using_resolution(adapter_limits),
                 experimental_features: wgpu::ExperimentalFeatures::disabled(),
                 memory_hints: wgpu::MemoryHints::default(),
                 trace: wgpu::Trace::default(),
                 label: None,
             })
             .await
-            .unwrap();
+            .map_err(DeviceRequirementsError::RequestDevice)
+    }
 
-        let surface_caps = window_surface.get_capabilities(&adapter);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-            format: surface_caps.formats[0],
-            view_formats: Vec::new(),
-            width: window_size.width,
-            height: window_size.height,
-            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
-            present_mode: surface_caps.present_modes[0],
-            desired_maximum_frame_latency: 2,
-        };
+    fn create_offscreen_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> TextureBundle {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen render target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: config.usage,
+            view_formats: &[],
+        });
 
-        window_surface.configure(&device, &config);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        TextureBundle {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    fn create_multisampled_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Option<TextureBundle> {
+        if sample_count <= 1 {
+            return None;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Multisampled frame"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Some(TextureBundle {
+            texture,
+            view,
+            sampler,
+        })
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn from_parts(
+        instance: wgpu::Instance,
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        render_target: RenderTarget,
+        config: wgpu::SurfaceConfiguration,
+        window_size: Size,
+        sample_count: u32,
+        scene_format: Option<wgpu::TextureFormat>,
+        pipeline_cache_dir: Option<&std::path::Path>,
+    ) -> State {
+        let scene_format = scene_format.unwrap_or(config.format);
+        let pipeline_cache = PipelineCache::new(&device, &adapter, pipeline_cache_dir);
         let depth_texture =
             DepthTextureFactory::new(&device, &config, sample_count, "Default Depth texture");
+        let multisampled_texture =
+            Self::create_multisampled_texture(&device, &config, sample_count);
 
         let mut tf = Texture2dFactory::new(2, 2);
 
@@ -136,6 +623,23 @@ impl State {
 
         let texture_bundle = tf.get_texture_and_sampler(&device, &queue, &data);
 
+        tf.set_texture_descriptor(wgpu::TextureDescriptor {
+            label: Some("Default normal texture"),
+            size: wgpu::Extent3d {
+                width: 2,
+                height: 2,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let normal_texture_bundle =
+            tf.get_texture_and_sampler(&device, &queue, &[128, 128, 255, 255].repeat(4));
+
         State {
             instance,
             adapter,
@@ -143,34 +647,102 @@ impl State {
             device,
             queue,
 
-            window_surface,
+            render_target,
             config,
             depth_texture: Some(depth_texture),
+            multisampled_texture,
 
             default_white_texture_bundle: texture_bundle,
+            default_normal_texture_bundle: normal_texture_bundle,
 
             delta_time: 0.0,
             window_size,
             sample_count,
+            scene_format,
+
+            frame_capture: None,
 
-            temp_texture : None,
+            pipeline_cache,
         }
     }
 
+    /// Returns the window surface, or `None` for headless states.
+    pub fn window_surface(&self) -> Option<&wgpu::Surface<'static>> {
+        match &self.render_target {
+            RenderTarget::Window(surface) => Some(surface),
+            RenderTarget::Offscreen(_) => None,
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        matches!(self.render_target, RenderTarget::Offscreen(_))
+    }
+
+    /// Resizes the render target and every size dependent texture.
+    ///
+    /// Zero sized requests (e.g. a minimised window) are ignored and the previous targets are kept.
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.window_surface.configure(&self.device, &self.config);
-
-            if self.depth_texture.is_some() {
-                self.depth_texture = Some(DepthTextureFactory::new(
-                    &self.device,
-                    &self.config,
-                    self.get_sample_count(),
-                    "Default Depth texture",
-                ));
+            self.window_size = Size::new(new_size.width, new_size.height);
+
+            match &mut self.render_target {
+                RenderTarget::Window(surface) => surface.configure(&self.device, &self.config),
+                RenderTarget::Offscreen(texture) => {
+                    *texture = Self::create_offscreen_texture(&self.device, &self.config)
+                }
+            }
+
+            if self.frame_capture.is_some() {
+                self.frame_capture = Some(Self::create_capture_texture(&self.device, &self.config));
             }
+
+            self.recreate_multisampled_targets();
+        }
+    }
+
+    /// Changes the MSAA sample count and recreates the color and depth targets.
+    ///
+    /// Pipelines bake in their sample count, so every pipeline drawing into these targets has to
+    /// be rebuilt before the next draw: the library's own with `recreate_pipelines`, e.g.
+    /// [`crate::pipelines::pbr::PbrPipeline::recreate_pipelines`], the application's in
+    /// [`crate::framework::Application::on_sample_count_changed`]. Cached pipelines for the old
+    /// sample count stay in [`State::pipeline_cache`] until it is cleared.
+    ///
+    /// The count has to be supported by both the surface and the [`State::scene_format`],
+    /// otherwise the current one is kept.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<(), UnsupportedSampleCount> {
+        if sample_count == self.sample_count {
+            return Ok(());
+        }
+
+        for format in [self.config.format, self.scene_format] {
+            let format_features = self.adapter.get_texture_format_features(format);
+            if sample_count != 1 && !format_features.flags.sample_count_supported(sample_count) {
+                return Err(UnsupportedSampleCount {
+                    sample_count,
+                    format,
+                });
+            }
+        }
+
+        self.sample_count = sample_count;
+        self.recreate_multisampled_targets();
+        Ok(())
+    }
+
+    fn recreate_multisampled_targets(&mut self) {
+        self.multisampled_texture =
+            Self::create_multisampled_texture(&self.device, &self.config, self.sample_count);
+
+        if self.depth_texture.is_some() {
+            self.depth_texture = Some(DepthTextureFactory::new(
+                &self.device,
+                &self.config,
+                self.get_sample_count(),
+                "Default Depth texture",
+            ));
         }
     }
 
@@ -184,210 +756,102 @@ impl State {
                 label: Some("Render Encoder"),
             });
 
-        let current_surface_texture = self.window_surface.get_current_texture();
-    
-            
-        let output_surface = match current_surface_texture {
-            CurrentSurfaceTexture::Success(output_surface) => output_surface,
-            CurrentSurfaceTexture::Suboptimal(output_surface)=> output_surface,
-            _ => return,
+        let (output_surface, view) = match &self.render_target {
+            RenderTarget::Window(surface) => {
+                let output_surface = match surface.get_current_texture() {
+                    CurrentSurfaceTexture::Success(output_surface) => output_surface,
+                    CurrentSurfaceTexture::Suboptimal(output_surface) => output_surface,
+                    CurrentSurfaceTexture::Outdated => {
+                        // Skip this frame, the next one picks up the new configuration.
+                        surface.configure(&self.device, &self.config);
+                        return;
+                    }
+                    _ => return,
+                };
+
+                let view = output_surface
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output_surface), view)
+            }
+            RenderTarget::Offscreen(texture) => (None, texture.view.clone()),
         };
 
-        let view = output_surface.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let multisampled_view = self
+            .multisampled_texture
+            .as_ref()
+            .map(|texture| texture.view.clone());
 
+        let mut per_frame_data = PerFrameData {
+            view,
+            encoder,
+            multisampled_view,
+        };
 
-            let multisampled_texture_extent = wgpu::Extent3d {
-                width: self.config.width,
-                height: self.config.height,
-                depth_or_array_layers: 1,
-            };
-
-            let multisampled_frame_descriptor = wgpu::TextureDescriptor {
-                size: multisampled_texture_extent,
-                mip_level_count: 1,
-                sample_count: self.get_sample_count(),
-                view_formats: &[TextureFormat::Bgra8UnormSrgb],
-                dimension: wgpu::TextureDimension::D2,
-                format: self.config.format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-                label: None,
-            };
-
-            let multisampled_view = self
-                .device
-                .create_texture(&multisampled_frame_descriptor)
-                .create_view(&wgpu::TextureViewDescriptor{
-                    usage : Some(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING),
-                    ..Default::default()
-                });
+        {
+            render_callback(self, &mut per_frame_data);
+        }
 
-            let mut per_frame_data = PerFrameData {
-                view,
-                encoder,
-                multisampled_view,
-            };
+        if let (Some(output_surface), Some(capture)) = (&output_surface, &self.frame_capture) {
+            per_frame_data.encoder.copy_texture_to_texture(
+                output_surface.texture.as_image_copy(),
+                capture.texture.as_image_copy(),
+                capture.texture.size(),
+            );
+        }
 
-            {
-                render_callback(self, &mut per_frame_data);
-            }
+        self.queue
+            .submit(std::iter::once(per_frame_data.encoder.finish()));
 
-            self.queue
-                .submit(std::iter::once(per_frame_data.encoder.finish()));
+        if let Some(output_surface) = output_surface {
             output_surface.present();
+        }
     }
 
-    pub fn save_window_surface_to_file(&mut self, _path: &str) {
-
-        unsafe {
-            self.device.start_graphics_debugger_capture();
+    /// Keeps a copy of every presented frame so window states can use [`State::capture_frame`].
+    ///
+    /// Headless states always render into a readable texture and don't need this. Surfaces that
+    /// can't be copied from never keep a capture.
+    pub fn set_frame_capture(&mut self, enabled: bool) {
+        let can_copy = self.config.usage.contains(wgpu::TextureUsages::COPY_SRC);
+        if !enabled || self.is_headless() || !can_copy {
+            self.frame_capture = None;
+        } else if self.frame_capture.is_none() {
+            self.frame_capture = Some(Self::create_capture_texture(&self.device, &self.config));
         }
+    }
 
-        let output_surface = self.window_surface.get_current_texture();
-
-            match output_surface {
-                CurrentSurfaceTexture::Success(output_surface) => {
-                     let width = output_surface.texture.width();
-        let height = output_surface.texture.height();
-
-
-        let format = wgpu::TextureFormat::Bgra8UnormSrgb; // Ensure this matches your texture's format
-
-        let mid_texture = factories::Texture2dFactory::new_with_options(
-            self,
-            [width, height],
-            factories::texture::Texture2dOptions {
-                format,
-                usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
-                label: Some("Temp Texture for Saving"),
-                ..Default::default()
-            },
-            factories::texture::SamplerOptions {
-                ..Default::default()
-            },
-            
-            &[],
-        );
-
-        let u8_size = std::mem::size_of::<u8>() as u32;
-        let bytes_per_pixel = u8_size * 4; // RGBA has 4 channels
-        let unpadded_bytes_per_row = width * bytes_per_pixel;
-        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
-        let padded_bytes_per_row = (unpadded_bytes_per_row + align - 1) & !(align - 1);
-        let buffer_size = padded_bytes_per_row * height;
-
-        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Output Buffer"),
-            size: buffer_size as wgpu::BufferAddress,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
-
-        // 3. Encode the copy command
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("SAVE TEXTURE Copy Encoder"),
-            });
-
-            let texture_size = wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            };
-        
-        self.temp_texture = Some(mid_texture);
-
-        encoder.copy_texture_to_texture(
-            TexelCopyTextureInfoBase {
-                texture: & output_surface.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            TexelCopyTextureInfoBase {
-                texture: &self.temp_texture.as_ref().unwrap().texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            texture_size
-        );
-
-        println!(
-            "Encoding copy command... {:?} - {:?}",
-            output_surface.texture.usage(),
-            self.config
-        );
-
-            encoder.copy_texture_to_buffer(
-            wgpu::TexelCopyTextureInfo {
-                texture: &output_surface.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            wgpu::TexelCopyBufferInfo {
-                buffer: &output_buffer,
-                layout: wgpu::TexelCopyBufferLayout {
-                    offset: 0,
-                    bytes_per_row: Some(padded_bytes_per_row),
-                    rows_per_image: Some(height),
-                },
-            },
-            texture_size,
-        );
-
-        self.queue.submit(Some(encoder.finish()));
-        // // 5. Map the buffer and wait
-        let buffer_slice = output_buffer.slice(..);
-
-
-        let (tx, rx) = futures::channel::oneshot::channel();
-        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-            println!("Buffer mapped... {:?}", result);
-            tx.send(result).unwrap();
-        });
-
-        self.device.poll(wgpu::wgt::PollType::Wait { submission_index: None, timeout: None }).unwrap();
-        // rx.await??;
-
-        pollster::block_on( rx ).unwrap().unwrap();
-
-        // Get the mapped data
-        let data = buffer_slice.get_mapped_range();
-
-        println!("Creating image buffer... {}", data.len());
-
-        let mut img_data = Vec::with_capacity((width * height * 4) as usize);
-        for row in 0..height {
-            let start = (row * padded_bytes_per_row) as usize;
-            let end = start + (width * bytes_per_pixel) as usize;
-            img_data.extend_from_slice(&data[start..end]);
-        }
+    fn create_capture_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+    ) -> TextureBundle {
+        let capture_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            ..config.clone()
+        };
+        Self::create_offscreen_texture(device, &capture_config)
+    }
 
-        // Save using the image crate
-        image::save_buffer(
-            "Test.jpg",
-            &img_data,
-            width,
-            height,
-            image::ColorType::Rgba8,
-        ).unwrap();
+    /// Reads the last rendered frame back as an sRGB encoded RGBA image.
+    pub async fn capture_frame_async(&self) -> Result<image::RgbaImage, CaptureError> {
+        let texture = match (&self.render_target, &self.frame_capture) {
+            (RenderTarget::Offscreen(texture), _) => texture,
+            (RenderTarget::Window(_), Some(texture)) => texture,
+            (RenderTarget::Window(_), None) => return Err(CaptureError::CaptureDisabled),
+        };
 
-        drop(data);
-        output_buffer.unmap();
+        readback::read_texture(&self.device, &self.queue, &texture.texture).await
+    }
 
-        unsafe  {
-            self.device.stop_graphics_debugger_capture();
-        }
-                },
-                _ => {
-                    println!("Failed to get output surface for saving!");
-                    return;
-                },
-            }
-       
+    /// Blocking version of [`State::capture_frame_async`].
+    pub fn capture_frame(&self) -> Result<image::RgbaImage, CaptureError> {
+        pollster::block_on(self.capture_frame_async())
+    }
 
+    /// Saves the last rendered frame, the encoder is picked from the extension of `path`.
+    pub fn save_frame(&self, path: impl AsRef<std::path::Path>) -> Result<(), CaptureError> {
+        let image = self.capture_frame()?;
+        readback::save_image(&image, path)
     }
 
     pub fn get_sample_count(&self) -> u32 {
@@ -0,0 +1,43 @@
+//! Helpers shared by the GPU tests.
+
+// Every test crate compiles this module, but not all of them use every helper.
+#![allow(dead_code)]
+
+use pira_wgpu::{
+    state::{DeviceRequirementsError, Size, State, StateOptions},
+    Error,
+};
+
+/// Creates an offscreen [`State`] without multisampling, see [`headless_state_with`].
+pub fn headless_state(size: Size) -> Option<State> {
+    headless_state_with(
+        size,
+        StateOptions {
+            sample_count: 1,
+            ..Default::default()
+        },
+    )
+}
+
+/// Creates an offscreen [`State`] rendering to `Rgba8UnormSrgb`.
+///
+/// Returns `None` and prints which test is skipped when no adapter is available, any other error
+/// fails the test.
+pub fn headless_state_with(size: Size, options: StateOptions) -> Option<State> {
+    match pollster::block_on(State::try_new_headless(
+        size,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+        &options,
+    )) {
+        Ok(state) => Some(state),
+        Err(Error::Device(DeviceRequirementsError::NoAdapter(_))) => {
+            let thread = std::thread::current();
+            eprintln!(
+                "No adapter available, skipping {}",
+                thread.name().unwrap_or("test")
+            );
+            None
+        }
+        Err(err) => panic!("{}", err),
+    }
+}
@@ -0,0 +1,132 @@
+//! Frame graph scheduling tests, skipped when no adapter is available.
+
+use pira_wgpu::{
+    frame_graph::{
+        ColorAttachment, FrameGraph, PassOptions, ResourceId, TexturePool, TransientTextureOptions,
+    },
+    pipelines::post::PostProcessing,
+    state::{Size, State},
+};
+
+mod common;
+
+const SIZE: Size = Size {
+    width: 32,
+    height: 32,
+};
+
+fn transient(size: Size) -> TransientTextureOptions {
+    TransientTextureOptions {
+        label: Some("Transient"),
+        size,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        sample_count: 1,
+    }
+}
+
+fn clear_pass(target: ResourceId, reads: Vec<ResourceId>) -> PassOptions {
+    PassOptions {
+        color_attachments: vec![ColorAttachment::new(
+            target,
+            wgpu::LoadOp::Clear(wgpu::Color::GREEN),
+        )],
+        reads,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn transients_alias_once_their_last_pass_is_done() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    let mut pool = TexturePool::new();
+
+    state.render(|state, frame_data| {
+        let mut graph = FrameGraph::new(state, &frame_data.view, None);
+        let first = graph.create_texture(transient(SIZE));
+        let second = graph.create_texture(transient(SIZE));
+        // Only used after the last read of `first`, so it can take its texture.
+        let third = graph.create_texture(transient(SIZE));
+        let backbuffer = graph.targets().backbuffer;
+
+        graph.add_pass(clear_pass(first, vec![]), |_, _| {});
+        graph.add_pass(clear_pass(second, vec![first]), |_, _| {});
+        graph.add_pass(
+            PassOptions {
+                color_attachments: vec![
+                    ColorAttachment::new(backbuffer, wgpu::LoadOp::Clear(wgpu::Color::GREEN)),
+                    ColorAttachment::new(third, wgpu::LoadOp::Clear(wgpu::Color::GREEN)),
+                ],
+                reads: vec![second],
+                ..Default::default()
+            },
+            |_, _| {},
+        );
+
+        graph.execute(state, &mut frame_data.encoder, &mut pool);
+    });
+
+    assert_eq!(pool.texture_count(), 2);
+
+    let frame = state.capture_frame().unwrap();
+    assert_eq!(frame.get_pixel(0, 0).0, [0, 255, 0, 255]);
+}
+
+#[test]
+fn textures_unused_for_a_frame_are_dropped() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    let mut pool = TexturePool::new();
+
+    for size in [SIZE, SIZE, Size::new(16, 16), Size::new(16, 16)] {
+        state.render(|state, frame_data| {
+            let mut graph = FrameGraph::new(state, &frame_data.view, None);
+            let texture = graph.create_texture(transient(size));
+            graph.add_pass(clear_pass(texture, vec![]), |_, _| {});
+            graph.execute(state, &mut frame_data.encoder, &mut pool);
+        });
+    }
+
+    assert_eq!(pool.texture_count(), 1);
+}
+
+fn render_post(state: &State, post_processing: &PostProcessing, pool: &mut TexturePool) {
+    state.render(|state, frame_data| {
+        let mut graph = FrameGraph::new(state, &frame_data.view, None);
+        let scene = post_processing.scene_targets(state, &mut graph);
+        graph.add_pass(
+            PassOptions {
+                color_attachments: vec![
+                    scene.color_attachment(wgpu::LoadOp::Clear(wgpu::Color::GREEN))
+                ],
+                ..Default::default()
+            },
+            |_, _| {},
+        );
+        post_processing.add_passes(state, &mut graph, scene.backbuffer);
+        graph.execute(state, &mut frame_data.encoder, pool);
+    });
+}
+
+#[test]
+fn post_bind_groups_are_kept_while_their_textures_are() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    let mut post_processing = PostProcessing::new(&state, Default::default());
+    let mut pool = TexturePool::new();
+
+    render_post(&state, &post_processing, &mut pool);
+    let count = post_processing.bind_group_count();
+    render_post(&state, &post_processing, &mut pool);
+    render_post(&state, &post_processing, &mut pool);
+    assert_eq!(post_processing.bind_group_count(), count);
+
+    // Only the composite and FXAA passes are left, the bloom levels go back to the pool.
+    post_processing.settings.bloom = false;
+    render_post(&state, &post_processing, &mut pool);
+    render_post(&state, &post_processing, &mut pool);
+    assert_eq!(post_processing.bind_group_count(), 2);
+}
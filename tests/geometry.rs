@@ -0,0 +1,93 @@
+//! Geometry helper tests, CPU only.
+
+use pira_wgpu::helpers::geometry::{attribute_names, cube, sphere, GeometryData};
+
+fn tangents(geometry: &GeometryData) -> Vec<glam::Vec4> {
+    geometry.attributes[&attribute_names::TANGENT]
+        .chunks_exact(4)
+        .map(glam::Vec4::from_slice)
+        .collect()
+}
+
+fn normals(geometry: &GeometryData) -> Vec<glam::Vec3> {
+    geometry.attributes[&attribute_names::NORMALS]
+        .chunks_exact(3)
+        .map(glam::Vec3::from_slice)
+        .collect()
+}
+
+#[test]
+fn tangents_are_unit_and_orthogonal_to_normals() {
+    let mut sphere = sphere::Sphere::new(2.0, 8, 16);
+    sphere.tangents();
+
+    let geometry = &sphere.geometry;
+    for (tangent, normal) in tangents(geometry).iter().zip(normals(geometry)) {
+        assert!((tangent.truncate().length() - 1.0).abs() < 1e-4);
+        assert!(tangent.truncate().dot(normal).abs() < 1e-4);
+        assert!(tangent.w == 1.0 || tangent.w == -1.0);
+    }
+}
+
+#[test]
+fn cube_tangents_follow_the_uv_directions() {
+    let mut cube = cube::Cube::new(1.0);
+    cube.tangents();
+
+    // The first face faces +z with u along +x and v along +y, so the bitangent points to -y.
+    let tangent = tangents(&cube.geometry)[0];
+    let normal = normals(&cube.geometry)[0];
+    assert!(tangent.truncate().abs_diff_eq(glam::Vec3::X, 1e-5));
+
+    let bitangent = normal.cross(tangent.truncate()) * tangent.w;
+    assert!(bitangent.abs_diff_eq(glam::Vec3::NEG_Y, 1e-5));
+}
+
+#[test]
+fn mirrored_uvs_split_the_shared_vertices() {
+    // Two triangles facing +z, sharing the edge at x = 0. The left one mirrors u, as when both
+    // halves of a symmetric model use the same half of a texture.
+    let mut geometry = GeometryData::new();
+    #[rustfmt::skip]
+    let (positions, uvs) = (
+        vec![
+            0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0,
+            1.0, 0.0, 0.0,
+            -1.0, 0.0, 0.0,
+        ],
+        vec![
+            0.0, 1.0,
+            0.0, 0.0,
+            1.0, 1.0,
+            1.0, 1.0,
+        ],
+    );
+    geometry
+        .attributes
+        .insert(attribute_names::POSITION, positions);
+    geometry.attributes.insert(attribute_names::UV, uvs);
+    geometry
+        .attributes
+        .insert(attribute_names::NORMALS, [0.0, 0.0, 1.0].repeat(4));
+    geometry.indices = vec![0, 2, 1, 0, 1, 3];
+
+    geometry.generate_tangents();
+
+    // The shared vertices are copied for the mirrored triangle, with all of their attributes.
+    assert_eq!(geometry.indices, [0, 2, 1, 4, 5, 3]);
+    assert_eq!(geometry.attributes[&attribute_names::POSITION].len(), 6 * 3);
+    assert_eq!(
+        geometry.attributes[&attribute_names::UV][8..],
+        [0.0, 1.0, 0.0, 0.0]
+    );
+    assert_eq!(normals(&geometry)[4], glam::Vec3::Z);
+
+    let tangents = tangents(&geometry);
+    for &index in &geometry.indices[..3] {
+        assert!(tangents[index as usize].abs_diff_eq(glam::vec4(1.0, 0.0, 0.0, 1.0), 1e-5));
+    }
+    for &index in &geometry.indices[3..] {
+        assert!(tangents[index as usize].abs_diff_eq(glam::vec4(-1.0, 0.0, 0.0, -1.0), 1e-5));
+    }
+}
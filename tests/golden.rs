@@ -0,0 +1,990 @@
+//! Golden image tests.
+//!
+//! Every test renders a small scene into an offscreen [`State`], reads the frame back and compares
+//! it against a reference PNG in `tests/golden/`. When a frame doesn't match, the rendered frame
+//! and a diff image are written to `target/tmp/golden/`.
+//!
+//! Run with `PIRA_BLESS_GOLDEN=1` to (re)generate the references after an intended visual change
+//! or for a new test, a missing reference fails the test otherwise. Tests are skipped when no
+//! adapter is available.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use pira_wgpu::{
+    factories::RenderPassFactory,
+    frame_graph::{FrameGraph, PassOptions, TexturePool},
+    helpers::cameras::{CameraTrait, PespectiveCamera},
+    helpers::geometry::{cube, sphere, GeometryFactory},
+    immediate_mode::DrawContext,
+    pipelines::{self, pbr, post, shadeless, sky, ModelUniform},
+    state::{Size, State, StateOptions},
+};
+
+mod common;
+
+const SIZE: Size = Size {
+    width: 128,
+    height: 128,
+};
+
+/// Channels can differ this much (out of 255) before a pixel counts as different.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels that are allowed to differ, this absorbs rasterization differences at edges.
+const MAX_DIFFERENT_PIXELS: f32 = 0.01;
+
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.1,
+    b: 0.15,
+    a: 1.0,
+};
+
+fn camera() -> PespectiveCamera {
+    let mut camera = PespectiveCamera::new(
+        std::f32::consts::FRAC_PI_3,
+        SIZE.aspect_ratio(),
+        0.1,
+        100.0,
+    );
+    camera.position = glam::vec3(8.0, 6.0, -12.0);
+    camera.look_at(glam::Vec3::ZERO);
+    camera
+}
+
+/// Renders a single frame with `draw` and reads it back.
+fn render<'a, F>(state: &'a State, draw: F) -> image::RgbaImage
+where
+    F: FnOnce(&mut wgpu::RenderPass<'a>),
+{
+    state.render(|state, frame_data| {
+        let mut render_pass_factory = RenderPassFactory::new();
+        render_pass_factory.add_color_atachment(CLEAR_COLOR, &frame_data.view, None);
+
+        // The pass only outlives the encoder borrow on paper, it is dropped before `render` submits.
+        let mut render_pass = render_pass_factory
+            .get_render_pass(state, &mut frame_data.encoder, true)
+            .forget_lifetime();
+        draw(&mut render_pass);
+    });
+
+    state.capture_frame().unwrap()
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn assert_golden(name: &str, image: &image::RgbaImage) {
+    let reference_path = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os("PIRA_BLESS_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        image.save(&reference_path).unwrap();
+        eprintln!("Wrote reference image {}", reference_path.display());
+        return;
+    }
+    if !reference_path.exists() {
+        std::fs::create_dir_all(output_dir()).unwrap();
+        let actual_path = output_dir().join(format!("{}.actual.png", name));
+        image.save(&actual_path).unwrap();
+        panic!(
+            "{}: missing reference {}, rerun with PIRA_BLESS_GOLDEN=1 (frame written to {})",
+            name,
+            reference_path.display(),
+            actual_path.display()
+        );
+    }
+
+    let reference = image::open(&reference_path).unwrap().to_rgba8();
+    assert_eq!(
+        reference.dimensions(),
+        image.dimensions(),
+        "{}: size differs from the reference",
+        name
+    );
+
+    let mut diff = image::RgbaImage::new(image.width(), image.height());
+    let mut different_pixels = 0;
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let actual = image.get_pixel(x, y);
+        let is_different = expected
+            .0
+            .iter()
+            .zip(actual.0.iter())
+            .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE);
+
+        let pixel = if is_different {
+            different_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            // Keep a dimmed copy of the reference around the highlighted pixels.
+            let luma = (expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 9;
+            image::Rgba([luma as u8, luma as u8, luma as u8, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+
+    let pixel_count = image.width() * image.height();
+    let different_fraction = different_pixels as f32 / pixel_count as f32;
+    if different_fraction > MAX_DIFFERENT_PIXELS {
+        std::fs::create_dir_all(output_dir()).unwrap();
+        let actual_path = output_dir().join(format!("{}.actual.png", name));
+        let diff_path = output_dir().join(format!("{}.diff.png", name));
+        image.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+
+        panic!(
+            "{}: {} of {} pixels differ from {}, see {} and {}",
+            name,
+            different_pixels,
+            pixel_count,
+            reference_path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn draw_shapes(state: &State, draw_context: &mut DrawContext) {
+    draw_context.start();
+    draw_context.push_color(1.0, 0.5, 0.0);
+    draw_context.push_circle(64.0, 64.0, 40.0);
+    draw_context.push_color(0.0, 0.5, 1.0);
+    draw_context.push_rect(8.0, 8.0, 32.0, 24.0);
+    draw_context.push_color_alpha(0.2, 1.0, 0.2, 0.5);
+    draw_context.push_line(
+        &[
+            glam::vec2(10.0, 118.0),
+            glam::vec2(64.0, 90.0),
+            glam::vec2(118.0, 118.0),
+        ],
+        4.0,
+    );
+    draw_context.end(state);
+}
+
+#[test]
+fn immediate_mode_shapes() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+
+    let mut draw_context = DrawContext::new(&state);
+    draw_shapes(&state, &mut draw_context);
+
+    let image = render(&state, |render_pass| {
+        draw_context.draw(&state, render_pass);
+    });
+
+    assert_golden("immediate_mode_shapes", &image);
+}
+
+#[test]
+fn sample_count_change() {
+    let Some(mut state) = common::headless_state_with(
+        SIZE,
+        StateOptions {
+            sample_count: 4,
+            ..Default::default()
+        },
+    ) else {
+        return;
+    };
+
+    let mut draw_context = DrawContext::new(&state);
+    assert!(state.set_sample_count(3).is_err());
+    assert_eq!(state.get_sample_count(), 4);
+
+    state.set_sample_count(1).unwrap();
+    draw_context.recreate_pipelines(&state);
+    draw_shapes(&state, &mut draw_context);
+
+    let image = render(&state, |render_pass| {
+        draw_context.draw(&state, render_pass);
+    });
+
+    assert_golden("immediate_mode_shapes", &image);
+}
+
+#[test]
+fn shadeless_cube() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+
+    let pipeline = shadeless::ShadelessPipeline::new_with_texture(
+        &state,
+        &state.default_white_texture_bundle,
+        wgpu::PrimitiveTopology::TriangleList,
+        true,
+        None,
+    );
+
+    // Identical requests share the pipeline from the state's cache.
+    let shared = shadeless::ShadelessPipeline::new_with_texture(
+        &state,
+        &state.default_white_texture_bundle,
+        wgpu::PrimitiveTopology::TriangleList,
+        true,
+        None,
+    );
+    let strip = shadeless::ShadelessPipeline::new_with_texture(
+        &state,
+        &state.default_white_texture_bundle,
+        wgpu::PrimitiveTopology::TriangleStrip,
+        true,
+        None,
+    );
+    assert!(Arc::ptr_eq(&pipeline.pipeline, &shared.pipeline));
+    assert!(!Arc::ptr_eq(&pipeline.pipeline, &strip.pipeline));
+    assert_eq!(state.pipeline_cache.shader_module_count(), 1);
+
+    // Modules are looked up by their whole source.
+    let cache = &state.pipeline_cache;
+    let source = "@compute @workgroup_size(1) fn main() {}";
+    let copy = String::from(source);
+    let (key, _) = cache.shader_module(&state.device, "Compute", source);
+    let (shared_key, _) = cache.shader_module(&state.device, "Compute", &copy);
+    assert!(Arc::ptr_eq(&key, &shared_key));
+    assert_eq!(cache.shader_module_count(), 2);
+
+    let mut cube = cube::Cube::new(5.0);
+    cube.texture_coords();
+    cube.vertex_colors_from_normal();
+    let mesh = shadeless::ShadelessPipeline::get_buffers_from_geometry(&state, &cube.geometry);
+
+    let camera = camera();
+    pipelines::write_global_uniform_buffer(
+        camera.get_perspective_matrix() * camera.get_view_matrix(),
+        pipeline.global_uniform_buffer.as_ref().unwrap(),
+        &state.queue,
+    );
+    pipelines::write_uniform_buffer(
+        &[ModelUniform::new(glam::Mat4::from_rotation_y(0.3))],
+        pipeline.model_uniform_buffer.as_ref().unwrap(),
+        &state.queue,
+        &state.device,
+    );
+
+    let image = render(&state, |render_pass| {
+        render_pass.set_pipeline(&pipeline.pipeline);
+        render_pass.set_bind_group(0, &pipeline.bind_group, &[0, 0]);
+        render_pass.set_bind_group(1, pipeline.texture_bind_group.as_ref().unwrap(), &[]);
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..mesh.vertex_count, 0, 0..1);
+    });
+
+    assert_golden("shadeless_cube", &image);
+}
+
+/// A small procedural equirectangular environment: a bright sky over a dark ground.
+fn environment_image() -> image::DynamicImage {
+    let image = image::Rgba32FImage::from_fn(64, 32, |x, y| {
+        let v = y as f32 / 31.0;
+        let u = x as f32 / 63.0;
+        if v < 0.5 {
+            image::Rgba([0.4 + u * 0.6, 0.6, 1.0 - v, 1.0])
+        } else {
+            image::Rgba([0.2, 0.15, 0.1, 1.0])
+        }
+    });
+
+    image::DynamicImage::ImageRgba32F(image)
+}
+
+/// The sky projects its image with `Rgba32Float` storage textures and renders the lighting maps
+/// into `IBL_FORMAT` targets, which not every adapter supports.
+fn can_bake_sky(state: &State) -> bool {
+    let storage = state
+        .adapter
+        .get_texture_format_features(wgpu::TextureFormat::Rgba32Float);
+    let ibl = state.adapter.get_texture_format_features(sky::IBL_FORMAT);
+    let supported = storage
+        .allowed_usages
+        .contains(wgpu::TextureUsages::STORAGE_BINDING)
+        && ibl
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+
+    if !supported {
+        eprintln!("Adapter can't bake the sky maps, skipping sky golden image test");
+    }
+    supported
+}
+
+fn sky_renderer(state: &State) -> sky::SkyRenderer {
+    sky::SkyRenderer::new(
+        state,
+        &environment_image(),
+        sky::SkyRendererOptions {
+            dst_size: 64,
+            ..Default::default()
+        },
+    )
+}
+
+#[test]
+fn sky_background() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let sky_renderer = sky_renderer(&state);
+    sky_renderer.set_uniform_buffer(&state, &camera());
+
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+
+    assert_golden("sky_background", &image);
+}
+
+#[test]
+fn baked_sky_round_trip() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    // Render what was loaded back from disk, it has to look exactly like the bake.
+    let baked_path = output_dir().join("baked_sky_round_trip.bin");
+    let baked = sky_renderer(&state);
+    baked.save_baked(&state, &baked_path).unwrap();
+    let sky_renderer = sky::SkyRenderer::load_baked(&state, &baked_path).unwrap();
+
+    let saved_again_path = output_dir().join("baked_sky_round_trip_again.bin");
+    sky_renderer.save_baked(&state, &saved_again_path).unwrap();
+    let bytes = std::fs::read(&baked_path).unwrap();
+    assert!(bytes == std::fs::read(&saved_again_path).unwrap());
+
+    sky_renderer.set_uniform_buffer(&state, &camera());
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+    assert_golden("sky_background", &image);
+
+    // Buffers far smaller than a face make the readback go in bands and batches.
+    let Some(small_buffers) = common::headless_state_with(
+        SIZE,
+        StateOptions {
+            sample_count: 1,
+            required_limits: wgpu::Limits {
+                max_storage_buffer_binding_size: 4096,
+                max_buffer_size: 1 << 17,
+                ..sky::required_limits()
+            },
+            ..Default::default()
+        },
+    ) else {
+        return;
+    };
+    let banded_path = output_dir().join("baked_sky_round_trip_banded.bin");
+    sky::SkyRenderer::load_baked(&small_buffers, &baked_path)
+        .unwrap()
+        .save_baked(&small_buffers, &banded_path)
+        .unwrap();
+    assert!(bytes == std::fs::read(&banded_path).unwrap());
+
+    // An environment that isn't a cube map is an error, not a validation panic. Its layer count
+    // follows the magic, the version, the irradiance source, the format, the width and height.
+    let mut corrupt = bytes;
+    corrupt[28..32].copy_from_slice(&1u32.to_le_bytes());
+    let corrupt_path = output_dir().join("baked_sky_corrupt.bin");
+    std::fs::write(&corrupt_path, corrupt).unwrap();
+    assert!(sky::SkyRenderer::load_baked(&state, &corrupt_path).is_err());
+}
+
+#[test]
+fn cached_sky() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let cache_dir = output_dir().join("sky_cache");
+    let _ = std::fs::remove_dir_all(&cache_dir);
+
+    let image = environment_image();
+    let options = || sky::SkyRendererOptions {
+        dst_size: 32,
+        irradiance_size: 16,
+        specular_size: 32,
+        specular_sample_count: 64,
+        ..Default::default()
+    };
+    let hash = sky::SkyRenderer::bake_hash(&image, &options());
+    let path = cache_dir.join(format!("sky_{:016x}.bin", hash));
+
+    // The first call bakes and writes the file, the second one loads it without saving again.
+    sky::SkyRenderer::new_cached(&state, &image, options(), &cache_dir);
+    let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+    let cached = sky::SkyRenderer::new_cached(&state, &image, options(), &cache_dir);
+    assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    assert_eq!(cached.textures.texture.width(), 32);
+
+    let changed = sky::SkyRendererOptions {
+        specular_sample_count: 32,
+        ..options()
+    };
+    assert_eq!(hash, sky::SkyRenderer::bake_hash(&image, &options()));
+    assert_ne!(hash, sky::SkyRenderer::bake_hash(&image, &changed));
+}
+
+#[test]
+fn physical_sky() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    // Low sun ahead of the camera, the view has the ground, the horizon and the glow of the sun.
+    let sky = sky::PhysicalSky {
+        sun_direction: glam::vec3(0.3, 0.2, 1.0),
+        ..Default::default()
+    };
+    let sky_renderer = sky::SkyRenderer::new_physical(
+        &state,
+        &sky,
+        sky::SkyRendererOptions {
+            dst_size: 64,
+            irradiance_size: 16,
+            specular_size: 32,
+            specular_sample_count: 64,
+            ..Default::default()
+        },
+    );
+    sky_renderer.set_uniform_buffer(&state, &camera());
+
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+
+    assert_golden("physical_sky", &image);
+}
+
+#[test]
+fn sky_params() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let mut sky_renderer = sky_renderer(&state);
+    sky_renderer.params = sky::SkyParams {
+        rotation: std::f32::consts::FRAC_PI_2,
+        exposure: -1.0,
+        background_mip: 2.0,
+        background_color: None,
+    };
+    sky_renderer.write_params(&state);
+    sky_renderer.set_uniform_buffer(&state, &camera());
+
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+    assert_golden("sky_params", &image);
+
+    // A solid background replaces the environment everywhere.
+    sky_renderer.params.background_color = Some(glam::vec3(0.2, 0.4, 0.6));
+    sky_renderer.write_params(&state);
+
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+    let first = *image.get_pixel(0, 0);
+    assert!(first[2] > first[0]);
+    assert!(image.pixels().all(|pixel| *pixel == first));
+}
+
+/// One face of a test cube map: a color per face, darkening across it to show its orientation.
+fn cube_face_image(face: sky::CubeFace) -> image::RgbaImage {
+    let color = match face {
+        sky::CubeFace::PositiveX => [230, 60, 40],
+        sky::CubeFace::NegativeX => [40, 200, 60],
+        sky::CubeFace::PositiveY => [150, 200, 255],
+        sky::CubeFace::NegativeY => [90, 60, 30],
+        sky::CubeFace::PositiveZ => [240, 220, 40],
+        sky::CubeFace::NegativeZ => [160, 40, 200],
+    };
+    image::RgbaImage::from_fn(16, 16, |x, y| {
+        let shade = 255 - x * 6 - y * 3;
+        let [r, g, b] = color.map(|channel| (channel * shade / 255) as u8);
+        image::Rgba([r, g, b, 255])
+    })
+}
+
+/// An uncompressed `R8G8B8A8_UNORM` KTX2 cube map with the mips of `faces`, in layer order.
+fn ktx2_cube_map(faces: &[image::RgbaImage; 6], level_count: u32) -> Vec<u8> {
+    let levels: Vec<Vec<u8>> = (0..level_count)
+        .map(|level| {
+            let size = faces[0].width() >> level;
+            faces
+                .iter()
+                .flat_map(|face| {
+                    image::imageops::resize(face, size, size, image::imageops::FilterType::Triangle)
+                        .into_raw()
+                })
+                .collect()
+        })
+        .collect();
+
+    let level_index_length = level_count as usize * ktx2::LevelIndex::LENGTH;
+    // The data format descriptor only has its total size, the loader doesn't read it.
+    let dfd_offset = ktx2::Header::LENGTH + level_index_length;
+    let mut offset = (dfd_offset + 4) as u64;
+
+    let header = ktx2::Header {
+        format: Some(ktx2::Format::R8G8B8A8_UNORM),
+        type_size: 1,
+        pixel_width: faces[0].width(),
+        pixel_height: faces[0].height(),
+        pixel_depth: 0,
+        layer_count: 0,
+        face_count: 6,
+        level_count,
+        supercompression_scheme: None,
+        index: ktx2::Index {
+            dfd_byte_offset: dfd_offset as u32,
+            dfd_byte_length: 4,
+            kvd_byte_offset: 0,
+            kvd_byte_length: 0,
+            sgd_byte_offset: 0,
+            sgd_byte_length: 0,
+        },
+    };
+
+    let mut bytes = header.as_bytes().to_vec();
+    for level in &levels {
+        let length = level.len() as u64;
+        let index = ktx2::LevelIndex {
+            byte_offset: offset,
+            byte_length: length,
+            uncompressed_byte_length: length,
+        };
+        bytes.extend_from_slice(&index.as_bytes());
+        offset += length;
+    }
+    bytes.extend_from_slice(&4u32.to_le_bytes());
+    for level in &levels {
+        bytes.extend_from_slice(level);
+    }
+    bytes
+}
+
+#[test]
+fn cube_map_faces() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let options = || sky::SkyRendererOptions {
+        irradiance_size: 16,
+        specular_size: 32,
+        specular_sample_count: 64,
+        ..Default::default()
+    };
+    let faces = sky::CubeFace::ALL.map(cube_face_image);
+
+    // Given in the order of a Unity skybox, the faces still end up on their own layers.
+    let order = sky::CubeFaceOrder::Unity;
+    let unity_faces = order
+        .faces()
+        .map(|face| image::DynamicImage::ImageRgba8(cube_face_image(face)));
+    let sky_renderer =
+        sky::SkyRenderer::new_from_faces(&state, &unity_faces, order, options()).unwrap();
+    sky_renderer.set_uniform_buffer(&state, &camera());
+
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+    assert_golden("cube_map_faces", &image);
+
+    // The mips of a KTX2 file are used as the specular map instead of baking one.
+    let ktx2 = ktx2_cube_map(&faces, 3);
+    let sky_renderer = sky::SkyRenderer::new_from_ktx2(&state, &ktx2, options()).unwrap();
+    sky_renderer.set_uniform_buffer(&state, &camera());
+    let specular = &sky_renderer.specular_reflection_texture.texture;
+    assert_eq!(specular.mip_level_count(), 3);
+    assert_eq!(specular.width(), 16);
+
+    let image = render(&state, |render_pass| {
+        sky_renderer.draw(render_pass);
+    });
+    assert_golden("cube_map_faces", &image);
+}
+
+#[test]
+fn pbr_sphere() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let sky_renderer = sky_renderer(&state);
+    let lights = pbr::LightSet::new(
+        &state,
+        vec![pbr::Light {
+            intensity: 200.0,
+            ..pbr::Light::point(glam::vec3(5.0, 5.0, 10.0))
+        }],
+        Default::default(),
+    );
+    let factors = pbr::PbrMaterialFactors {
+        albedo: glam::vec3(0.9, 0.3, 0.2),
+        roughness: 0.4,
+        metallic: 0.2,
+        ..Default::default()
+    };
+
+    let image = render_pbr_sphere(&state, &sky_renderer, &lights, factors, true);
+
+    assert_golden("pbr_sphere", &image);
+}
+
+#[test]
+fn light_set_grows_and_allocates_shadows_lazily() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+
+    let mut lights = pbr::LightSet::new(
+        &state,
+        vec![pbr::Light::point(glam::Vec3::Y)],
+        Default::default(),
+    );
+    let camera = camera();
+    lights.update_shadows(&state, &camera, camera.get_view_matrix());
+    assert!(!lights.shadows.is_allocated());
+    assert_eq!(lights.shadows.shadow_map.texture.width(), 1);
+
+    // Past the initial capacity of the light buffer.
+    for index in 0..40 {
+        let light = pbr::Light::point(glam::Vec3::X * index as f32);
+        lights.lights.push(light);
+    }
+    let sun = pbr::Light::directional(glam::vec3(0.3, -1.0, 0.2));
+    lights.lights.push(sun);
+    lights.write(&state);
+    assert_eq!(lights.uploaded_count(), 42);
+
+    lights.update_shadows(&state, &camera, camera.get_view_matrix());
+    assert!(lights.shadows.is_allocated());
+    assert_eq!(lights.shadows.shadow_map.texture.width(), 2048);
+    assert_eq!(lights.shadows.shadow_map.texture.depth_or_array_layers(), 4);
+}
+
+#[test]
+fn masked_materials_cast_cut_out_shadows() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let sky_renderer = sky_renderer(&state);
+    let mut lights = pbr::LightSet::new(
+        &state,
+        vec![pbr::Light {
+            intensity: 3.0,
+            ..pbr::Light::directional(glam::vec3(0.2, -1.0, 0.1))
+        }],
+        pbr::ShadowOptions {
+            resolution: 512,
+            ..Default::default()
+        },
+    );
+    let camera = camera();
+    lights.update_shadows(&state, &camera, camera.get_view_matrix());
+    sky_renderer.set_uniform_buffer(&state, &camera);
+
+    let pipeline = pbr::PbrPipeline::new(
+        &state,
+        &sky_renderer,
+        &lights,
+        wgpu::PrimitiveTopology::TriangleList,
+        true,
+    );
+    let material = |alpha_mode, alpha| {
+        pbr::PbrMaterial::new(
+            &state,
+            &pipeline,
+            pbr::PbrMaterialOptions {
+                factors: pbr::PbrMaterialFactors {
+                    alpha,
+                    metallic: 0.0,
+                    ..Default::default()
+                },
+                alpha_mode,
+                ..Default::default()
+            },
+        )
+    };
+    let floor_material = material(pbr::AlphaMode::Opaque, 1.0);
+    let opaque = material(pbr::AlphaMode::Opaque, 1.0);
+    let cut_out = material(pbr::AlphaMode::Mask { cutoff: 0.5 }, 0.2);
+
+    let mut cube = cube::Cube::new(1.0);
+    cube.texture_coords();
+    cube.normals();
+    cube.tangents();
+    let floor = pbr::PbrPipeline::get_buffers_from_geometry(&state, &cube.geometry);
+    let floor_transform = glam::Mat4::from_scale_rotation_translation(
+        glam::vec3(30.0, 0.5, 30.0),
+        glam::Quat::IDENTITY,
+        glam::vec3(0.0, -3.0, 0.0),
+    );
+    let mut sphere = sphere::Sphere::new(3.0, 16, 32);
+    sphere.texture_coords();
+    sphere.normals();
+    sphere.tangents();
+    let occluder = pbr::PbrPipeline::get_buffers_from_geometry(&state, &sphere.geometry);
+
+    let view = camera.get_view_matrix();
+    let projection = camera.get_perspective_matrix();
+    let view_uniform = pipelines::ViewUniform {
+        view_pespective_matrix: projection * view,
+        view_matrix: view,
+        perspective_matrix: projection,
+        camera_position: camera.position,
+    };
+
+    // The occluder is only drawn into the shadow map, the frame shows the floor it shadows.
+    let mut pool = TexturePool::new();
+    let mut render_floor = |occluder_material: Option<&pbr::PbrMaterial>| {
+        pipeline.begin_frame(&state, &view_uniform);
+        state.render(|state, frame_data| {
+            let mut graph = FrameGraph::new(state, &frame_data.view, None);
+            let (pipeline, lights, occluder) = (&pipeline, &lights, &occluder);
+            lights.shadows.add_passes(&mut graph, move |render_pass| {
+                if let Some(material) = occluder_material {
+                    let transform = glam::Mat4::IDENTITY;
+                    pipeline.draw_shadow_mesh(state, render_pass, occluder, transform, material);
+                }
+            });
+
+            let targets = graph.targets();
+            let (floor, material) = (&floor, &floor_material);
+            graph.add_pass(
+                PassOptions {
+                    label: Some("Floor"),
+                    color_attachments: vec![
+                        targets.color_attachment(wgpu::LoadOp::Clear(CLEAR_COLOR))
+                    ],
+                    depth_attachment: targets.depth_attachment(),
+                    ..Default::default()
+                },
+                move |_, mut render_pass| {
+                    render_pass.set_bind_group(1, &pipeline.environment_bind_group, &[]);
+                    render_pass.set_bind_group(3, &lights.bind_group, &[]);
+                    let transform = floor_transform;
+                    pipeline.draw_mesh(state, &mut render_pass, floor, transform, material);
+                },
+            );
+            graph.execute(state, &mut frame_data.encoder, &mut pool);
+        });
+        state.capture_frame().unwrap()
+    };
+
+    let unshadowed = render_floor(None);
+    let shadowed = render_floor(Some(&opaque));
+    let cut_out = render_floor(Some(&cut_out));
+
+    let darker_pixels = |image: &image::RgbaImage| {
+        image
+            .pixels()
+            .zip(unshadowed.pixels())
+            .filter(|(a, b)| b.0[1].saturating_sub(a.0[1]) > CHANNEL_TOLERANCE)
+            .count()
+    };
+    assert!(
+        darker_pixels(&shadowed) > 100,
+        "the opaque occluder casts no shadow"
+    );
+    assert_eq!(
+        darker_pixels(&cut_out),
+        0,
+        "the cut out occluder casts a shadow"
+    );
+}
+
+/// Draws a sphere with `factors` in front of the sky, or of the clear color without `background`.
+fn render_pbr_sphere(
+    state: &State,
+    sky_renderer: &sky::SkyRenderer,
+    lights: &pbr::LightSet,
+    factors: pbr::PbrMaterialFactors,
+    background: bool,
+) -> image::RgbaImage {
+    let camera = camera();
+    sky_renderer.set_uniform_buffer(state, &camera);
+
+    let pipeline = pbr::PbrPipeline::new(
+        state,
+        sky_renderer,
+        lights,
+        wgpu::PrimitiveTopology::TriangleList,
+        true,
+    );
+
+    let material = pbr::PbrMaterial::new(
+        state,
+        &pipeline,
+        pbr::PbrMaterialOptions {
+            factors,
+            ..Default::default()
+        },
+    );
+
+    let mut sphere = sphere::Sphere::new(5.0, 16, 32);
+    sphere.texture_coords();
+    sphere.normals();
+    sphere.tangents();
+    let mesh = pbr::PbrPipeline::get_buffers_from_geometry(state, &sphere.geometry);
+
+    let view = camera.get_view_matrix();
+    let projection = camera.get_perspective_matrix();
+    pipeline.begin_frame(
+        state,
+        &pipelines::ViewUniform {
+            view_pespective_matrix: projection * view,
+            view_matrix: view,
+            perspective_matrix: projection,
+            camera_position: camera.position,
+        },
+    );
+
+    render(state, |render_pass| {
+        if background {
+            sky_renderer.draw(render_pass);
+        }
+
+        render_pass.set_pipeline(&pipeline.pipeline);
+        render_pass.set_bind_group(1, &pipeline.environment_bind_group, &[]);
+        render_pass.set_bind_group(3, &lights.bind_group, &[]);
+        pipeline.draw_mesh(state, render_pass, &mesh, glam::Mat4::IDENTITY, &material);
+    })
+}
+
+#[test]
+fn sh_irradiance_matches_cube_map() {
+    let Some(state) = common::headless_state(SIZE) else {
+        return;
+    };
+    if !can_bake_sky(&state) {
+        return;
+    }
+
+    let lights = pbr::LightSet::new(&state, Vec::new(), Default::default());
+    // Rough and white, so the diffuse irradiance dominates.
+    let factors = pbr::PbrMaterialFactors {
+        albedo: glam::Vec3::ONE,
+        roughness: 1.0,
+        metallic: 0.0,
+        ambient: glam::Vec3::ZERO,
+        ..Default::default()
+    };
+
+    let render_with = |irradiance_source| {
+        let sky_renderer = sky::SkyRenderer::new(
+            &state,
+            &environment_image(),
+            sky::SkyRendererOptions {
+                dst_size: 64,
+                irradiance_source,
+                ..Default::default()
+            },
+        );
+        render_pbr_sphere(&state, &sky_renderer, &lights, factors, false)
+    };
+    let cube_map = render_with(sky::IrradianceSource::CubeMap);
+    let sh = render_with(sky::IrradianceSource::SphericalHarmonics);
+
+    let mut max_difference = 0;
+    for (a, b) in cube_map.pixels().zip(sh.pixels()) {
+        for (a, b) in a.0.iter().zip(b.0.iter()) {
+            max_difference = max_difference.max(a.abs_diff(*b));
+        }
+    }
+    assert!(
+        max_difference <= 8,
+        "SH irradiance differs from the cube map by up to {}",
+        max_difference
+    );
+}
+
+#[test]
+fn post_processing() {
+    let Some(state) = common::headless_state_with(
+        SIZE,
+        StateOptions {
+            sample_count: 1,
+            scene_format: Some(post::SCENE_FORMAT),
+            ..Default::default()
+        },
+    ) else {
+        return;
+    };
+
+    let post_processing = post::PostProcessing::new(
+        &state,
+        post::PostProcessingOptions {
+            settings: post::PostSettings {
+                bloom_intensity: 0.5,
+                vignette_intensity: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    // Values above 1 only survive in the HDR scene and are what the bloom picks up.
+    let mut draw_context = DrawContext::new(&state);
+    draw_context.start();
+    draw_context.push_color(8.0, 4.0, 1.0);
+    draw_context.push_circle(64.0, 64.0, 16.0);
+    draw_context.push_color(0.2, 0.4, 0.8);
+    draw_context.push_rect(8.0, 8.0, 32.0, 24.0);
+    draw_context.end(&state);
+
+    let mut pool = TexturePool::new();
+    state.render(|state, frame_data| {
+        let mut graph = FrameGraph::new(state, &frame_data.view, None);
+        let scene = post_processing.scene_targets(state, &mut graph);
+        graph.add_pass(
+            PassOptions {
+                label: Some("Scene"),
+                color_attachments: vec![scene.color_attachment(wgpu::LoadOp::Clear(CLEAR_COLOR))],
+                depth_attachment: scene.depth_attachment(),
+                ..Default::default()
+            },
+            |_, mut render_pass| draw_context.draw(state, &mut render_pass),
+        );
+        post_processing.add_passes(state, &mut graph, scene.backbuffer);
+        graph.execute(state, &mut frame_data.encoder, &mut pool);
+    });
+
+    assert_golden("post_processing", &state.capture_frame().unwrap());
+}
@@ -0,0 +1,105 @@
+//! Shader hot reload tests, CPU only. Run with `--features hot-reload`.
+
+#![cfg(feature = "hot-reload")]
+
+use std::path::{Path, PathBuf};
+
+use pira_wgpu::helpers::hot_reload::{ShaderReloadError, ShaderSource};
+
+const MAIN: &str = "#include \"value.wgsl\"
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(VALUE);
+}
+";
+
+fn shader_dir(name: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A shader including `value.wgsl`, both written to `dir` as if they were embedded from there.
+fn shader_source(dir: &Path) -> ShaderSource {
+    let value = "const VALUE: f32 = 1.0;\n";
+    std::fs::write(dir.join("main.wgsl"), MAIN).unwrap();
+    std::fs::write(dir.join("value.wgsl"), value).unwrap();
+
+    let mut source = ShaderSource::new("main.wgsl", MAIN, dir.join("main.wgsl"));
+    source
+        .preprocessor
+        .add_chunk_file("value.wgsl", value, dir.join("value.wgsl"));
+    source
+}
+
+#[test]
+fn included_chunks_are_watched() {
+    let dir = shader_dir("hot_reload_watched");
+    let source = shader_source(&dir);
+    assert_eq!(
+        source.watched_paths(),
+        [dir.join("main.wgsl"), dir.join("value.wgsl")]
+    );
+
+    // The built-in chunks are read from the crate's sources.
+    let source = ShaderSource::new(
+        "constants_user.wgsl",
+        "#include \"constants.wgsl\"\n",
+        dir.join("constants_user.wgsl"),
+    );
+    let paths = source.watched_paths();
+    assert_eq!(paths.len(), 2);
+    assert!(paths[1].ends_with("src/pipelines/shaders/constants.wgsl"));
+    assert!(paths[1].exists());
+}
+
+#[test]
+fn reloads_read_the_source_and_its_chunks_from_disk() {
+    let dir = shader_dir("hot_reload_read");
+    let source = shader_source(&dir);
+    assert!(source
+        .read_validated()
+        .unwrap()
+        .source
+        .contains("VALUE: f32 = 1.0"));
+
+    std::fs::write(dir.join("value.wgsl"), "const VALUE: f32 = 2.0;\n").unwrap();
+    assert!(source
+        .read_validated()
+        .unwrap()
+        .source
+        .contains("VALUE: f32 = 2.0"));
+
+    std::fs::write(
+        dir.join("main.wgsl"),
+        MAIN.replace("vec4<f32>(VALUE)", "vec4<f32>(VALUE * 0.5)"),
+    )
+    .unwrap();
+    assert!(source
+        .read_validated()
+        .unwrap()
+        .source
+        .contains("VALUE * 0.5"));
+}
+
+#[test]
+fn errors_in_chunks_point_at_the_chunk() {
+    let dir = shader_dir("hot_reload_errors");
+    let source = shader_source(&dir);
+
+    std::fs::write(dir.join("value.wgsl"), "const VALUE: f32 = ;\n").unwrap();
+    match source.read_validated() {
+        Err(ShaderReloadError::Parse(message)) => {
+            assert!(message.contains("in value.wgsl:1"), "{}", message)
+        }
+        other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+    }
+
+    std::fs::remove_file(dir.join("value.wgsl")).unwrap();
+    assert!(matches!(
+        source.read_validated(),
+        Err(ShaderReloadError::Io(_))
+    ));
+}
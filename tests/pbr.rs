@@ -0,0 +1,142 @@
+//! PBR uniform and light tests, CPU only.
+
+use pira_wgpu::pipelines::pbr::{AlphaMode, GpuLight, Light, LightSet, PbrModelUniform};
+
+fn transform_normal(uniform: &PbrModelUniform, normal: glam::Vec3) -> glam::Vec3 {
+    uniform.normal_matrix.transform_vector3(normal).normalize()
+}
+
+#[test]
+fn normal_matrix_follows_the_inverse_transpose() {
+    let model = glam::Mat4::from_scale_rotation_translation(
+        glam::vec3(2.0, 1.0, 0.5),
+        glam::Quat::from_rotation_y(0.7),
+        glam::vec3(1.0, 2.0, 3.0),
+    );
+    let uniform = PbrModelUniform::new(model);
+
+    let linear = glam::Mat3::from_mat4(model);
+    let expected = linear.inverse().transpose() * linear.determinant().abs();
+    assert!(glam::Mat3::from_mat4(uniform.normal_matrix).abs_diff_eq(expected, 1e-5));
+}
+
+#[test]
+fn small_scales_keep_their_rotation() {
+    let rotation = glam::Quat::from_euler(glam::EulerRot::YXZ, 0.7, -0.4, 0.2);
+    let model = glam::Mat4::from_scale_rotation_translation(
+        glam::Vec3::splat(0.004),
+        rotation,
+        glam::vec3(1.0, 2.0, 3.0),
+    );
+    let uniform = PbrModelUniform::new(model);
+
+    for normal in [glam::Vec3::X, glam::Vec3::Y, glam::vec3(0.6, 0.0, -0.8)] {
+        let transformed = transform_normal(&uniform, normal);
+        assert!(
+            transformed.abs_diff_eq(rotation * normal, 1e-4),
+            "{} became {} instead of {}",
+            normal,
+            transformed,
+            rotation * normal
+        );
+    }
+}
+
+#[test]
+fn mirrored_model_matrix_keeps_normals_outward() {
+    let uniform = PbrModelUniform::new(glam::Mat4::from_scale(glam::vec3(-1.0, 1.0, 1.0)));
+
+    assert_eq!(transform_normal(&uniform, glam::Vec3::X), glam::Vec3::NEG_X);
+    assert_eq!(transform_normal(&uniform, glam::Vec3::Y), glam::Vec3::Y);
+}
+
+#[test]
+fn singular_model_matrix_keeps_finite_normals() {
+    for scale in [
+        glam::vec3(0.0, 1.0, 1.0),
+        glam::vec3(1.0, 0.0, 1.0),
+        glam::Vec3::ZERO,
+    ] {
+        let uniform = PbrModelUniform::new(glam::Mat4::from_scale(scale));
+        assert!(
+            uniform.normal_matrix.is_finite(),
+            "NaN normal matrix for scale {}",
+            scale
+        );
+    }
+
+    // Flattened along x, every normal of the remaining plane points along x.
+    let uniform = PbrModelUniform::new(glam::Mat4::from_scale(glam::vec3(0.0, 1.0, 1.0)));
+    let normal = transform_normal(&uniform, glam::vec3(0.6, 0.8, 0.0));
+    assert!(normal.abs_diff_eq(glam::Vec3::X, 1e-6));
+}
+
+#[test]
+fn lights_pack_into_the_shader_layout() {
+    // `Light` in shader_pbr.wgsl: three vec3/scalar pairs and the two spot cosines, padded to 16.
+    assert_eq!(std::mem::size_of::<GpuLight>(), 64);
+
+    let mut spot = Light::spot(
+        glam::vec3(1.0, 2.0, 3.0),
+        glam::vec3(0.0, -2.0, 0.0),
+        0.25,
+        0.5,
+    );
+    spot.color = glam::vec3(1.0, 0.5, 0.25);
+    spot.intensity = 3.0;
+    spot.range = 10.0;
+
+    let gpu = spot.to_gpu();
+    assert_eq!(gpu.position, [1.0, 2.0, 3.0]);
+    assert_eq!(gpu.range, 10.0);
+    assert_eq!(gpu.direction, [0.0, -1.0, 0.0]);
+    assert_eq!(gpu.kind, 1);
+    assert_eq!(gpu.color, [1.0, 0.5, 0.25]);
+    assert_eq!(gpu.intensity, 3.0);
+    assert_eq!(gpu.cos_inner, 0.25f32.cos());
+    assert_eq!(gpu.cos_outer, 0.5f32.cos());
+
+    let point = Light::point(glam::Vec3::ONE).to_gpu();
+    assert_eq!(point.kind, 0);
+    assert_eq!((point.cos_inner, point.cos_outer), (-1.0, -1.0));
+
+    let directional = Light::directional(glam::Vec3::ZERO).to_gpu();
+    assert_eq!(directional.kind, 2);
+    assert_eq!(directional.direction, [0.0; 3]);
+}
+
+#[test]
+fn light_limit_follows_the_storage_binding_size() {
+    let limits = wgpu::Limits {
+        max_storage_buffer_binding_size: 16 + 64 * 100 + 63,
+        ..wgpu::Limits::default()
+    };
+    assert_eq!(LightSet::max_lights(&limits), 100);
+
+    let limits = wgpu::Limits {
+        max_buffer_size: 16 + 64 * 10,
+        ..wgpu::Limits::default()
+    };
+    assert_eq!(LightSet::max_lights(&limits), 10);
+
+    assert!(LightSet::max_lights(&wgpu::Limits::downlevel_defaults()) > 1000);
+}
+
+#[test]
+fn alpha_modes_pick_their_blend_and_depth_state() {
+    let mask = AlphaMode::Mask { cutoff: 0.5 };
+    for alpha_mode in [AlphaMode::Opaque, mask] {
+        assert_eq!(alpha_mode.blend_config().get(), None);
+        let depth = alpha_mode.depth_config().get().unwrap();
+        assert_eq!(depth.depth_write_enabled, Some(true));
+        assert_eq!(depth.depth_compare, Some(wgpu::CompareFunction::Less));
+    }
+
+    let blend = AlphaMode::Blend.blend_config().get().unwrap();
+    assert_eq!(blend.color.src_factor, wgpu::BlendFactor::SrcAlpha);
+    assert_eq!(blend.color.dst_factor, wgpu::BlendFactor::OneMinusSrcAlpha);
+    // Blended surfaces are depth tested but don't hide what is drawn after them.
+    let depth = AlphaMode::Blend.depth_config().get().unwrap();
+    assert_eq!(depth.depth_write_enabled, Some(false));
+    assert_eq!(depth.depth_compare, Some(wgpu::CompareFunction::Less));
+}
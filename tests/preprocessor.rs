@@ -0,0 +1,99 @@
+//! WGSL preprocessor tests, CPU only.
+
+use pira_wgpu::pipelines::preprocessor::{PreprocessErrorKind, ShaderPreprocessor, BUILTIN_CHUNKS};
+
+#[test]
+fn chunks_are_included_once_and_lines_map_back() {
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_chunk(
+        "common.wgsl",
+        "#include \"constants.wgsl\"\nconst TAU : f32 = PI * 2.0;",
+    );
+
+    let shader = preprocessor
+        .process(
+            "main.wgsl",
+            "#include \"constants.wgsl\"\n#include \"common.wgsl\"\nfn main() {}",
+        )
+        .unwrap();
+
+    assert_eq!(shader.source.matches("const PI").count(), 1);
+    let main_line = shader
+        .source
+        .lines()
+        .position(|line| line == "fn main() {}")
+        .unwrap();
+    assert_eq!(
+        shader.original_location(main_line as u32 + 1),
+        Some(("main.wgsl", 3))
+    );
+    let tau_line = shader
+        .source
+        .lines()
+        .position(|line| line.contains("TAU"))
+        .unwrap();
+    assert_eq!(
+        shader.original_location(tau_line as u32 + 1),
+        Some(("common.wgsl", 2))
+    );
+}
+
+#[test]
+fn conditionals_and_defines() {
+    let source = "\
+#define SIZE 4u
+#ifdef FAST
+const SAMPLES = SIZE;
+#else
+const SAMPLES = SIZE * 16u;
+#ifndef FAST
+const SLOW = true;
+#endif
+#endif
+";
+
+    let slow = ShaderPreprocessor::new()
+        .process("main.wgsl", source)
+        .unwrap();
+    assert_eq!(
+        slow.source,
+        "const SAMPLES = 4u * 16u;\nconst SLOW = true;\n"
+    );
+
+    let fast = ShaderPreprocessor::new()
+        .define("FAST", "")
+        .process("main.wgsl", source)
+        .unwrap();
+    assert_eq!(fast.source, "const SAMPLES = 4u;\n");
+}
+
+#[test]
+fn errors_point_at_the_original_file_and_line() {
+    let mut preprocessor = ShaderPreprocessor::new();
+    preprocessor.add_chunk("broken.wgsl", "// ok\n#include \"missing.wgsl\"");
+
+    let err = preprocessor
+        .process("main.wgsl", "\n#include \"broken.wgsl\"")
+        .unwrap_err();
+    assert_eq!((err.file.as_str(), err.line), ("broken.wgsl", 2));
+    assert!(
+        matches!(err.kind, PreprocessErrorKind::UnknownInclude(ref name) if name == "missing.wgsl")
+    );
+
+    let err = preprocessor
+        .process("main.wgsl", "#ifdef A\n\n#else")
+        .unwrap_err();
+    assert_eq!(err.line, 1);
+    assert!(matches!(err.kind, PreprocessErrorKind::UnterminatedIf));
+}
+
+#[test]
+fn builtin_chunks_parse() {
+    for (name, _) in BUILTIN_CHUNKS {
+        let source = format!("#include \"{}\"", name);
+        let shader = ShaderPreprocessor::new()
+            .process("main.wgsl", &source)
+            .unwrap();
+        assert!(!shader.source.contains('#'), "{} left a directive", name);
+    }
+}
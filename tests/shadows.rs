@@ -0,0 +1,112 @@
+//! Shadow cascade tests, CPU only.
+
+use pira_wgpu::helpers::cameras::{CameraTrait, PespectiveCamera};
+use pira_wgpu::pipelines::pbr::{Cascade, ShadowMaps, ShadowSettings, MAX_CASCADES};
+
+const RESOLUTION: u32 = 1024;
+
+fn camera(position: glam::Vec3, target: glam::Vec3) -> PespectiveCamera {
+    let mut camera = PespectiveCamera::new(std::f32::consts::FRAC_PI_3, 16.0 / 9.0, 0.1, 100.0);
+    camera.position = position;
+    camera.look_at(target);
+    camera
+}
+
+fn cascades(camera: &PespectiveCamera, direction: glam::Vec3) -> Vec<Cascade> {
+    ShadowMaps::fit_cascades(
+        camera,
+        camera.get_view_matrix(),
+        direction,
+        &ShadowSettings::default(),
+        4,
+        RESOLUTION,
+    )
+}
+
+#[test]
+fn cascade_splits_blend_uniform_and_logarithmic() {
+    let uniform = ShadowMaps::cascade_splits(1.0, 81.0, 4, 0.0);
+    assert_eq!(uniform, [21.0, 41.0, 61.0, 81.0]);
+
+    let logarithmic = ShadowMaps::cascade_splits(1.0, 81.0, 4, 1.0);
+    for (split, expected) in logarithmic.iter().zip([3.0, 9.0, 27.0, 81.0]) {
+        assert!((split - expected).abs() < 1e-3, "{:?}", logarithmic);
+    }
+
+    let mixed = ShadowMaps::cascade_splits(1.0, 81.0, 4, 0.5);
+    for index in 0..MAX_CASCADES {
+        let expected = (uniform[index] + logarithmic[index]) / 2.0;
+        assert!((mixed[index] - expected).abs() < 1e-3, "{:?}", mixed);
+    }
+
+    // Unused cascades end at the far plane.
+    assert_eq!(
+        ShadowMaps::cascade_splits(1.0, 81.0, 2, 0.0),
+        [41.0, 81.0, 81.0, 81.0]
+    );
+}
+
+#[test]
+fn cascades_cover_their_part_of_the_view() {
+    let camera = camera(glam::vec3(3.0, 4.0, -10.0), glam::vec3(0.0, 1.0, 5.0));
+    let cascades = cascades(&camera, glam::vec3(0.3, -1.0, 0.2));
+    let forward = camera.rotation * glam::Vec3::Z;
+
+    assert_eq!(cascades.len(), 4);
+    assert_eq!(cascades[3].split, ShadowSettings::default().max_distance);
+
+    let mut start = camera.near;
+    for cascade in &cascades {
+        assert!(cascade.split > start);
+        for step in 0..=10 {
+            let distance = start + (cascade.split - start) * step as f32 / 10.0;
+            let point = camera.position + forward * distance;
+            let clip = cascade.view_proj.project_point3(point);
+            assert!(
+                clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0 && (0.0..=1.0).contains(&clip.z),
+                "{} at {} is outside its cascade: {}",
+                point,
+                distance,
+                clip
+            );
+        }
+        start = cascade.split;
+    }
+}
+
+#[test]
+fn cascades_keep_their_size_while_the_camera_turns() {
+    let direction = glam::vec3(0.3, -1.0, 0.2);
+    let position = glam::vec3(3.0, 4.0, -10.0);
+    let reference = cascades(&camera(position, glam::Vec3::ZERO), direction);
+
+    for angle in [0.3f32, 1.2, 2.5, 4.0] {
+        let target = position + glam::vec3(angle.sin(), -0.2, angle.cos());
+        let turned = cascades(&camera(position, target), direction);
+        for (a, b) in reference.iter().zip(&turned) {
+            assert_eq!(a.texel_size, b.texel_size);
+        }
+    }
+}
+
+#[test]
+fn cascades_move_in_whole_texels() {
+    let direction = glam::vec3(0.3, -1.0, 0.2);
+
+    for step in 0..20 {
+        let offset = glam::vec3(0.013, 0.002, 0.021) * step as f32;
+        let position = glam::vec3(3.0, 4.0, -10.0) + offset;
+        let camera = camera(position, position + glam::vec3(-0.3, -0.3, 1.0));
+
+        for cascade in cascades(&camera, direction) {
+            // The world origin lands on the texel grid of every cascade, wherever the camera is.
+            let origin = cascade.view_proj.project_point3(glam::Vec3::ZERO);
+            let texels = origin.truncate() * RESOLUTION as f32 / 2.0;
+            assert!(
+                (texels - texels.round()).abs().max_element() < 0.01,
+                "the origin is {} texels into the cascade",
+                texels
+            );
+        }
+    }
+}